@@ -1,5 +1,5 @@
 use lazy_static::lazy_static;
-use tenderdash_abci::{proto::abci, Application, CancellationToken, ServerBuilder};
+use tenderdash_abci::{proto::abci, Application, CancellationToken, ServePolicy, ServerBuilder};
 use tracing::info;
 use tracing_subscriber::filter::LevelFilter;
 
@@ -23,11 +23,8 @@ pub fn main() {
         .build()
         .expect("server failed");
 
-    loop {
-        match server.next_client() {
-            Ok(_) => {},
-            Err(e) => tracing::error!("error {}", e),
-        };
+    if let Err(e) = server.serve(ServePolicy::new()) {
+        tracing::error!("error {}", e);
     }
 }
 
@@ -3,9 +3,12 @@
 use tenderdash_proto::abci::{ExecTxResult, ValidatorSetUpdate};
 use tracing::{debug, error};
 
-use crate::proto::{
-    abci,
-    abci::{request, response},
+use crate::{
+    proto::{
+        abci,
+        abci::{request, response},
+    },
+    RequestContext,
 };
 
 /// An ABCI application.
@@ -137,6 +140,145 @@ pub trait Application {
     ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
         Ok(Default::default())
     }
+
+    /// Context-aware version of [`Application::echo()`].
+    ///
+    /// Each `*_with_context()` method receives [RequestContext] of the request,
+    /// and by default calls the method of the same name without the
+    /// `_with_context` suffix. Override either of them.
+    fn echo_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        self.echo(request)
+    }
+
+    /// Context-aware version of [`Application::flush()`].
+    fn flush_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestFlush,
+    ) -> Result<abci::ResponseFlush, abci::ResponseException> {
+        self.flush(request)
+    }
+
+    /// Context-aware version of [`Application::info()`].
+    fn info_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        self.info(request)
+    }
+
+    /// Context-aware version of [`Application::init_chain()`].
+    fn init_chain_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestInitChain,
+    ) -> Result<abci::ResponseInitChain, abci::ResponseException> {
+        self.init_chain(request)
+    }
+
+    /// Context-aware version of [`Application::query()`].
+    fn query_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        self.query(request)
+    }
+
+    /// Context-aware version of [`Application::check_tx()`].
+    fn check_tx_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestCheckTx,
+    ) -> Result<abci::ResponseCheckTx, abci::ResponseException> {
+        self.check_tx(request)
+    }
+
+    /// Context-aware version of [`Application::list_snapshots()`].
+    fn list_snapshots_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestListSnapshots,
+    ) -> Result<abci::ResponseListSnapshots, abci::ResponseException> {
+        self.list_snapshots(request)
+    }
+
+    /// Context-aware version of [`Application::offer_snapshot()`].
+    fn offer_snapshot_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestOfferSnapshot,
+    ) -> Result<abci::ResponseOfferSnapshot, abci::ResponseException> {
+        self.offer_snapshot(request)
+    }
+
+    /// Context-aware version of [`Application::load_snapshot_chunk()`].
+    fn load_snapshot_chunk_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestLoadSnapshotChunk,
+    ) -> Result<abci::ResponseLoadSnapshotChunk, abci::ResponseException> {
+        self.load_snapshot_chunk(request)
+    }
+
+    /// Context-aware version of [`Application::apply_snapshot_chunk()`].
+    fn apply_snapshot_chunk_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestApplySnapshotChunk,
+    ) -> Result<abci::ResponseApplySnapshotChunk, abci::ResponseException> {
+        self.apply_snapshot_chunk(request)
+    }
+
+    /// Context-aware version of [`Application::extend_vote()`].
+    fn extend_vote_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestExtendVote,
+    ) -> Result<abci::ResponseExtendVote, abci::ResponseException> {
+        self.extend_vote(request)
+    }
+
+    /// Context-aware version of [`Application::finalize_block()`].
+    fn finalize_block_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        self.finalize_block(request)
+    }
+
+    /// Context-aware version of [`Application::prepare_proposal()`].
+    fn prepare_proposal_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestPrepareProposal,
+    ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+        self.prepare_proposal(request)
+    }
+
+    /// Context-aware version of [`Application::process_proposal()`].
+    fn process_proposal_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        self.process_proposal(request)
+    }
+
+    /// Context-aware version of [`Application::verify_vote_extension()`].
+    fn verify_vote_extension_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        self.verify_vote_extension(request)
+    }
 }
 
 pub trait RequestDispatcher {
@@ -151,48 +293,109 @@ pub trait RequestDispatcher {
 // Implement `RequestDispatcher` for all `Application`s.
 impl<A: Application> RequestDispatcher for A {
     fn handle(&self, request: abci::Request) -> Option<abci::Response> {
-        #[cfg(feature = "tracing-span")]
-        let _span = super::tracing_span::span(request.clone().value?);
-        tracing::trace!(?request, "received ABCI request");
+        let context = RequestContext::current_or_detached(request.value.as_ref());
 
         let response: response::Value = match request.value? {
-            request::Value::Echo(req) => self.echo(req).map(|v| v.into()),
-            request::Value::Flush(req) => self.flush(req).map(|v| v.into()),
-            request::Value::Info(req) => self.info(req).map(|v| v.into()),
-            request::Value::InitChain(req) => self.init_chain(req).map(|v| v.into()),
-            request::Value::Query(req) => self.query(req).map(|v| v.into()),
-            request::Value::CheckTx(req) => self.check_tx(req).map(|v| v.into()),
-            request::Value::OfferSnapshot(req) => self.offer_snapshot(req).map(|v| v.into()),
-            request::Value::LoadSnapshotChunk(req) => {
-                self.load_snapshot_chunk(req).map(|v| v.into())
-            },
-            request::Value::ApplySnapshotChunk(req) => {
-                self.apply_snapshot_chunk(req).map(|v| v.into())
-            },
-            request::Value::ListSnapshots(req) => self.list_snapshots(req).map(|v| v.into()),
-            request::Value::PrepareProposal(req) => self.prepare_proposal(req).map(|v| v.into()),
-            request::Value::ProcessProposal(req) => self.process_proposal(req).map(|v| v.into()),
-            request::Value::FinalizeBlock(req) => self.finalize_block(req).map(|v| v.into()),
-            request::Value::ExtendVote(req) => self.extend_vote(req).map(|v| v.into()),
-            request::Value::VerifyVoteExtension(req) => {
-                self.verify_vote_extension(req).map(|v| v.into())
+            request::Value::Echo(req) => self.echo_with_context(&context, req).map(|v| v.into()),
+            request::Value::Flush(req) => self.flush_with_context(&context, req).map(|v| v.into()),
+            request::Value::Info(req) => self.info_with_context(&context, req).map(|v| v.into()),
+            request::Value::InitChain(req) => self
+                .init_chain_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::Query(req) => self.query_with_context(&context, req).map(|v| v.into()),
+            request::Value::CheckTx(req) => {
+                self.check_tx_with_context(&context, req).map(|v| v.into())
             },
+            request::Value::OfferSnapshot(req) => self
+                .offer_snapshot_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::LoadSnapshotChunk(req) => self
+                .load_snapshot_chunk_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::ApplySnapshotChunk(req) => self
+                .apply_snapshot_chunk_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::ListSnapshots(req) => self
+                .list_snapshots_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::PrepareProposal(req) => self
+                .prepare_proposal_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::ProcessProposal(req) => self
+                .process_proposal_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::FinalizeBlock(req) => self
+                .finalize_block_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::ExtendVote(req) => self
+                .extend_vote_with_context(&context, req)
+                .map(|v| v.into()),
+            request::Value::VerifyVoteExtension(req) => self
+                .verify_vote_extension_with_context(&context, req)
+                .map(|v| v.into()),
         }
         .unwrap_or_else(|e| e.into());
 
-        if let response::Value::Exception(_) = response {
-            tracing::error!(?response, "sending ABCI exception");
-        } else {
-            let response_log = serialize_response_for_logging(&response);
-            tracing::trace!(?response_log, "sending ABCI response");
-        };
-
         Some(abci::Response {
             value: Some(response),
         })
     }
 }
 
+/// Names of ABCI methods, as returned by [method_name()].
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub(crate) const METHOD_NAMES: [&str; 15] = [
+    "ApplySnapshotChunk",
+    "CheckTx",
+    "Echo",
+    "ExtendVote",
+    "FinalizeBlock",
+    "Flush",
+    "Info",
+    "InitChain",
+    "ListSnapshots",
+    "LoadSnapshotChunk",
+    "OfferSnapshot",
+    "PrepareProposal",
+    "ProcessProposal",
+    "Query",
+    "VerifyVoteExtension",
+];
+
+/// Name of ABCI method that processes the request.
+pub(crate) fn method_name(request: &request::Value) -> &'static str {
+    match request {
+        request::Value::ApplySnapshotChunk(_) => "ApplySnapshotChunk",
+        request::Value::CheckTx(_) => "CheckTx",
+        request::Value::Echo(_) => "Echo",
+        request::Value::ExtendVote(_) => "ExtendVote",
+        request::Value::FinalizeBlock(_) => "FinalizeBlock",
+        request::Value::Flush(_) => "Flush",
+        request::Value::Info(_) => "Info",
+        request::Value::InitChain(_) => "InitChain",
+        request::Value::ListSnapshots(_) => "ListSnapshots",
+        request::Value::LoadSnapshotChunk(_) => "LoadSnapshotChunk",
+        request::Value::OfferSnapshot(_) => "OfferSnapshot",
+        request::Value::PrepareProposal(_) => "PrepareProposal",
+        request::Value::ProcessProposal(_) => "ProcessProposal",
+        request::Value::Query(_) => "Query",
+        request::Value::VerifyVoteExtension(_) => "VerifyVoteExtension",
+    }
+}
+
+/// Log response that is about to be sent to Tenderdash, on trace level.
+///
+/// Exceptions are logged as errors by
+/// [ExceptionLogLayer](crate::layer::ExceptionLogLayer).
+pub(crate) fn log_response(response: &response::Value) {
+    if let response::Value::Exception(_) = response {
+        tracing::trace!(?response, "sending ABCI exception");
+    } else {
+        let response_log = serialize_response_for_logging(response);
+        tracing::trace!(?response_log, "sending ABCI response");
+    };
+}
+
 /// Serialize message for logging.
 ///
 /// This macro is used to serialize the message for logging.
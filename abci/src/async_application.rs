@@ -0,0 +1,374 @@
+//! Asynchronous ABCI application interface.
+//!
+//! [AsyncApplication] is an async counterpart of
+//! [Application](crate::Application). Its handlers are executed directly on the
+//! tokio runtime used by the server, so applications can `.await` storage or
+//! RPC calls without `block_on()`.
+
+use async_trait::async_trait;
+
+use crate::{
+    check_version,
+    proto::{
+        abci,
+        abci::{request, response},
+    },
+    RequestContext,
+};
+
+/// An asynchronous ABCI application.
+///
+/// Default method implementations are the same as in
+/// [Application](crate::Application).
+#[async_trait]
+pub trait AsyncApplication: Send + Sync {
+    /// Echo back the same message as provided in the request.
+    async fn echo(
+        &self,
+        request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        Ok(abci::ResponseEcho {
+            message: request.message,
+        })
+    }
+
+    /// Signals that messages queued on the client should be flushed to the
+    /// server.
+    async fn flush(
+        &self,
+        _request: abci::RequestFlush,
+    ) -> Result<abci::ResponseFlush, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Provide information about the ABCI application.
+    async fn info(
+        &self,
+        request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        if !check_version(&request.abci_version) {
+            return Err(abci::ResponseException {
+                error: format!(
+                    "version mismatch: tenderdash {} vs our {}",
+                    request.version,
+                    crate::proto::ABCI_VERSION
+                ),
+            });
+        }
+
+        Ok(Default::default())
+    }
+
+    /// Called once upon genesis.
+    async fn init_chain(
+        &self,
+        _request: abci::RequestInitChain,
+    ) -> Result<abci::ResponseInitChain, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Query the application for data at the current or past height.
+    async fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Check the given transaction before putting it into the local mempool.
+    async fn check_tx(
+        &self,
+        _request: abci::RequestCheckTx,
+    ) -> Result<abci::ResponseCheckTx, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Used during state sync to discover available snapshots on peers.
+    async fn list_snapshots(
+        &self,
+        _request: abci::RequestListSnapshots,
+    ) -> Result<abci::ResponseListSnapshots, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Called when bootstrapping the node using state sync.
+    async fn offer_snapshot(
+        &self,
+        _request: abci::RequestOfferSnapshot,
+    ) -> Result<abci::ResponseOfferSnapshot, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Used during state sync to retrieve chunks of snapshots from peers.
+    async fn load_snapshot_chunk(
+        &self,
+        _request: abci::RequestLoadSnapshotChunk,
+    ) -> Result<abci::ResponseLoadSnapshotChunk, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Apply the given snapshot chunk to the application's state.
+    async fn apply_snapshot_chunk(
+        &self,
+        _request: abci::RequestApplySnapshotChunk,
+    ) -> Result<abci::ResponseApplySnapshotChunk, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    async fn extend_vote(
+        &self,
+        _request: abci::RequestExtendVote,
+    ) -> Result<abci::ResponseExtendVote, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    async fn finalize_block(
+        &self,
+        _request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    async fn prepare_proposal(
+        &self,
+        _request: abci::RequestPrepareProposal,
+    ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    async fn process_proposal(
+        &self,
+        _request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    async fn verify_vote_extension(
+        &self,
+        _request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        Ok(Default::default())
+    }
+
+    /// Context-aware version of [`AsyncApplication::echo()`].
+    ///
+    /// Each `*_with_context()` method receives [RequestContext] of the request,
+    /// and by default calls the method of the same name without the
+    /// `_with_context` suffix. Override either of them.
+    async fn echo_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        self.echo(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::flush()`].
+    async fn flush_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestFlush,
+    ) -> Result<abci::ResponseFlush, abci::ResponseException> {
+        self.flush(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::info()`].
+    async fn info_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        self.info(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::init_chain()`].
+    async fn init_chain_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestInitChain,
+    ) -> Result<abci::ResponseInitChain, abci::ResponseException> {
+        self.init_chain(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::query()`].
+    async fn query_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        self.query(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::check_tx()`].
+    async fn check_tx_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestCheckTx,
+    ) -> Result<abci::ResponseCheckTx, abci::ResponseException> {
+        self.check_tx(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::list_snapshots()`].
+    async fn list_snapshots_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestListSnapshots,
+    ) -> Result<abci::ResponseListSnapshots, abci::ResponseException> {
+        self.list_snapshots(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::offer_snapshot()`].
+    async fn offer_snapshot_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestOfferSnapshot,
+    ) -> Result<abci::ResponseOfferSnapshot, abci::ResponseException> {
+        self.offer_snapshot(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::load_snapshot_chunk()`].
+    async fn load_snapshot_chunk_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestLoadSnapshotChunk,
+    ) -> Result<abci::ResponseLoadSnapshotChunk, abci::ResponseException> {
+        self.load_snapshot_chunk(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::apply_snapshot_chunk()`].
+    async fn apply_snapshot_chunk_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestApplySnapshotChunk,
+    ) -> Result<abci::ResponseApplySnapshotChunk, abci::ResponseException> {
+        self.apply_snapshot_chunk(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::extend_vote()`].
+    async fn extend_vote_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestExtendVote,
+    ) -> Result<abci::ResponseExtendVote, abci::ResponseException> {
+        self.extend_vote(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::finalize_block()`].
+    async fn finalize_block_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        self.finalize_block(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::prepare_proposal()`].
+    async fn prepare_proposal_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestPrepareProposal,
+    ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+        self.prepare_proposal(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::process_proposal()`].
+    async fn process_proposal_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        self.process_proposal(request).await
+    }
+
+    /// Context-aware version of [`AsyncApplication::verify_vote_extension()`].
+    async fn verify_vote_extension_with_context(
+        &self,
+        _context: &RequestContext,
+        request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        self.verify_vote_extension(request).await
+    }
+}
+
+/// Asynchronous version of [RequestDispatcher](crate::RequestDispatcher).
+#[async_trait]
+pub trait AsyncRequestDispatcher: Send + Sync {
+    /// Executes the relevant application method based on the type of the
+    /// request, and produces the corresponding response.
+    ///
+    /// `AsyncRequestDispatcher` can indicate that it will no longer process
+    /// new requests by returning `None` variant.
+    async fn handle(&self, request: abci::Request) -> Option<abci::Response>;
+}
+
+// Implement `AsyncRequestDispatcher` for all `AsyncApplication`s.
+#[async_trait]
+impl<A: AsyncApplication> AsyncRequestDispatcher for A {
+    async fn handle(&self, request: abci::Request) -> Option<abci::Response> {
+        let context = RequestContext::current_or_detached(request.value.as_ref());
+        dispatch(self, &context, request).await
+    }
+}
+
+/// Call method of `app` that processes `request` with `context`.
+async fn dispatch<A: AsyncApplication>(
+    app: &A,
+    context: &RequestContext,
+    request: abci::Request,
+) -> Option<abci::Response> {
+    let response: response::Value = match request.value? {
+        request::Value::Echo(req) => app.echo_with_context(context, req).await.map(|v| v.into()),
+        request::Value::Flush(req) => app.flush_with_context(context, req).await.map(|v| v.into()),
+        request::Value::Info(req) => app.info_with_context(context, req).await.map(|v| v.into()),
+        request::Value::InitChain(req) => app
+            .init_chain_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::Query(req) => app.query_with_context(context, req).await.map(|v| v.into()),
+        request::Value::CheckTx(req) => app
+            .check_tx_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::OfferSnapshot(req) => app
+            .offer_snapshot_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::LoadSnapshotChunk(req) => app
+            .load_snapshot_chunk_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::ApplySnapshotChunk(req) => app
+            .apply_snapshot_chunk_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::ListSnapshots(req) => app
+            .list_snapshots_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::PrepareProposal(req) => app
+            .prepare_proposal_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::ProcessProposal(req) => app
+            .process_proposal_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::FinalizeBlock(req) => app
+            .finalize_block_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::ExtendVote(req) => app
+            .extend_vote_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+        request::Value::VerifyVoteExtension(req) => app
+            .verify_vote_extension_with_context(context, req)
+            .await
+            .map(|v| v.into()),
+    }
+    .unwrap_or_else(|e| e.into());
+
+    Some(abci::Response {
+        value: Some(response),
+    })
+}
@@ -0,0 +1,378 @@
+//! ABCI socket client.
+//!
+//! Connects to an ABCI application the same way Tenderdash does, which is
+//! useful in integration tests, health probes and tooling.
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Mutex, PoisonError},
+};
+
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use tenderdash_proto::prost::Message;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    runtime::Runtime,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use crate::{
+    proto::abci::{self, request, response, Request, Response},
+    server::codec::{decode_frame, encode_frame},
+    BindAddress, Error,
+};
+
+/// Bidirectional byte stream of the connection.
+trait Io: AsyncRead + AsyncWrite + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Send> Io for T {}
+
+type Stream = Pin<Box<dyn Io>>;
+
+/// Request queued for sending, together with channel that receives the
+/// response.
+type Call = (Request, oneshot::Sender<Result<Response, Error>>);
+
+/// Asynchronous ABCI socket client.
+///
+/// Requests are pipelined: they are written as soon as they are issued, and
+/// responses, which the server sends in the order of requests, are matched
+/// with them by position. The client can be cloned to issue requests
+/// concurrently; all clones share one connection, which is closed when the
+/// last clone is dropped.
+///
+/// [ResponseException] returned by the application is reported as
+/// [Error::Exception].
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example() -> Result<(), tenderdash_abci::Error> {
+/// use tenderdash_abci::{proto::abci, AbciClient};
+///
+/// let client = AbciClient::connect("unix:///tmp/abci.sock").await?;
+/// let info = client.info(abci::RequestInfo::default()).await?;
+/// println!("application height: {}", info.last_block_height);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [ResponseException]: abci::ResponseException
+#[derive(Clone, Debug)]
+pub struct AbciClient {
+    calls: mpsc::Sender<Call>,
+}
+
+impl AbciClient {
+    /// Connect to ABCI server at `address`, like `tcp://127.0.0.1:26658` or
+    /// `unix:///tmp/abci.sock`.
+    ///
+    /// Must be called inside tokio runtime, which is then used to process the
+    /// connection.
+    ///
+    /// Use [ClientBuilder] to configure the client.
+    pub async fn connect(address: &str) -> Result<Self, Error> {
+        ClientBuilder::new(address).connect().await
+    }
+
+    async fn open(address: &BindAddress) -> Result<Stream, Error> {
+        match address {
+            #[cfg(feature = "tcp")]
+            BindAddress::Tcp { .. } => {
+                let addrs = address.socket_addrs()?;
+                let stream = tokio::net::TcpStream::connect(addrs.as_slice()).await?;
+                stream.set_nodelay(true)?;
+                Ok(Box::pin(stream))
+            },
+            #[cfg(feature = "unix")]
+            BindAddress::Unix(path) => Ok(Box::pin(tokio::net::UnixStream::connect(path).await?)),
+            #[cfg(all(feature = "unix", any(target_os = "linux", target_os = "android")))]
+            BindAddress::AbstractUnix(name) => {
+                #[cfg(target_os = "android")]
+                use std::os::android::net::SocketAddrExt;
+                #[cfg(target_os = "linux")]
+                use std::os::linux::net::SocketAddrExt;
+
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+                stream.set_nonblocking(true)?;
+                Ok(Box::pin(tokio::net::UnixStream::from_std(stream)?))
+            },
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::Configuration(format!(
+                "address {} is not supported by the client",
+                address
+            ))),
+        }
+    }
+
+    /// Worker that writes requests and matches responses with them.
+    ///
+    /// Requests are written while responses are read, so that the client and
+    /// the server do not block each other when socket buffers are full.
+    /// Finishes when all clients are dropped or the connection fails; pending
+    /// calls fail then.
+    async fn worker(framed: Framed<Stream, ClientCoder>, mut calls: mpsc::Receiver<Call>) {
+        let (mut sink, mut stream) = framed.split();
+        let in_flight = Mutex::new(VecDeque::<oneshot::Sender<Result<Response, Error>>>::new());
+        let in_flight = || in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let write = async {
+            while let Some((request, response_tx)) = calls.recv().await {
+                // Registered before writing, so the response cannot arrive
+                // before its call.
+                in_flight().push_back(response_tx);
+                if let Err(error) = sink.send(request).await {
+                    if let Some(response_tx) = in_flight().pop_back() {
+                        response_tx.send(Err(error)).ok();
+                    }
+                    return;
+                }
+            }
+        };
+        let read = async {
+            loop {
+                let response = stream.next().await;
+                let response_tx = in_flight().pop_front();
+                match (response, response_tx) {
+                    (Some(Ok(response)), Some(response_tx)) => {
+                        response_tx.send(Ok(response)).ok();
+                    },
+                    (Some(Ok(response)), None) => {
+                        tracing::error!(?response, "received response without request");
+                        return;
+                    },
+                    (Some(Err(error)), response_tx) => {
+                        tracing::error!(?error, "cannot read response");
+                        if let Some(response_tx) = response_tx {
+                            response_tx.send(Err(error)).ok();
+                        }
+                        return;
+                    },
+                    (None, _) => {
+                        tracing::debug!("server closed connection");
+                        return;
+                    },
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = write => {},
+            _ = read => {},
+        }
+    }
+
+    /// Send request and wait for its response.
+    ///
+    /// Returns [Error::Exception] when the application responds with
+    /// exception.
+    pub async fn request(&self, value: request::Value) -> Result<response::Value, Error> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let request = Request { value: Some(value) };
+
+        self.calls
+            .send((request, response_tx))
+            .await
+            .map_err(|_| connection_closed())?;
+        let response = response_rx.await.map_err(|_| connection_closed())??;
+
+        match response.value {
+            Some(response::Value::Exception(exception)) => Err(Error::Exception(exception.error)),
+            Some(value) => Ok(value),
+            None => Err(Error::UnexpectedResponse("empty response".to_string())),
+        }
+    }
+}
+
+/// Error returned when the connection was closed before the response was
+/// received.
+fn connection_closed() -> Error {
+    Error::Connection(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "connection closed",
+    ))
+}
+
+/// Blocking ABCI socket client.
+///
+/// Works like [AbciClient], but runs the connection on its own
+/// single-threaded runtime and blocks until the response is received. Must
+/// not be used inside tokio runtime.
+#[derive(Debug)]
+pub struct BlockingAbciClient {
+    client: AbciClient,
+    runtime: Runtime,
+}
+
+impl BlockingAbciClient {
+    /// Connect to ABCI server at `address`; see [AbciClient::connect()].
+    pub fn connect(address: &str) -> Result<Self, Error> {
+        ClientBuilder::new(address).connect_blocking()
+    }
+
+    /// Send request and wait for its response; see [AbciClient::request()].
+    pub fn request(&self, value: request::Value) -> Result<response::Value, Error> {
+        self.runtime.block_on(self.client.request(value))
+    }
+}
+
+/// Builder of [AbciClient] and [BlockingAbciClient] with custom settings.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn example() -> Result<(), tenderdash_abci::Error> {
+/// use tenderdash_abci::ClientBuilder;
+///
+/// let client = ClientBuilder::new("tcp://127.0.0.1:26658")
+///     .with_max_response_size(4 * 1024 * 1024)
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    address: String,
+    max_response_size: Option<usize>,
+}
+
+impl ClientBuilder {
+    /// Create builder of client that connects to ABCI server at `address`;
+    /// see [AbciClient::connect()].
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            max_response_size: None,
+        }
+    }
+
+    /// Set maximum size of encoded response, in bytes.
+    ///
+    /// When the server sends a larger response, the request fails with
+    /// [Error::FrameTooLarge] and the connection is closed, without buffering
+    /// the response. By default, responses are not limited.
+    pub fn with_max_response_size(mut self, max_size: usize) -> Self {
+        self.max_response_size = Some(max_size);
+        self
+    }
+
+    /// Connect to the server; see [AbciClient::connect()].
+    pub async fn connect(self) -> Result<AbciClient, Error> {
+        let address: BindAddress = self.address.parse()?;
+        let stream = AbciClient::open(&address).await?;
+        let coder = ClientCoder {
+            max_response_size: self.max_response_size,
+        };
+
+        let (calls_tx, calls_rx) = mpsc::channel(16);
+        tokio::spawn(AbciClient::worker(Framed::new(stream, coder), calls_rx));
+
+        Ok(AbciClient { calls: calls_tx })
+    }
+
+    /// Connect to the server with blocking client; see
+    /// [BlockingAbciClient::connect()].
+    pub fn connect_blocking(self) -> Result<BlockingAbciClient, Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let client = runtime.block_on(self.connect())?;
+
+        Ok(BlockingAbciClient { client, runtime })
+    }
+}
+
+/// Define typed methods of [AbciClient] and [BlockingAbciClient].
+macro_rules! typed_methods {
+    ($($(#[$doc:meta])* $method:ident($variant:ident, $request:ident) -> $response:ident;)*) => {
+        impl AbciClient {
+            $(
+                $(#[$doc])*
+                pub async fn $method(
+                    &self,
+                    request: abci::$request,
+                ) -> Result<abci::$response, Error> {
+                    match self.request(request::Value::$variant(request)).await? {
+                        response::Value::$variant(response) => Ok(response),
+                        other => Err(Error::UnexpectedResponse(format!("{:?}", other))),
+                    }
+                }
+            )*
+        }
+
+        impl BlockingAbciClient {
+            $(
+                $(#[$doc])*
+                pub fn $method(&self, request: abci::$request) -> Result<abci::$response, Error> {
+                    self.runtime.block_on(self.client.$method(request))
+                }
+            )*
+        }
+    };
+}
+
+typed_methods! {
+    /// Send `Echo` request.
+    echo(Echo, RequestEcho) -> ResponseEcho;
+    /// Send `Flush` request.
+    flush(Flush, RequestFlush) -> ResponseFlush;
+    /// Send `Info` request.
+    info(Info, RequestInfo) -> ResponseInfo;
+    /// Send `InitChain` request.
+    init_chain(InitChain, RequestInitChain) -> ResponseInitChain;
+    /// Send `Query` request.
+    query(Query, RequestQuery) -> ResponseQuery;
+    /// Send `CheckTx` request.
+    check_tx(CheckTx, RequestCheckTx) -> ResponseCheckTx;
+    /// Send `ListSnapshots` request.
+    list_snapshots(ListSnapshots, RequestListSnapshots) -> ResponseListSnapshots;
+    /// Send `OfferSnapshot` request.
+    offer_snapshot(OfferSnapshot, RequestOfferSnapshot) -> ResponseOfferSnapshot;
+    /// Send `LoadSnapshotChunk` request.
+    load_snapshot_chunk(LoadSnapshotChunk, RequestLoadSnapshotChunk) -> ResponseLoadSnapshotChunk;
+    /// Send `ApplySnapshotChunk` request.
+    apply_snapshot_chunk(ApplySnapshotChunk, RequestApplySnapshotChunk) -> ResponseApplySnapshotChunk;
+    /// Send `PrepareProposal` request.
+    prepare_proposal(PrepareProposal, RequestPrepareProposal) -> ResponsePrepareProposal;
+    /// Send `ProcessProposal` request.
+    process_proposal(ProcessProposal, RequestProcessProposal) -> ResponseProcessProposal;
+    /// Send `ExtendVote` request.
+    extend_vote(ExtendVote, RequestExtendVote) -> ResponseExtendVote;
+    /// Send `VerifyVoteExtension` request.
+    verify_vote_extension(VerifyVoteExtension, RequestVerifyVoteExtension) -> ResponseVerifyVoteExtension;
+    /// Send `FinalizeBlock` request.
+    finalize_block(FinalizeBlock, RequestFinalizeBlock) -> ResponseFinalizeBlock;
+}
+
+/// Encoder of requests and decoder of responses, using the same
+/// length-delimited framing as the server.
+#[derive(Debug)]
+struct ClientCoder {
+    max_response_size: Option<usize>,
+}
+
+impl Encoder<Request> for ClientCoder {
+    type Error = Error;
+
+    fn encode(&mut self, request: Request, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        encode_frame(&request, None, dst)?;
+        Ok(())
+    }
+}
+
+impl Decoder for ClientCoder {
+    type Error = Error;
+    type Item = Response;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match decode_frame(src, self.max_response_size)? {
+            Some((_, frame)) => Ok(Some(Response::decode(frame)?)),
+            None => Ok(None),
+        }
+    }
+}
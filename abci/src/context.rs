@@ -0,0 +1,244 @@
+//! Context of the request being processed.
+#[cfg(not(feature = "tracing-span"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "server")]
+use std::sync::OnceLock;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::proto::abci::request;
+#[cfg(feature = "server")]
+use crate::CancellationToken;
+
+#[cfg(feature = "server")]
+tokio::task_local! {
+    /// Context of the request being dispatched.
+    static CURRENT: RequestContext;
+}
+
+/// Context of a request, passed to `*_with_context()` methods of
+/// [Application](crate::Application) and
+/// [AsyncApplication](crate::AsyncApplication).
+///
+/// The server creates a context for each received request, and makes it
+/// available to the dispatcher with [RequestContext::current()]. Requests
+/// passed to the dispatcher directly get a detached context, see
+/// [RequestContext::detached()].
+///
+/// Cloning the context does not allocate, so it is cheap to pass it around.
+///
+/// # Examples
+///
+/// ```
+/// use tenderdash_abci::{proto::abci, Application, RequestContext};
+///
+/// struct MyApp {}
+///
+/// impl Application for MyApp {
+///     fn query_with_context(
+///         &self,
+///         context: &RequestContext,
+///         request: abci::RequestQuery,
+///     ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+///         tracing::debug!(
+///             connection_id = context.connection_id(),
+///             request_id = context.request_id(),
+///             path = request.path,
+///             "processing query"
+///         );
+///         Ok(Default::default())
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    connection_id: Option<u64>,
+    request_id: Arc<str>,
+    received_at: Instant,
+    deadline: Option<Instant>,
+    span: tracing::Span,
+    /// Cancellation token; created on first use by detached contexts.
+    #[cfg(feature = "server")]
+    cancel: OnceLock<CancellationToken>,
+}
+
+impl RequestContext {
+    /// Create context of `request` received by the server on connection
+    /// `connection_id` at `received_at`.
+    #[cfg(feature = "server")]
+    pub(crate) fn new(
+        request: Option<&request::Value>,
+        connection_id: u64,
+        received_at: Instant,
+        cancel: CancellationToken,
+    ) -> Self {
+        let request_id = generate_request_id();
+        Self {
+            connection_id: Some(connection_id),
+            span: request_span(request, &request_id),
+            request_id: request_id.into(),
+            received_at,
+            deadline: None,
+            cancel: cancel.into(),
+        }
+    }
+
+    /// Create context of `request` that was not received by the server, like
+    /// in tests of the application.
+    ///
+    /// Detached context has no connection ID, and its cancellation token is
+    /// never cancelled.
+    pub fn detached(request: Option<&request::Value>) -> Self {
+        let request_id = generate_request_id();
+        Self {
+            connection_id: None,
+            span: request_span(request, &request_id),
+            request_id: request_id.into(),
+            received_at: Instant::now(),
+            deadline: None,
+            #[cfg(feature = "server")]
+            cancel: OnceLock::new(),
+        }
+    }
+
+    /// Set time when the hard deadline of the request passes.
+    #[cfg(feature = "server")]
+    pub(crate) fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Context of the request being dispatched by the server, or `None` when
+    /// called outside of the server.
+    ///
+    /// The context is available to the dispatcher during processing of the
+    /// request, on the thread or task the server called the dispatcher on.
+    pub fn current() -> Option<Self> {
+        #[cfg(feature = "server")]
+        return CURRENT.try_with(Clone::clone).ok();
+        #[cfg(not(feature = "server"))]
+        None
+    }
+
+    /// Context of the request being dispatched, or detached context of
+    /// `request`.
+    ///
+    /// Dispatchers also trace the request in span of the returned context, so
+    /// that a request dispatched outside of the server gets only one span.
+    pub(crate) fn current_or_detached(request: Option<&request::Value>) -> Self {
+        Self::current().unwrap_or_else(|| Self::detached(request))
+    }
+
+    /// Run `dispatch`, making this context current.
+    #[cfg(feature = "server")]
+    pub(crate) fn scope<R>(&self, dispatch: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self.clone(), dispatch)
+    }
+
+    /// Async version of [RequestContext::scope()].
+    #[cfg(feature = "server")]
+    pub(crate) async fn scope_async<F: std::future::Future>(&self, dispatch: F) -> F::Output {
+        CURRENT.scope(self.clone(), dispatch).await
+    }
+
+    /// Identifier of the connection that sent the request; `None` for
+    /// detached context.
+    pub fn connection_id(&self) -> Option<u64> {
+        self.connection_id
+    }
+
+    /// Unique identifier of the request.
+    ///
+    /// With `tracing-span` feature, it is the `request_id` field of
+    /// [span](RequestContext::span()), a random UUID; otherwise, a number
+    /// unique within the process.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Time when the request was received.
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Time elapsed since the request was received.
+    pub fn elapsed(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+
+    /// Time when the hard deadline of the request passes, if configured with
+    /// [`ServerBuilder::with_deadline()`](crate::ServerBuilder::with_deadline()).
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Tracing span of the request, with the ABCI method and request ID.
+    ///
+    /// The span is entered by [SpanLayer](crate::layer::SpanLayer); otherwise,
+    /// use [tracing::Span::in_scope()] or [tracing::Instrument] to process
+    /// the request inside it.
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Token cancelled when the server is cancelled, or when the hard deadline
+    /// of the request passes.
+    ///
+    /// It is a child of the server's [CancellationToken], so cancelling it
+    /// does not affect the server.
+    #[cfg(feature = "server")]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        self.cancel.get_or_init(CancellationToken::new)
+    }
+}
+
+/// Generate unique identifier of a request.
+#[cfg(feature = "tracing-span")]
+fn generate_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Generate unique identifier of a request.
+#[cfg(not(feature = "tracing-span"))]
+fn generate_request_id() -> String {
+    static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).to_string()
+}
+
+/// Create tracing span of `request` with `request_id`.
+fn request_span(request: Option<&request::Value>, request_id: &str) -> tracing::Span {
+    match request {
+        #[cfg(feature = "tracing-span")]
+        Some(value) => crate::tracing_span::request_span_with_id(value, request_id),
+        #[cfg(not(feature = "tracing-span"))]
+        Some(value) => tracing::error_span!(
+            "abci",
+            endpoint = crate::application::method_name(value),
+            request_id
+        ),
+        None => tracing::error_span!("abci", request_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestContext;
+    use crate::proto::abci::{request, RequestInfo};
+
+    #[test]
+    /// Given detached contexts, when they are created, then each of them has a
+    /// unique request ID and no connection ID.
+    fn test_detached() {
+        let request = request::Value::Info(RequestInfo::default());
+        let first = RequestContext::detached(Some(&request));
+        let second = RequestContext::detached(None);
+
+        assert_ne!(first.request_id(), second.request_id());
+        assert_eq!(first.clone().request_id(), first.request_id());
+        assert_eq!(first.connection_id(), None);
+        assert_eq!(first.deadline(), None);
+        assert!(RequestContext::current().is_none());
+    }
+}
@@ -0,0 +1,14 @@
+//! Helpers for execution of blocks by ABCI++ applications.
+//!
+//! [BlockExecutionTracker] follows height and round of the block being
+//! executed, and detects requests that violate the ABCI++ block lifecycle.
+//! With `crypto` feature, [ExecutionCache] stores results of blocks executed
+//! in `PrepareProposal` and `ProcessProposal`, so that `FinalizeBlock` does not
+//! execute them again.
+#[cfg(feature = "crypto")]
+mod cache;
+mod tracker;
+
+#[cfg(feature = "crypto")]
+pub use self::cache::{txs_hash, ExecutionCache, ExecutionResult, ProposalId, ProposalKey};
+pub use self::tracker::{BlockExecutionTracker, BlockStage, ProtocolViolation};
@@ -0,0 +1,359 @@
+//! Cache of results of optimistic block execution.
+use std::collections::BTreeMap;
+
+use crate::proto::abci::{self, tx_record::TxAction, ExecTxResult, TxRecord, ValidatorSetUpdate};
+
+/// Identity of a proposal within its height and round.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProposalId {
+    /// Hash of the block, known to validators that process the proposal.
+    BlockHash(Vec<u8>),
+    /// Hash of transactions of the block, as calculated by [txs_hash()]; used
+    /// by the proposer, as the block hash is not known when the proposal is
+    /// prepared.
+    TxsHash([u8; 32]),
+}
+
+/// Key of a proposal in [ExecutionCache].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProposalKey {
+    pub height: i64,
+    pub round: i32,
+    pub id: ProposalId,
+}
+
+impl ProposalKey {
+    /// Key of proposal prepared with `request`, containing transactions
+    /// included by `response`.
+    pub fn prepared(
+        request: &abci::RequestPrepareProposal,
+        response: &abci::ResponsePrepareProposal,
+    ) -> Self {
+        Self {
+            height: request.height,
+            round: request.round,
+            id: ProposalId::TxsHash(txs_hash(&included_txs(&response.tx_records))),
+        }
+    }
+
+    /// Key of proposal processed with `request`.
+    pub fn processed(request: &abci::RequestProcessProposal) -> Self {
+        Self {
+            height: request.height,
+            round: request.round,
+            id: ProposalId::BlockHash(request.hash.to_vec()),
+        }
+    }
+
+    /// Keys under which block finalized with `request` can be cached: its
+    /// hash, and hash of its transactions when the block is included.
+    fn finalized(request: &abci::RequestFinalizeBlock) -> impl Iterator<Item = Self> + '_ {
+        let by_hash = ProposalId::BlockHash(request.hash.to_vec());
+        let by_txs = request
+            .block
+            .as_ref()
+            .and_then(|block| block.data.as_ref())
+            .map(|data| ProposalId::TxsHash(txs_hash(&data.txs)));
+
+        std::iter::once(by_hash).chain(by_txs).map(|id| Self {
+            height: request.height,
+            round: request.round,
+            id,
+        })
+    }
+}
+
+/// Hash of the transaction list: SHA-256 of concatenated SHA-256 hashes of
+/// transactions.
+pub fn txs_hash<T: AsRef<[u8]>>(txs: &[T]) -> [u8; 32] {
+    let hashes = txs
+        .iter()
+        .flat_map(|tx| lhash::sha256(tx.as_ref()))
+        .collect::<Vec<u8>>();
+    lhash::sha256(&hashes)
+}
+
+/// Transactions that `tx_records` include in the block.
+pub(crate) fn included_txs(tx_records: &[TxRecord]) -> Vec<&[u8]> {
+    tx_records
+        .iter()
+        .filter(|record| {
+            matches!(
+                TxAction::try_from(record.action),
+                Ok(TxAction::Unmodified) | Ok(TxAction::Added)
+            )
+        })
+        .map(|record| record.tx.as_ref())
+        .collect()
+}
+
+/// Result of block execution, stored in [ExecutionCache].
+#[derive(Clone, Debug)]
+pub struct ExecutionResult<S> {
+    /// Handle of uncommitted state of the application after execution, to be
+    /// committed when the block is finalized.
+    pub state: S,
+    pub tx_results: Vec<ExecTxResult>,
+    pub app_hash: Vec<u8>,
+    pub validator_set_update: Option<ValidatorSetUpdate>,
+}
+
+/// Cache of results of blocks executed in `PrepareProposal` and
+/// `ProcessProposal`, to be reused in `FinalizeBlock`.
+///
+/// Results are stored under [ProposalKey]. When a result of a later round or
+/// height is inserted, results of earlier ones are discarded, as their
+/// proposals will not be finalized; results of the finalized height are
+/// discarded by [ExecutionCache::take_finalized()]. When the finalized block
+/// is not in the cache, the application must execute it again.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// use tenderdash_abci::{
+///     execution::{ExecutionCache, ExecutionResult, ProposalKey},
+///     proto::abci,
+///     Application,
+/// };
+///
+/// /// Uncommitted state of the application.
+/// #[derive(Clone, Default)]
+/// struct State {}
+///
+/// #[derive(Default)]
+/// struct MyApp {
+///     cache: Mutex<ExecutionCache<State>>,
+/// }
+///
+/// impl MyApp {
+///     fn execute<T: AsRef<[u8]>>(&self, txs: &[T]) -> ExecutionResult<State> {
+///         ExecutionResult {
+///             state: State::default(),
+///             tx_results: vec![Default::default(); txs.len()],
+///             app_hash: vec![0; 32],
+///             validator_set_update: None,
+///         }
+///     }
+/// }
+///
+/// impl Application for MyApp {
+///     fn process_proposal(
+///         &self,
+///         request: abci::RequestProcessProposal,
+///     ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+///         let result = self.execute(&request.txs);
+///         let response = abci::ResponseProcessProposal {
+///             status: abci::response_process_proposal::ProposalStatus::Accept.into(),
+///             app_hash: result.app_hash.clone().into(),
+///             tx_results: result.tx_results.clone(),
+///             validator_set_update: result.validator_set_update.clone(),
+///             ..Default::default()
+///         };
+///         let key = ProposalKey::processed(&request);
+///         self.cache.lock().unwrap().insert(key, result);
+///         Ok(response)
+///     }
+///
+///     fn finalize_block(
+///         &self,
+///         request: abci::RequestFinalizeBlock,
+///     ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+///         let cached = self.cache.lock().unwrap().take_finalized(&request);
+///         let _result = match cached {
+///             Some(result) => result,
+///             None => {
+///                 let txs = request.block.and_then(|b| b.data).unwrap_or_default().txs;
+///                 self.execute(&txs)
+///             },
+///         };
+///         // commit `_result.state`
+///         Ok(Default::default())
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExecutionCache<S> {
+    entries: BTreeMap<ProposalKey, ExecutionResult<S>>,
+}
+
+impl<S> Default for ExecutionCache<S> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+        }
+    }
+}
+
+impl<S> ExecutionCache<S> {
+    /// Create empty cache.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Store `result` of proposal identified by `key`, discarding results of
+    /// earlier rounds and heights.
+    ///
+    /// Result of a round earlier than the latest cached one is not stored.
+    pub fn insert(&mut self, key: ProposalKey, result: ExecutionResult<S>) {
+        if self
+            .latest()
+            .is_some_and(|latest| latest > (key.height, key.round))
+        {
+            tracing::debug!(
+                height = key.height,
+                round = key.round,
+                "not caching execution result of stale round"
+            );
+            return;
+        }
+
+        self.entries
+            .retain(|cached, _| (cached.height, cached.round) >= (key.height, key.round));
+        self.entries.insert(key, result);
+    }
+
+    /// Cached result of proposal identified by `key`.
+    pub fn get(&self, key: &ProposalKey) -> Option<&ExecutionResult<S>> {
+        self.entries.get(key)
+    }
+
+    /// Take result of the block finalized with `request`, found by block hash
+    /// or transactions hash, and discard results of its height.
+    pub fn take_finalized(
+        &mut self,
+        request: &abci::RequestFinalizeBlock,
+    ) -> Option<ExecutionResult<S>> {
+        let result = ProposalKey::finalized(request).find_map(|key| self.entries.remove(&key));
+        self.entries
+            .retain(|cached, _| cached.height > request.height);
+        result
+    }
+
+    /// Number of cached results.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Discard all cached results.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Latest height and round with cached results.
+    fn latest(&self) -> Option<(i64, i32)> {
+        self.entries
+            .keys()
+            .next_back()
+            .map(|key| (key.height, key.round))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExecutionCache, ExecutionResult, ProposalKey};
+    use crate::proto::{
+        abci::{self, tx_record::TxAction, TxRecord},
+        types,
+    };
+
+    /// Convert to type of a `bytes` proto field, which is `Bytes` when the
+    /// `zero-copy` feature is enabled.
+    fn bytes_field<T: From<Vec<u8>>>(value: Vec<u8>) -> T {
+        T::from(value)
+    }
+
+    fn result(state: u32) -> ExecutionResult<u32> {
+        ExecutionResult {
+            state,
+            tx_results: Default::default(),
+            app_hash: vec![state as u8],
+            validator_set_update: None,
+        }
+    }
+
+    fn processed(height: i64, round: i32, hash: &[u8]) -> ProposalKey {
+        ProposalKey::processed(&abci::RequestProcessProposal {
+            height,
+            round,
+            hash: hash.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn finalize(height: i64, round: i32, hash: &[u8], txs: &[&[u8]]) -> abci::RequestFinalizeBlock {
+        abci::RequestFinalizeBlock {
+            height,
+            round,
+            hash: hash.to_vec(),
+            block: Some(types::Block {
+                data: Some(types::Data {
+                    txs: txs.iter().map(|tx| tx.to_vec()).collect(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    /// Given results of processed proposals, when later rounds are cached and
+    /// the block is finalized, then stale rounds are discarded and the result
+    /// is reused.
+    fn test_processed() {
+        let mut cache = ExecutionCache::new();
+        cache.insert(processed(1, 0, b"a"), result(1));
+        cache.insert(processed(1, 1, b"b"), result(2));
+        cache.insert(processed(1, 1, b"c"), result(3));
+        assert!(cache.get(&processed(1, 0, b"a")).is_none());
+        assert_eq!(cache.len(), 2);
+
+        cache.insert(processed(1, 0, b"d"), result(4));
+        assert!(cache.get(&processed(1, 0, b"d")).is_none());
+
+        let finalized = cache.take_finalized(&finalize(1, 1, b"c", &[]));
+        assert_eq!(finalized.map(|result| result.state), Some(3));
+        assert!(cache.is_empty());
+        assert!(cache.take_finalized(&finalize(1, 1, b"c", &[])).is_none());
+    }
+
+    #[test]
+    /// Given result of prepared proposal, when the block is finalized, then
+    /// the result is found by transactions included in the block.
+    fn test_prepared() {
+        let request = abci::RequestPrepareProposal {
+            height: 2,
+            round: 0,
+            ..Default::default()
+        };
+        let response = abci::ResponsePrepareProposal {
+            tx_records: [
+                (TxAction::Unmodified, b"tx1"),
+                (TxAction::Removed, b"tx2"),
+                (TxAction::Added, b"tx3"),
+            ]
+            .into_iter()
+            .map(|(action, tx)| TxRecord {
+                action: action.into(),
+                tx: bytes_field(tx.to_vec()),
+            })
+            .collect(),
+            ..Default::default()
+        };
+        let mut cache = ExecutionCache::new();
+        cache.insert(ProposalKey::prepared(&request, &response), result(5));
+
+        assert!(cache
+            .take_finalized(&finalize(2, 0, b"hash", &[b"tx1", b"tx2", b"tx3"]))
+            .is_none());
+        cache.insert(ProposalKey::prepared(&request, &response), result(5));
+        let finalized = cache.take_finalized(&finalize(2, 0, b"hash", &[b"tx1", b"tx3"]));
+        assert_eq!(finalized.map(|result| result.state), Some(5));
+    }
+}
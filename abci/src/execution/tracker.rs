@@ -0,0 +1,675 @@
+//! State machine of the ABCI++ block lifecycle.
+use std::collections::{BTreeMap, BTreeSet};
+
+#[cfg(feature = "crypto")]
+use super::cache::{included_txs, txs_hash};
+#[cfg(feature = "crypto")]
+use crate::proto::abci::response;
+use crate::proto::abci::{self, request};
+
+/// Stage of the block lifecycle reached by [BlockExecutionTracker].
+///
+/// At each height, Tenderdash sends `PrepareProposal` (to the proposer) or
+/// `ProcessProposal` (to other validators) once per round, then `ExtendVote`
+/// for the block the validator precommits, `VerifyVoteExtension` for vote
+/// extensions of other validators, and finally `FinalizeBlock` for the
+/// committed block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlockStage {
+    /// No block request was tracked yet.
+    #[default]
+    Unknown,
+    /// `InitChain` was received; next block is at the initial height.
+    Initialized,
+    /// Proposal was prepared at current height and round.
+    Prepared,
+    /// Proposal was processed at current height and round.
+    Processed,
+    /// Vote was extended at current height and round.
+    VoteExtended,
+    /// Block at current height was finalized.
+    Finalized,
+}
+
+impl BlockStage {
+    /// Whether execution of a block is in progress.
+    fn in_progress(self) -> bool {
+        matches!(self, Self::Prepared | Self::Processed | Self::VoteExtended)
+    }
+}
+
+/// Request that violates the ABCI++ block lifecycle, detected by
+/// [BlockExecutionTracker].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ProtocolViolation {
+    #[error("InitChain received more than once")]
+    RepeatedInitChain,
+    #[error("InitChain received after block requests at height {height}")]
+    InitChainAfterBlock { height: i64 },
+    #[error("{method} at height {height} goes back from expected height {expected}")]
+    HeightRegression {
+        method: &'static str,
+        height: i64,
+        expected: i64,
+    },
+    #[error("{method} at height {height} skips expected height {expected}")]
+    HeightSkipped {
+        method: &'static str,
+        height: i64,
+        expected: i64,
+    },
+    #[error("{method} at height {height} round {round} after round {current} was reached")]
+    RoundRegression {
+        method: &'static str,
+        height: i64,
+        round: i32,
+        current: i32,
+    },
+    #[error("{method} at height {height} round {round} after vote was extended in this round")]
+    RepeatedRound {
+        method: &'static str,
+        height: i64,
+        round: i32,
+    },
+    #[error(
+        "{method} of block {} at height {height} round {round}, which was neither prepared nor processed",
+        hex::encode(.hash)
+    )]
+    UnknownBlock {
+        method: &'static str,
+        height: i64,
+        round: i32,
+        hash: Vec<u8>,
+    },
+}
+
+/// Tracker of height and round of the block being executed by the
+/// application.
+///
+/// The tracker follows `InitChain`, `PrepareProposal`, `ProcessProposal`,
+/// `ExtendVote`, `VerifyVoteExtension` and `FinalizeBlock` requests, and
+/// returns [ProtocolViolation] when a request does not fit the lifecycle, like
+/// finalizing a block that was never processed, going back to a previous
+/// height, or repeated `InitChain`. A rejected request does not change the
+/// state of the tracker.
+///
+/// A new tracker accepts any height of the first block request; use
+/// [BlockExecutionTracker::with_last_block_height()] when the application
+/// restarts with already committed blocks. `OfferSnapshot` resets the tracker,
+/// as the next block follows the restored snapshot.
+///
+/// Hash of a block prepared by the proposer is not known until the block is
+/// finalized, so `FinalizeBlock` of any block of a round in which a proposal
+/// was prepared is accepted. With `crypto` feature, pass responses to
+/// [BlockExecutionTracker::track_response()] to check that transactions of
+/// the finalized block are the ones of the prepared proposal.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// use tenderdash_abci::{execution::BlockExecutionTracker, proto::abci, Application};
+///
+/// #[derive(Default)]
+/// struct MyApp {
+///     tracker: Mutex<BlockExecutionTracker>,
+/// }
+///
+/// impl Application for MyApp {
+///     fn finalize_block(
+///         &self,
+///         request: abci::RequestFinalizeBlock,
+///     ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+///         self.tracker
+///             .lock()
+///             .unwrap()
+///             .finalize_block(&request)
+///             .map_err(|e| abci::ResponseException {
+///                 error: e.to_string(),
+///             })?;
+///         Ok(Default::default())
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BlockExecutionTracker {
+    init_chain_received: bool,
+    stage: BlockStage,
+    /// Height of the stage; for [BlockStage::Initialized], height before the
+    /// initial one.
+    height: i64,
+    /// Round of the stage.
+    round: i32,
+    /// Rounds of current height in which a proposal was prepared, with hash of
+    /// transactions of the proposal, if known.
+    prepared: BTreeMap<i32, Option<[u8; 32]>>,
+    /// Hashes of blocks processed at current height.
+    processed: BTreeSet<Vec<u8>>,
+}
+
+impl BlockExecutionTracker {
+    /// Create tracker that knows nothing about the chain.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start tracking after block at `height` was finalized.
+    pub fn with_last_block_height(mut self, height: i64) -> Self {
+        self.finish(BlockStage::Finalized, height, 0);
+        self
+    }
+
+    /// Stage of the lifecycle reached.
+    pub fn stage(&self) -> BlockStage {
+        self.stage
+    }
+
+    /// Height of the block in progress or last finalized one; `None` before
+    /// the first block.
+    pub fn height(&self) -> Option<i64> {
+        self.block_tracked().then_some(self.height)
+    }
+
+    /// Round of the block in progress or last finalized one; `None` before
+    /// the first block.
+    pub fn round(&self) -> Option<i32> {
+        self.block_tracked().then_some(self.round)
+    }
+
+    /// Height expected in next block requests; `None` if unknown.
+    pub fn next_height(&self) -> Option<i64> {
+        match self.stage {
+            BlockStage::Unknown => None,
+            BlockStage::Initialized | BlockStage::Finalized => Some(self.height + 1),
+            BlockStage::Prepared | BlockStage::Processed | BlockStage::VoteExtended => {
+                Some(self.height)
+            },
+        }
+    }
+
+    /// Track `request` of any type; requests not related to block execution
+    /// are accepted.
+    pub fn track(&mut self, request: &request::Value) -> Result<(), ProtocolViolation> {
+        match request {
+            request::Value::InitChain(request) => self.init_chain(request),
+            request::Value::PrepareProposal(request) => self.prepare_proposal(request),
+            request::Value::ProcessProposal(request) => self.process_proposal(request),
+            request::Value::ExtendVote(request) => self.extend_vote(request),
+            request::Value::VerifyVoteExtension(request) => self.verify_vote_extension(request),
+            request::Value::FinalizeBlock(request) => self.finalize_block(request),
+            request::Value::OfferSnapshot(_) => {
+                *self = Self {
+                    init_chain_received: self.init_chain_received,
+                    ..Default::default()
+                };
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Track response to `request`; only responses to `PrepareProposal` are
+    /// tracked.
+    #[cfg(feature = "crypto")]
+    pub fn track_response(&mut self, request: &request::Value, response: &response::Value) {
+        if let (
+            request::Value::PrepareProposal(request),
+            response::Value::PrepareProposal(response),
+        ) = (request, response)
+        {
+            self.prepare_proposal_response(request, response);
+        }
+    }
+
+    /// Track `InitChain` request.
+    pub fn init_chain(
+        &mut self,
+        request: &abci::RequestInitChain,
+    ) -> Result<(), ProtocolViolation> {
+        if self.init_chain_received {
+            return Err(ProtocolViolation::RepeatedInitChain);
+        }
+        if self.stage != BlockStage::Unknown {
+            return Err(ProtocolViolation::InitChainAfterBlock {
+                height: self.height,
+            });
+        }
+
+        let initial_height = request.initial_height.max(1);
+        self.init_chain_received = true;
+        self.finish(BlockStage::Initialized, initial_height - 1, 0);
+        Ok(())
+    }
+
+    /// Track `PrepareProposal` request.
+    pub fn prepare_proposal(
+        &mut self,
+        request: &abci::RequestPrepareProposal,
+    ) -> Result<(), ProtocolViolation> {
+        self.start_round("PrepareProposal", request.height, request.round)?;
+        self.prepared.insert(request.round, None);
+        self.stage = BlockStage::Prepared;
+        Ok(())
+    }
+
+    /// Track `response` to tracked `PrepareProposal` request, to check
+    /// transactions of the block when it is finalized.
+    ///
+    /// Response to a request that was not tracked at current height is
+    /// ignored.
+    #[cfg(feature = "crypto")]
+    pub fn prepare_proposal_response(
+        &mut self,
+        request: &abci::RequestPrepareProposal,
+        response: &abci::ResponsePrepareProposal,
+    ) {
+        if !self.stage.in_progress() || request.height != self.height {
+            return;
+        }
+        if let Some(prepared) = self.prepared.get_mut(&request.round) {
+            *prepared = Some(txs_hash(&included_txs(&response.tx_records)));
+        }
+    }
+
+    /// Track `ProcessProposal` request.
+    pub fn process_proposal(
+        &mut self,
+        request: &abci::RequestProcessProposal,
+    ) -> Result<(), ProtocolViolation> {
+        self.start_round("ProcessProposal", request.height, request.round)?;
+        self.processed.insert(request.hash.clone());
+        self.stage = BlockStage::Processed;
+        Ok(())
+    }
+
+    /// Track `ExtendVote` request; the block must be prepared or processed at
+    /// current height.
+    pub fn extend_vote(
+        &mut self,
+        request: &abci::RequestExtendVote,
+    ) -> Result<(), ProtocolViolation> {
+        const METHOD: &str = "ExtendVote";
+        self.check_height(METHOD, request.height)?;
+        self.check_round(METHOD, request.height, request.round)?;
+        self.check_block(METHOD, request.height, request.round, &request.hash, None)?;
+
+        self.stage = BlockStage::VoteExtended;
+        self.round = request.round;
+        Ok(())
+    }
+
+    /// Track `VerifyVoteExtension` request.
+    ///
+    /// Only the height is checked, as vote extensions of other validators can
+    /// refer to rounds and blocks not seen by this one.
+    pub fn verify_vote_extension(
+        &mut self,
+        request: &abci::RequestVerifyVoteExtension,
+    ) -> Result<(), ProtocolViolation> {
+        self.check_height("VerifyVoteExtension", request.height)
+    }
+
+    /// Track `FinalizeBlock` request; the block must be prepared or processed
+    /// at current height.
+    ///
+    /// The round is not checked against current one, as the block can be
+    /// committed in an earlier round than the one reached.
+    pub fn finalize_block(
+        &mut self,
+        request: &abci::RequestFinalizeBlock,
+    ) -> Result<(), ProtocolViolation> {
+        const METHOD: &str = "FinalizeBlock";
+        #[cfg(feature = "crypto")]
+        let txs_hash = request
+            .block
+            .as_ref()
+            .and_then(|block| block.data.as_ref())
+            .map(|data| txs_hash(&data.txs));
+        #[cfg(not(feature = "crypto"))]
+        let txs_hash = None;
+
+        self.check_height(METHOD, request.height)?;
+        self.check_block(
+            METHOD,
+            request.height,
+            request.round,
+            &request.hash,
+            txs_hash,
+        )?;
+
+        self.finish(BlockStage::Finalized, request.height, request.round);
+        Ok(())
+    }
+
+    /// Whether the stage refers to a block.
+    fn block_tracked(&self) -> bool {
+        !matches!(self.stage, BlockStage::Unknown | BlockStage::Initialized)
+    }
+
+    /// Move to `stage` at `height` and `round`, forgetting blocks of previous
+    /// height.
+    fn finish(&mut self, stage: BlockStage, height: i64, round: i32) {
+        self.stage = stage;
+        self.height = height;
+        self.round = round;
+        self.prepared.clear();
+        self.processed.clear();
+    }
+
+    /// Check that block request of `method` is at expected height.
+    fn check_height(&self, method: &'static str, height: i64) -> Result<(), ProtocolViolation> {
+        let Some(expected) = self.next_height() else {
+            return Ok(());
+        };
+        if height < expected {
+            Err(ProtocolViolation::HeightRegression {
+                method,
+                height,
+                expected,
+            })
+        } else if height > expected {
+            Err(ProtocolViolation::HeightSkipped {
+                method,
+                height,
+                expected,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that `round` does not go back from current round at `height`.
+    fn check_round(
+        &self,
+        method: &'static str,
+        height: i64,
+        round: i32,
+    ) -> Result<(), ProtocolViolation> {
+        if self.stage.in_progress() && round < self.round {
+            return Err(ProtocolViolation::RoundRegression {
+                method,
+                height,
+                round,
+                current: self.round,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that block with `hash` was processed, or proposal was prepared in
+    /// `round`, at current height.
+    ///
+    /// When hash of transactions of the block and of the prepared proposal are
+    /// both known, they must match.
+    fn check_block(
+        &self,
+        method: &'static str,
+        height: i64,
+        round: i32,
+        hash: &[u8],
+        txs_hash: Option<[u8; 32]>,
+    ) -> Result<(), ProtocolViolation> {
+        let prepared = match (self.prepared.get(&round), txs_hash) {
+            (Some(Some(prepared)), Some(txs_hash)) => *prepared == txs_hash,
+            (Some(_), _) => true,
+            (None, _) => false,
+        };
+        if self.stage.in_progress() && (self.processed.contains(hash) || prepared) {
+            return Ok(());
+        }
+        Err(ProtocolViolation::UnknownBlock {
+            method,
+            height,
+            round,
+            hash: hash.to_vec(),
+        })
+    }
+
+    /// Start new round of proposal at `height`, or continue current one.
+    fn start_round(
+        &mut self,
+        method: &'static str,
+        height: i64,
+        round: i32,
+    ) -> Result<(), ProtocolViolation> {
+        self.check_height(method, height)?;
+        self.check_round(method, height, round)?;
+        if self.stage == BlockStage::VoteExtended && round == self.round {
+            return Err(ProtocolViolation::RepeatedRound {
+                method,
+                height,
+                round,
+            });
+        }
+
+        if !self.stage.in_progress() {
+            self.finish(self.stage, height, round);
+        }
+        self.round = round;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockExecutionTracker, BlockStage, ProtocolViolation};
+    use crate::proto::abci;
+
+    fn prepare(height: i64, round: i32) -> abci::RequestPrepareProposal {
+        abci::RequestPrepareProposal {
+            height,
+            round,
+            ..Default::default()
+        }
+    }
+
+    fn process(height: i64, round: i32, hash: &[u8]) -> abci::RequestProcessProposal {
+        abci::RequestProcessProposal {
+            height,
+            round,
+            hash: hash.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn extend(height: i64, round: i32, hash: &[u8]) -> abci::RequestExtendVote {
+        abci::RequestExtendVote {
+            height,
+            round,
+            hash: hash.to_vec(),
+        }
+    }
+
+    /// Convert to type of a `bytes` proto field, which is `Bytes` when the
+    /// `zero-copy` feature is enabled.
+    #[cfg(feature = "crypto")]
+    fn bytes_field<T: From<Vec<u8>>>(value: Vec<u8>) -> T {
+        T::from(value)
+    }
+
+    fn finalize(height: i64, round: i32, hash: &[u8]) -> abci::RequestFinalizeBlock {
+        abci::RequestFinalizeBlock {
+            height,
+            round,
+            hash: hash.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    /// Given blocks processed and prepared in following rounds, when they are
+    /// finalized, then the tracker follows heights and rounds.
+    fn test_lifecycle() {
+        let mut tracker = BlockExecutionTracker::new();
+        tracker
+            .init_chain(&abci::RequestInitChain {
+                initial_height: 5,
+                ..Default::default()
+            })
+            .expect("init chain");
+        assert_eq!(tracker.stage(), BlockStage::Initialized);
+        assert_eq!(tracker.next_height(), Some(5));
+        assert_eq!(tracker.height(), None);
+
+        // Validator: round 0 fails, block of round 1 is committed.
+        tracker
+            .process_proposal(&process(5, 0, b"a"))
+            .expect("process");
+        tracker
+            .process_proposal(&process(5, 1, b"b"))
+            .expect("process");
+        tracker.extend_vote(&extend(5, 1, b"b")).expect("extend");
+        tracker
+            .verify_vote_extension(&abci::RequestVerifyVoteExtension {
+                height: 5,
+                round: 1,
+                ..Default::default()
+            })
+            .expect("verify");
+        tracker
+            .finalize_block(&finalize(5, 1, b"b"))
+            .expect("finalize");
+        assert_eq!(tracker.stage(), BlockStage::Finalized);
+        assert_eq!((tracker.height(), tracker.round()), (Some(5), Some(1)));
+
+        // Proposer: hash of prepared block is not known until finalization.
+        tracker.prepare_proposal(&prepare(6, 0)).expect("prepare");
+        tracker.extend_vote(&extend(6, 0, b"c")).expect("extend");
+        tracker
+            .finalize_block(&finalize(6, 0, b"c"))
+            .expect("finalize");
+        assert_eq!(tracker.next_height(), Some(7));
+    }
+
+    #[test]
+    /// Given tracked blocks, when contradictory requests are received, then
+    /// violations are returned and the state is not changed.
+    fn test_violations() {
+        let mut tracker = BlockExecutionTracker::new().with_last_block_height(10);
+        assert_eq!(
+            tracker.init_chain(&Default::default()),
+            Err(ProtocolViolation::InitChainAfterBlock { height: 10 })
+        );
+        assert_eq!(
+            tracker.process_proposal(&process(10, 0, b"a")),
+            Err(ProtocolViolation::HeightRegression {
+                method: "ProcessProposal",
+                height: 10,
+                expected: 11,
+            })
+        );
+        assert_eq!(
+            tracker.prepare_proposal(&prepare(12, 0)),
+            Err(ProtocolViolation::HeightSkipped {
+                method: "PrepareProposal",
+                height: 12,
+                expected: 11,
+            })
+        );
+
+        tracker
+            .process_proposal(&process(11, 1, b"a"))
+            .expect("process");
+        assert_eq!(
+            tracker.process_proposal(&process(11, 0, b"b")),
+            Err(ProtocolViolation::RoundRegression {
+                method: "ProcessProposal",
+                height: 11,
+                round: 0,
+                current: 1,
+            })
+        );
+        let unknown = tracker.finalize_block(&finalize(11, 1, b"b"));
+        assert_eq!(
+            unknown,
+            Err(ProtocolViolation::UnknownBlock {
+                method: "FinalizeBlock",
+                height: 11,
+                round: 1,
+                hash: b"b".to_vec(),
+            })
+        );
+        assert!(unknown.unwrap_err().to_string().contains("block 62 "));
+        assert_eq!(tracker.stage(), BlockStage::Processed);
+
+        tracker
+            .finalize_block(&finalize(11, 1, b"a"))
+            .expect("finalize");
+        assert_eq!(
+            tracker.finalize_block(&finalize(11, 1, b"a")),
+            Err(ProtocolViolation::HeightRegression {
+                method: "FinalizeBlock",
+                height: 11,
+                expected: 12,
+            })
+        );
+
+        tracker.prepare_proposal(&prepare(12, 0)).expect("prepare");
+        tracker.extend_vote(&extend(12, 0, b"c")).expect("extend");
+        assert_eq!(
+            tracker.prepare_proposal(&prepare(12, 0)),
+            Err(ProtocolViolation::RepeatedRound {
+                method: "PrepareProposal",
+                height: 12,
+                round: 0,
+            })
+        );
+
+        let mut tracker = BlockExecutionTracker::new();
+        tracker.init_chain(&Default::default()).expect("init chain");
+        assert_eq!(
+            tracker.init_chain(&Default::default()),
+            Err(ProtocolViolation::RepeatedInitChain)
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    /// Given tracked response to PrepareProposal, when a block is finalized,
+    /// then its transactions must match the prepared proposal.
+    fn test_prepared_txs() {
+        use crate::proto::{
+            abci::{tx_record::TxAction, TxRecord},
+            types,
+        };
+
+        let finalize_txs = |txs: &[&[u8]]| abci::RequestFinalizeBlock {
+            block: Some(types::Block {
+                data: Some(types::Data {
+                    txs: txs.iter().map(|tx| tx.to_vec()).collect(),
+                }),
+                ..Default::default()
+            }),
+            ..finalize(1, 0, b"hash")
+        };
+        let response = abci::ResponsePrepareProposal {
+            tx_records: vec![TxRecord {
+                action: TxAction::Unmodified.into(),
+                tx: bytes_field(b"tx1".to_vec()),
+            }],
+            ..Default::default()
+        };
+
+        let mut tracker = BlockExecutionTracker::new();
+        let request = prepare(1, 0);
+        tracker.prepare_proposal(&request).expect("prepare");
+        tracker.track_response(
+            &abci::request::Value::PrepareProposal(request),
+            &abci::response::Value::PrepareProposal(response),
+        );
+
+        assert_eq!(
+            tracker.finalize_block(&finalize_txs(&[b"tx2"])),
+            Err(ProtocolViolation::UnknownBlock {
+                method: "FinalizeBlock",
+                height: 1,
+                round: 0,
+                hash: b"hash".to_vec(),
+            })
+        );
+        tracker
+            .finalize_block(&finalize_txs(&[b"tx1"]))
+            .expect("finalize");
+    }
+}
@@ -0,0 +1,299 @@
+//! Middleware layers wrapping request dispatchers.
+//!
+//! A [Layer] wraps a [RequestDispatcher] or [AsyncRequestDispatcher] and
+//! returns a new dispatcher, which can process requests before they reach the
+//! wrapped one, and responses before they are returned. Layers are added with
+//! [`ServerBuilder::layer()`](crate::ServerBuilder::layer()), or applied
+//! directly with [Layer::layer()].
+//!
+//! Built-in layers:
+//!
+//! * [SpanLayer] - process each request inside a tracing span, with the ABCI
+//!   method, request ID and block height (requires `tracing-span` feature),
+//! * [TraceLayer] - log requests and responses on trace level,
+//! * [ExceptionLogLayer] - log exceptions returned by the dispatcher as errors,
+//! * [DefaultLayers] - all of the above, in this order.
+//!
+//! Dispatchers implemented by [Application](crate::Application) and
+//! [AsyncApplication](crate::AsyncApplication) do not create spans nor log
+//! anything. The server wraps its dispatchers with [DefaultLayers], unless
+//! other layers are added with
+//! [`ServerBuilder::layer()`](crate::ServerBuilder::layer()), which replace the
+//! default ones.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tenderdash_abci::{
+//!     layer::{DefaultLayers, TraceLayer},
+//!     Application, ServerBuilder,
+//! };
+//!
+//! struct MyApp {}
+//! impl Application for MyApp {}
+//!
+//! // Only log requests and responses on trace level
+//! let server = ServerBuilder::new(MyApp {}, "unix:///tmp/abci.sock")
+//!     .layer(TraceLayer)
+//!     .build()
+//!     .expect("server failed");
+//!
+//! // Keep default layers and add custom ones on top of them
+//! let server = ServerBuilder::new(MyApp {}, "unix:///tmp/abci.sock")
+//!     .layer(DefaultLayers)
+//!     .layer(TraceLayer)
+//!     .build()
+//!     .expect("server failed");
+//! ```
+use async_trait::async_trait;
+
+use crate::{
+    application::{log_response, method_name},
+    proto::abci::{response, Request, Response},
+    AsyncRequestDispatcher, RequestContext, RequestDispatcher,
+};
+
+/// Wraps a dispatcher of type `D` into another dispatcher.
+///
+/// Dispatchers returned by layers should implement [RequestDispatcher] when
+/// `D` implements it, and [AsyncRequestDispatcher] when `D` implements it, so
+/// that the layer can be used with both synchronous and asynchronous
+/// applications.
+pub trait Layer<D> {
+    /// Dispatcher returned by the layer.
+    type Dispatcher;
+
+    /// Wrap `inner` dispatcher.
+    fn layer(&self, inner: D) -> Self::Dispatcher;
+}
+
+/// Layer that processes each request inside a tracing span.
+///
+/// The span is [`RequestContext::span()`](crate::RequestContext::span()) of
+/// the request being dispatched by the server, so its `request_id` is the same
+/// as in the context; outside of the server, a new span is created with
+/// [`tracing_span::request_span()`](crate::tracing_span::request_span()).
+#[cfg(feature = "tracing-span")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpanLayer;
+
+#[cfg(feature = "tracing-span")]
+impl<D> Layer<D> for SpanLayer {
+    type Dispatcher = RequestSpan<D>;
+
+    fn layer(&self, inner: D) -> Self::Dispatcher {
+        RequestSpan { inner }
+    }
+}
+
+/// Dispatcher returned by [SpanLayer].
+#[cfg(feature = "tracing-span")]
+#[derive(Debug)]
+pub struct RequestSpan<D> {
+    inner: D,
+}
+
+#[cfg(feature = "tracing-span")]
+impl<D: RequestDispatcher> RequestDispatcher for RequestSpan<D> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        use crate::tracing_span::current_span;
+
+        let _span = current_span(request.value.as_ref()?).entered();
+        self.inner.handle(request)
+    }
+}
+
+#[cfg(feature = "tracing-span")]
+#[async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for RequestSpan<D> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        use tracing::Instrument;
+
+        use crate::tracing_span::current_span;
+
+        let span = current_span(request.value.as_ref()?);
+        self.inner.handle(request).instrument(span).await
+    }
+}
+
+/// Layer that logs requests and responses on trace level.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer;
+
+impl<D> Layer<D> for TraceLayer {
+    type Dispatcher = Trace<D>;
+
+    fn layer(&self, inner: D) -> Self::Dispatcher {
+        Trace { inner }
+    }
+}
+
+/// Dispatcher returned by [TraceLayer].
+#[derive(Debug)]
+pub struct Trace<D> {
+    inner: D,
+}
+
+impl<D: RequestDispatcher> RequestDispatcher for Trace<D> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        tracing::trace!(?request, "received ABCI request");
+        let response = self.inner.handle(request)?;
+        if let Some(value) = &response.value {
+            log_response(value);
+        }
+        Some(response)
+    }
+}
+
+#[async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for Trace<D> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        tracing::trace!(?request, "received ABCI request");
+        let response = self.inner.handle(request).await?;
+        if let Some(value) = &response.value {
+            log_response(value);
+        }
+        Some(response)
+    }
+}
+
+/// Layer that logs exceptions returned by the dispatcher as errors.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExceptionLogLayer;
+
+impl<D> Layer<D> for ExceptionLogLayer {
+    type Dispatcher = ExceptionLog<D>;
+
+    fn layer(&self, inner: D) -> Self::Dispatcher {
+        ExceptionLog { inner }
+    }
+}
+
+/// Dispatcher returned by [ExceptionLogLayer].
+#[derive(Debug)]
+pub struct ExceptionLog<D> {
+    inner: D,
+}
+
+impl<D> ExceptionLog<D> {
+    /// Log `response` to request of `method` if it is an exception.
+    fn log(method: Option<&'static str>, response: &Response) {
+        if let Some(response::Value::Exception(exception)) = &response.value {
+            let context = RequestContext::current();
+            tracing::error!(
+                method,
+                error = exception.error,
+                connection_id = context.as_ref().and_then(RequestContext::connection_id),
+                request_id = context.as_ref().map(RequestContext::request_id),
+                "error processing request"
+            );
+        }
+    }
+}
+
+impl<D: RequestDispatcher> RequestDispatcher for ExceptionLog<D> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        let method = request.value.as_ref().map(method_name);
+        let response = self.inner.handle(request)?;
+        Self::log(method, &response);
+        Some(response)
+    }
+}
+
+#[async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for ExceptionLog<D> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        let method = request.value.as_ref().map(method_name);
+        let response = self.inner.handle(request).await?;
+        Self::log(method, &response);
+        Some(response)
+    }
+}
+
+/// Layer that applies [SpanLayer] (with `tracing-span` feature), [TraceLayer]
+/// and [ExceptionLogLayer], in this order.
+///
+/// Used by the server when no layers are added with
+/// [`ServerBuilder::layer()`](crate::ServerBuilder::layer()).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultLayers;
+
+#[cfg(feature = "tracing-span")]
+type DefaultStack<D> = RequestSpan<Trace<ExceptionLog<D>>>;
+#[cfg(not(feature = "tracing-span"))]
+type DefaultStack<D> = Trace<ExceptionLog<D>>;
+
+impl<D> Layer<D> for DefaultLayers {
+    type Dispatcher = Defaults<D>;
+
+    fn layer(&self, inner: D) -> Self::Dispatcher {
+        let inner = TraceLayer.layer(ExceptionLogLayer.layer(inner));
+        #[cfg(feature = "tracing-span")]
+        let inner = SpanLayer.layer(inner);
+
+        Defaults { inner }
+    }
+}
+
+/// Dispatcher returned by [DefaultLayers].
+#[derive(Debug)]
+pub struct Defaults<D> {
+    inner: DefaultStack<D>,
+}
+
+impl<D: RequestDispatcher> RequestDispatcher for Defaults<D> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        self.inner.handle(request)
+    }
+}
+
+#[async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for Defaults<D> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        self.inner.handle(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultLayers, ExceptionLogLayer, Layer, TraceLayer};
+    use crate::{
+        proto::abci::{request, response, Request, RequestEcho},
+        Application, AsyncApplication, AsyncRequestDispatcher, RequestDispatcher,
+    };
+
+    struct App;
+    impl Application for App {}
+
+    struct AsyncApp;
+    impl AsyncApplication for AsyncApp {}
+
+    fn echo() -> Request {
+        Request {
+            value: Some(request::Value::Echo(RequestEcho {
+                message: "layered".to_string(),
+            })),
+        }
+    }
+
+    #[tokio::test]
+    /// Given dispatchers wrapped in built-in layers, when requests are
+    /// handled, then responses of wrapped dispatchers are returned.
+    async fn test_layers_pass_responses() {
+        let sync = DefaultLayers.layer(TraceLayer.layer(ExceptionLogLayer.layer(App)));
+        let Some(response::Value::Echo(echo_response)) = sync.handle(echo()).and_then(|r| r.value)
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(echo_response.message, "layered");
+
+        let async_dispatcher =
+            DefaultLayers.layer(TraceLayer.layer(ExceptionLogLayer.layer(AsyncApp)));
+        let Some(response::Value::Echo(echo_response)) =
+            async_dispatcher.handle(echo()).await.and_then(|r| r.value)
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(echo_response.message, "layered");
+    }
+}
@@ -11,22 +11,58 @@
 //!
 //! Implement the [application::Application] trait with custom logic for
 //! blockchain processing. Expect messages defined in [proto::abci] crate.
+//! Applications that need to `.await` inside handlers can implement
+//! [AsyncApplication] instead, and start the server with
+//! [ServerBuilder::build_async()].
+//!
+//! With `client` feature, `AbciClient` connects to ABCI applications the same
+//! way Tenderdash does, which is useful in integration tests and tooling.
+//! With `testing` feature, [testing::Simulator] drives applications through
+//! consensus flows of a simulated Tenderdash network.
 
 mod application;
+mod async_application;
+#[cfg(feature = "client")]
+mod client;
+mod context;
+pub mod layer;
 #[cfg(feature = "server")]
 mod server;
 
 use std::io;
 
 pub use application::{check_version, Application, RequestDispatcher};
+pub use async_application::{AsyncApplication, AsyncRequestDispatcher};
+#[cfg(feature = "client")]
+pub use client::{AbciClient, BlockingAbciClient, ClientBuilder};
+pub use context::RequestContext;
+pub use layer::Layer;
+#[cfg(feature = "metrics")]
+pub use server::metrics::{self, Metrics};
+#[cfg(feature = "server")]
+pub use server::recording;
+#[cfg(all(feature = "server", feature = "grpc"))]
+pub use server::GrpcBridge;
 #[allow(deprecated)]
 #[cfg(feature = "server")]
-pub use server::{start_server, CancellationToken, Server, ServerBuilder, ServerRuntime};
+pub use server::{
+    current_connection_id, start_server, AsyncServer, BindAddress, CancellationToken,
+    ConnectionClass, ConnectionHooks, Deadline, DeadlineAction, DisconnectReason, IoStats,
+    PanicPolicy, Reconnect, Recorder, ServePolicy, Server, ServerBuilder, ServerRuntime,
+    StdListener,
+};
+#[cfg(feature = "tls")]
+pub use server::{tls, TlsConfig};
 pub use tenderdash_proto as proto;
 use tenderdash_proto::prost::{DecodeError, EncodeError};
 
+pub mod execution;
+#[cfg(feature = "tower")]
+pub mod service;
 #[cfg(feature = "crypto")]
 pub mod signatures;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(feature = "tracing-span")]
 /// Create tracing::Span for better logging
 pub mod tracing_span;
@@ -46,6 +82,37 @@ pub enum Error {
     Canonical(String),
     #[error("server terminated")]
     Cancelled(),
+    /// Server was cancelled, but some responses were lost because connections
+    /// were not drained within the drain timeout.
+    ///
+    /// Reported as a separate variant rather than a flag of
+    /// [Error::Cancelled], so that existing matches of `Error::Cancelled()`
+    /// keep compiling; they do not match servers whose drain timed out.
+    #[error("server terminated, drain timed out")]
+    DrainTimedOut,
     #[error("async runtime error")]
     Async(String),
+    #[error("frame of {size} bytes exceeds limit of {limit} bytes")]
+    FrameTooLarge { size: usize, limit: usize },
+    #[error("application returned exception: {0}")]
+    Exception(String),
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
+    /// Request dispatcher returned no response, and the connection should be
+    /// closed.
+    #[error("request dispatcher stopped")]
+    DispatcherStopped,
+}
+
+impl Error {
+    /// Error returned when the server was cancelled; `drained` is `false` when
+    /// connections were not drained within the drain timeout.
+    #[cfg(feature = "server")]
+    pub(crate) fn cancelled(drained: bool) -> Self {
+        if drained {
+            Self::Cancelled()
+        } else {
+            Self::DrainTimedOut
+        }
+    }
 }
@@ -1,13 +1,25 @@
 //! Tenderdash ABCI Server.
-mod codec;
+mod bind_address;
+pub(crate) mod codec;
+mod connection_class;
+mod deadline;
 mod generic;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hooks;
+mod listener;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod panics;
+pub mod recording;
+mod serve;
+mod stats;
+#[cfg(feature = "tls")]
+pub mod tls;
 
-#[cfg(feature = "tcp")]
-use std::{
-    net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6},
-    str::FromStr,
-};
+use std::{collections::BTreeMap, ops::ControlFlow, sync::Arc, time::Duration};
 
+use async_trait::async_trait;
 use futures::Future;
 #[cfg(feature = "tcp")]
 use tokio::net::TcpListener;
@@ -19,8 +31,41 @@ use tokio::{
 };
 pub use tokio_util::sync::CancellationToken;
 
-use self::generic::GenericServer;
-use crate::{application::RequestDispatcher, Error};
+#[cfg(feature = "grpc")]
+pub use self::grpc::GrpcBridge;
+#[cfg(feature = "grpc")]
+use self::grpc::GrpcServer;
+#[cfg(feature = "metrics")]
+pub use self::metrics::Metrics;
+#[cfg(feature = "tls")]
+pub use self::tls::TlsConfig;
+pub use self::{
+    bind_address::BindAddress,
+    codec::current_connection_id,
+    connection_class::ConnectionClass,
+    deadline::{Deadline, DeadlineAction},
+    hooks::{ConnectionHooks, DisconnectReason},
+    listener::StdListener,
+    panics::PanicPolicy,
+    recording::Recorder,
+    serve::{Reconnect, ServePolicy},
+    stats::IoStats,
+};
+use self::{
+    codec::{FrameLimits, ResponseBatching},
+    connection_class::ClassDispatcher,
+    generic::GenericServer,
+    hooks::Hooks,
+    listener::ListenerSource,
+};
+use crate::{
+    application::RequestDispatcher,
+    layer::{DefaultLayers, Defaults, Layer},
+    AsyncRequestDispatcher, Error,
+};
+
+/// Default maximum time of draining connections on shutdown.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[cfg(not(any(feature = "tcp", feature = "unix")))]
 compile_error!("At least one of `tcp` or `unix` features must be enabled");
@@ -38,81 +83,260 @@ pub trait Server {
     ///
     /// It is safe to call this method multiple times after it finishes;
     /// however, errors must be examined and handled, as the connection
-    /// should not terminate. One exception is [Error::Cancelled], which
-    /// means server shutdown was requested.
+    /// should not terminate. Exceptions are [Error::Cancelled], which
+    /// means server shutdown was requested, and [Error::DrainTimedOut], which
+    /// also means that some responses were lost on shutdown.
     fn next_client(&self) -> Result<(), Error>;
 
+    /// Accept and process clients one after another, as long as `policy`
+    /// allows.
+    ///
+    /// Returns `Ok(())` when the server is cancelled and connections are
+    /// drained, or when `policy` does not allow accepting more clients.
+    /// Returns the last error when errors limit of `policy` is reached, and
+    /// [Error::DrainTimedOut] when responses were lost on shutdown.
+    fn serve(&self, policy: ServePolicy) -> Result<(), Error> {
+        let mut state = policy.start();
+        loop {
+            match state.next(self.next_client()) {
+                ControlFlow::Continue(delay) => std::thread::sleep(delay),
+                ControlFlow::Break(result) => return result,
+            }
+        }
+    }
+
     #[deprecated = "use `next_client()`"]
     fn handle_connection(&self) -> Result<(), Error> {
         self.next_client()
     }
 }
 
+/// Asynchronous ABCI Server handle.
+///
+/// Returned by [`ServerBuilder::build_async()`]. Requests are processed by
+/// [AsyncRequestDispatcher] directly on the tokio runtime of the server.
+#[async_trait]
+pub trait AsyncServer: Send + Sync {
+    /// Process one incoming connection.
+    ///
+    /// Semantics are the same as in [`Server::next_client()`].
+    async fn next_client(&self) -> Result<(), Error>;
+
+    /// Accept and process clients one after another, as long as `policy`
+    /// allows.
+    ///
+    /// Semantics are the same as in [`Server::serve()`].
+    async fn serve(&self, policy: ServePolicy) -> Result<(), Error> {
+        let mut state = policy.start();
+        loop {
+            match state.next(self.next_client().await) {
+                ControlFlow::Continue(delay) => tokio::time::sleep(delay).await,
+                ControlFlow::Break(result) => return result,
+            }
+        }
+    }
+}
+
 /// ABCI server builder that creates and starts ABCI server
 ///
 /// Create new server with [`ServerBuilder::new()`], configure it as needed, and
 /// finalize using [`ServerBuilder::build()`]. This will create and start new
 /// ABCI server.
 ///
-/// Use [`Server::next_client()`] to accept connection from ABCI client
-/// (Tenderdash) and start processing incoming requests. Each incoming
+/// Use [`Server::serve()`] to accept connections from ABCI client
+/// (Tenderdash) and process incoming requests until the server is cancelled,
+/// or [`Server::next_client()`] to process one connection. Each incoming
 /// connection will be processed using `app`.
 ///
 /// # Examples
 ///
 /// ```no_run
+/// use tenderdash_abci::ServePolicy;
+///
 /// struct MyAbciApplication {};
 /// impl tenderdash_abci::Application for MyAbciApplication {};
 /// let app = MyAbciApplication {};
 /// let bind_address = "unix:///tmp/abci.sock";
 /// let server = tenderdash_abci::ServerBuilder::new(app, &bind_address).build().expect("server failed");
-/// loop {
-///     if let Err(tenderdash_abci::Error::Cancelled()) = server.next_client() {
-///         break;
-///     }
-/// }
+/// server.serve(ServePolicy::new()).expect("server failed");
 /// ```
-pub struct ServerBuilder<D>
-where
-    D: RequestDispatcher,
-{
+pub struct ServerBuilder<D> {
     app: D,
-    bind_address: String,
+    bind_address: Result<BindAddress, Error>,
     cancel: Option<CancellationToken>,
     server_runtime: Option<ServerRuntime>,
+    config: ServerConfig,
+    listener: Option<ListenerSource>,
+    /// Wrap dispatchers with [DefaultLayers] when the server is built; cleared
+    /// when a layer is added.
+    default_layers: bool,
 }
 
-impl<'a, App: RequestDispatcher + 'a> ServerBuilder<App> {
+/// Server configuration options, set using [ServerBuilder].
+#[derive(Clone, Debug)]
+pub(crate) struct ServerConfig {
+    /// Maximum number of client connections served at once.
+    pub(crate) max_connections: usize,
+    /// Dispatchers dedicated to some connection classes; requests of other
+    /// classes are processed by the main dispatcher.
+    pub(crate) class_dispatchers: BTreeMap<ConnectionClass, ClassDispatcher>,
+    /// Maximum sizes of requests and responses.
+    pub(crate) frame_limits: FrameLimits,
+    /// Thresholds of batched response writing; `None` flushes after each
+    /// response.
+    pub(crate) response_batching: Option<ResponseBatching>,
+    /// Throughput counters.
+    pub(crate) io_stats: IoStats,
+    /// Maximum time of draining connections on shutdown.
+    pub(crate) drain_timeout: Duration,
+    /// Deadlines of request processing, by ABCI method name.
+    pub(crate) deadlines: BTreeMap<String, Deadline>,
+    /// Callbacks notified about connection lifecycle.
+    pub(crate) hooks: Option<Hooks>,
+    /// Action taken after a dispatcher panicked; `None` if panics are not
+    /// caught.
+    pub(crate) panic_policy: Option<PanicPolicy>,
+    /// Permissions of Unix socket file.
+    #[cfg(feature = "unix")]
+    pub(crate) unix_socket_mode: Option<u32>,
+    /// Group ID of Unix socket file.
+    #[cfg(feature = "unix")]
+    pub(crate) unix_socket_group: Option<u32>,
+    /// TLS configuration, used with `tls://` addresses.
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+    /// Metrics of request processing.
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Option<Metrics>,
+    /// Address of built-in HTTP listener that serves metrics.
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics_address: Option<String>,
+    /// Cancel the server on SIGTERM or SIGINT.
+    #[cfg(feature = "signal")]
+    pub(crate) handle_signals: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1,
+            class_dispatchers: Default::default(),
+            frame_limits: Default::default(),
+            response_batching: None,
+            io_stats: Default::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            deadlines: Default::default(),
+            hooks: None,
+            panic_policy: None,
+            #[cfg(feature = "unix")]
+            unix_socket_mode: None,
+            #[cfg(feature = "unix")]
+            unix_socket_group: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "metrics")]
+            metrics_address: None,
+            #[cfg(feature = "signal")]
+            handle_signals: false,
+        }
+    }
+}
+
+impl<App> ServerBuilder<App> {
     /// Create new server builder.
     ///
     /// # Arguments
     ///
     /// * `address` - address in URI format, pointing either to TCP address and
-    ///   port (eg. `tcp://0.0.0.0:1234`, `tcp://[::1]:1234`) or Unix socket
-    ///   (`unix:///var/run/abci.sock`)
+    ///   port (eg. `tcp://0.0.0.0:1234`, `tcp://[::1]:1234`,
+    ///   `tcp://localhost:1234`), TLS-encrypted TCP address and port (eg.
+    ///   `tls://0.0.0.0:1234`, requires `tls` feature and
+    ///   [`ServerBuilder::with_tls()`]), gRPC address and port (eg.
+    ///   `grpc://0.0.0.0:1234`, requires `grpc` feature) or Unix socket
+    ///   (`unix:///var/run/abci.sock`, or `unix://@abci` for Linux abstract
+    ///   socket); see [BindAddress]. Invalid address is reported as
+    ///   [Error::Configuration] when the server is built.
     /// * `app` - request dispatcher, most likely implementation of Application
-    ///   trait
+    ///   or AsyncApplication trait
     pub fn new(app: App, address: &str) -> Self {
         Self {
             app,
-            bind_address: address.to_string(),
+            bind_address: address.parse(),
             cancel: None,
             server_runtime: None,
+            config: Default::default(),
+            listener: None,
+            default_layers: true,
         }
     }
 
-    /// Build and start the ABCI server.
-    ///
-    /// # Return
-    ///
-    /// Returns [`Server`] which provides [`Server::next_client()`]
-    /// method. Call it in a loop to accept and process incoming
-    /// connections.
-    pub fn build(self) -> Result<Box<dyn Server + 'a>, crate::Error> {
-        let bind_address =
-            url::Url::parse(self.bind_address.as_ref()).expect("invalid bind address");
-        if bind_address.scheme() != "tcp" && bind_address.scheme() != "unix" {
-            panic!("app_address must be either tcp:// or unix://");
+    /// Set address to listen on, replacing the one passed to
+    /// [`ServerBuilder::new()`].
+    pub fn with_bind_address(mut self, address: BindAddress) -> Self {
+        self.bind_address = Ok(address);
+        self
+    }
+
+    /// Start listening on the bind address, or use provided listener.
+    fn bind(self) -> Result<BoundServer<App>, crate::Error> {
+        if self.config.max_connections == 0 {
+            return Err(Error::Configuration(
+                "max_connections must be greater than 0".to_string(),
+            ));
+        }
+        deadline::validate_deadlines(&self.config.deadlines)?;
+        let listener = self
+            .listener
+            .map(ListenerSource::take)
+            .transpose()?
+            .flatten();
+        if let Some(listener) = listener {
+            let server_runtime: ServerRuntime = self.server_runtime.unwrap_or_default();
+            let _guard = server_runtime.handle.enter();
+            let cancel = self.cancel.unwrap_or_default();
+            start_services(&self.config, &server_runtime, &cancel)?;
+
+            return match listener {
+                #[cfg(feature = "tcp")]
+                StdListener::Tcp(listener) => {
+                    let server = GenericServer::<App, TcpListener>::from_std(
+                        self.app,
+                        listener,
+                        cancel,
+                        server_runtime,
+                        self.config,
+                    )?;
+                    Ok(BoundServer::Tcp(server))
+                },
+                #[cfg(feature = "unix")]
+                StdListener::Unix(listener) => {
+                    #[cfg(feature = "tls")]
+                    if self.config.tls.is_some() {
+                        return Err(Error::Configuration(
+                            "TLS is not supported with Unix sockets".to_string(),
+                        ));
+                    }
+                    let server = GenericServer::<App, UnixListener>::from_std(
+                        self.app,
+                        listener,
+                        cancel,
+                        server_runtime,
+                        self.config,
+                    )?;
+                    Ok(BoundServer::Unix(server))
+                },
+            };
+        }
+
+        let bind_address = self.bind_address?;
+        #[cfg(feature = "tls")]
+        if self.config.tls.is_some() != matches!(bind_address, BindAddress::Tls { .. }) {
+            return Err(Error::Configuration(
+                "TLS configuration must be provided for tls:// addresses, and only for them"
+                    .to_string(),
+            ));
         }
         let server_runtime: ServerRuntime = self.server_runtime.unwrap_or_default();
 
@@ -120,30 +344,91 @@ impl<'a, App: RequestDispatcher + 'a> ServerBuilder<App> {
 
         // No cancel is defined, so we add some "mock"
         let cancel = self.cancel.unwrap_or_default();
+        start_services(&self.config, &server_runtime, &cancel)?;
 
-        let server = match bind_address.scheme() {
+        let server = match &bind_address {
             #[cfg(feature = "tcp")]
-            "tcp" => Box::new(GenericServer::<App, TcpListener>::bind(
+            BindAddress::Tcp { .. } => BoundServer::Tcp(GenericServer::<App, TcpListener>::bind(
                 self.app,
-                parse_tcp_uri(bind_address),
+                bind_address.socket_addrs()?.as_slice(),
                 cancel,
                 server_runtime,
-            )?) as Box<dyn Server + 'a>,
+                self.config,
+            )?),
+            #[cfg(feature = "tls")]
+            BindAddress::Tls { .. } => BoundServer::Tcp(GenericServer::<App, TcpListener>::bind(
+                self.app,
+                bind_address.socket_addrs()?.as_slice(),
+                cancel,
+                server_runtime,
+                self.config,
+            )?),
             #[cfg(feature = "unix")]
-            "unix" => Box::new(GenericServer::<App, UnixListener>::bind(
+            BindAddress::Unix(path) => BoundServer::Unix(GenericServer::<App, UnixListener>::bind(
                 self.app,
-                bind_address.path(),
+                path,
                 cancel,
                 server_runtime,
-            )?) as Box<dyn Server + 'a>,
-            _ => panic!(
-                "listen address uses unsupported scheme `{}`",
-                bind_address.scheme()
-            ),
+                self.config,
+            )?),
+            #[cfg(feature = "grpc")]
+            BindAddress::Grpc { .. } => BoundServer::Grpc(GrpcServer::bind(
+                self.app,
+                bind_address.socket_addrs()?.as_slice(),
+                cancel,
+                server_runtime,
+                self.config,
+            )?),
+            #[cfg(all(feature = "unix", any(target_os = "linux", target_os = "android")))]
+            BindAddress::AbstractUnix(name) => {
+                BoundServer::Unix(GenericServer::<App, UnixListener>::bind_abstract(
+                    self.app,
+                    name,
+                    cancel,
+                    server_runtime,
+                    self.config,
+                )?)
+            },
+            #[allow(unreachable_patterns)]
+            address => {
+                return Err(Error::Configuration(format!(
+                    "address {} is not supported; check enabled features",
+                    address
+                )))
+            },
         };
 
         Ok(server)
     }
+
+    /// Use already bound listener instead of binding to the address passed
+    /// to [`ServerBuilder::new()`].
+    ///
+    /// Accepts [std::net::TcpListener] and
+    /// [std::os::unix::net::UnixListener]. The address passed to
+    /// [`ServerBuilder::new()`] is ignored. TLS, if configured with
+    /// [`ServerBuilder::with_tls()`], is used on TCP listeners.
+    pub fn with_listener<L: Into<StdListener>>(mut self, listener: L) -> Self {
+        self.listener = Some(ListenerSource::Std(listener.into()));
+        self
+    }
+
+    /// Use socket passed by systemd socket activation, if any.
+    ///
+    /// When the process was started by systemd with socket activation
+    /// (`LISTEN_PID` and `LISTEN_FDS` environment variables are set), the
+    /// first socket passed by systemd is used, like in
+    /// [`ServerBuilder::with_listener()`]. Otherwise, the server binds to the
+    /// address passed to [`ServerBuilder::new()`].
+    ///
+    /// This allows systemd to own the ABCI socket, so that the application can
+    /// be restarted without Tenderdash losing the socket.
+    #[cfg(unix)]
+    pub fn with_systemd_socket(mut self) -> Self {
+        self.listener = Some(ListenerSource::Systemd);
+        self
+    }
+
     /// Set a [CancellationToken] token to support graceful shutdown.
     ///
     /// Call [`CancellationToken::cancel()`] to stop the server gracefully.
@@ -155,6 +440,311 @@ impl<'a, App: RequestDispatcher + 'a> ServerBuilder<App> {
             ..self
         }
     }
+
+    /// Cancel the server when the process receives SIGTERM or SIGINT.
+    ///
+    /// The [CancellationToken] of the server is cancelled, so connections are
+    /// drained and [`Server::serve()`] returns. On non-Unix platforms, only
+    /// Ctrl-C is handled. The runtime of the server must have signal handling
+    /// enabled.
+    #[cfg(feature = "signal")]
+    pub fn with_signal_handling(mut self) -> Self {
+        self.config.handle_signals = true;
+        self
+    }
+    /// Set maximum number of client connections served at the same time.
+    ///
+    /// By default, only one connection is served at a time, and
+    /// [`Server::next_client()`] returns when that connection is closed.
+    ///
+    /// When `max_connections` is greater than 1, the server keeps accepting
+    /// new connections until it is cancelled, serving up to `max_connections`
+    /// of them at once. This is useful when Tenderdash reconnects or when
+    /// some other client (like a debugging tool) connects alongside
+    /// Tenderdash. All connections share one request dispatcher; requests are
+    /// processed one at a time, in the order they were received, unless
+    /// dedicated dispatchers are configured with
+    /// [`ServerBuilder::with_dispatcher()`].
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// Process requests of given [ConnectionClass] with a separate
+    /// [RequestDispatcher].
+    ///
+    /// Requests of each class configured this way are processed by a dedicated
+    /// worker thread, so a slow `Query` or `LoadSnapshotChunk` does not delay
+    /// `PrepareProposal`. Requests of classes without a dedicated dispatcher,
+    /// as well as `Echo` and `Flush`, are processed by the main dispatcher
+    /// passed to [`ServerBuilder::new()`].
+    ///
+    /// Responses are always sent in the order of requests received on a
+    /// connection.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tenderdash_abci::{Application, ConnectionClass, ServerBuilder};
+    ///
+    /// struct ConsensusApp {}
+    /// impl Application for ConsensusApp {}
+    ///
+    /// struct QueryApp {}
+    /// impl Application for QueryApp {}
+    ///
+    /// let server = ServerBuilder::new(ConsensusApp {}, "unix:///tmp/abci.sock")
+    ///     .with_dispatcher(ConnectionClass::Info, QueryApp {})
+    ///     .build()
+    ///     .expect("server failed");
+    /// ```
+    pub fn with_dispatcher<D>(mut self, class: ConnectionClass, dispatcher: D) -> Self
+    where
+        D: RequestDispatcher + Send + Sync + 'static,
+    {
+        self.config
+            .class_dispatchers
+            .insert(class, ClassDispatcher::Sync(Arc::new(dispatcher)));
+        self
+    }
+
+    /// Process requests of given [ConnectionClass] with a separate
+    /// [AsyncRequestDispatcher].
+    ///
+    /// Works like [`ServerBuilder::with_dispatcher()`], but the dispatcher is
+    /// executed as a task on the tokio runtime of the server.
+    pub fn with_async_dispatcher<D>(mut self, class: ConnectionClass, dispatcher: D) -> Self
+    where
+        D: AsyncRequestDispatcher + 'static,
+    {
+        self.config
+            .class_dispatchers
+            .insert(class, ClassDispatcher::Async(Arc::new(dispatcher)));
+        self
+    }
+
+    /// Wrap the main dispatcher with a [Layer].
+    ///
+    /// Layers added later wrap the ones added earlier, so they see requests
+    /// first and responses last.
+    ///
+    /// By default, the server wraps all its dispatchers with
+    /// [DefaultLayers], which process each request inside a tracing span, log
+    /// requests and responses on trace level, and log exceptions as errors.
+    /// Adding any layer replaces the default ones, also for dispatchers passed
+    /// to [`ServerBuilder::with_dispatcher()`], which are then used as passed;
+    /// add [DefaultLayers] first to keep them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tenderdash_abci::{
+    ///     layer::{DefaultLayers, TraceLayer},
+    ///     Application, ServerBuilder,
+    /// };
+    ///
+    /// struct MyApp {}
+    /// impl Application for MyApp {}
+    ///
+    /// let server = ServerBuilder::new(MyApp {}, "unix:///tmp/abci.sock")
+    ///     .layer(DefaultLayers)
+    ///     .layer(TraceLayer)
+    ///     .build()
+    ///     .expect("server failed");
+    /// ```
+    pub fn layer<L: Layer<App>>(self, layer: L) -> ServerBuilder<L::Dispatcher> {
+        ServerBuilder {
+            app: layer.layer(self.app),
+            bind_address: self.bind_address,
+            cancel: self.cancel,
+            server_runtime: self.server_runtime,
+            config: self.config,
+            listener: self.listener,
+            default_layers: false,
+        }
+    }
+
+    /// Wrap the main dispatcher and dispatchers of connection classes with
+    /// [DefaultLayers].
+    fn with_default_layers(mut self) -> ServerBuilder<Defaults<App>> {
+        for dispatcher in self.config.class_dispatchers.values_mut() {
+            *dispatcher = dispatcher.clone().with_default_layers();
+        }
+
+        self.layer(DefaultLayers)
+    }
+
+    /// Set maximum size of encoded request, in bytes.
+    ///
+    /// Applies to requests of [ConnectionClass]es without a limit set with
+    /// [`ServerBuilder::with_max_request_size_for()`], and to `Echo` and
+    /// `Flush` requests. When a client announces a larger request, the
+    /// connection is closed before the request is read, and
+    /// [Error::FrameTooLarge] is logged. By default, request size is not
+    /// limited.
+    pub fn with_max_request_size(mut self, max_size: usize) -> Self {
+        self.config.frame_limits.max_request_size = Some(max_size);
+        self
+    }
+
+    /// Set maximum size of encoded request of given [ConnectionClass], in
+    /// bytes.
+    ///
+    /// Overrides limit set with [`ServerBuilder::with_max_request_size()`] for
+    /// requests of this class. When that default limit is not set, requests
+    /// larger than the largest class limit are rejected regardless of their
+    /// class, as the class is not known until the request is received.
+    pub fn with_max_request_size_for(mut self, class: ConnectionClass, max_size: usize) -> Self {
+        self.config
+            .frame_limits
+            .class_request_size
+            .insert(class, max_size);
+        self
+    }
+
+    /// Set maximum size of encoded response, in bytes.
+    ///
+    /// Larger responses are not sent; the connection is closed instead. By
+    /// default, response size is not limited.
+    pub fn with_max_response_size(mut self, max_size: usize) -> Self {
+        self.config.frame_limits.max_response_size = Some(max_size);
+        self
+    }
+
+    /// Buffer responses and flush them to the connection in batches.
+    ///
+    /// By default, the connection is flushed after each response. With
+    /// batching enabled, responses are buffered until a `Flush` response is
+    /// written, `max_bytes` of responses are buffered, or the oldest buffered
+    /// response waits for `max_delay`. Tenderdash sends `Flush` requests at
+    /// batch boundaries, so this reduces the number of writes during bursts of
+    /// `CheckTx` requests.
+    pub fn with_response_batching(mut self, max_bytes: usize, max_delay: Duration) -> Self {
+        self.config.response_batching = Some(ResponseBatching {
+            max_bytes,
+            max_delay,
+        });
+        self
+    }
+
+    /// Count requests, responses and flushes using `stats`.
+    ///
+    /// Keep a clone of `stats` to read the counters while the server is
+    /// running.
+    pub fn with_io_stats(mut self, stats: IoStats) -> Self {
+        self.config.io_stats = stats;
+        self
+    }
+
+    /// Record [Metrics] of request processing.
+    ///
+    /// Keep a clone of `metrics` to render them with [`Metrics::render()`]
+    /// while the server is running.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    /// Serve [Metrics] over HTTP at `address`, like `127.0.0.1:9100`.
+    ///
+    /// Metrics are available at `/metrics` path until the server is
+    /// cancelled. When [`ServerBuilder::with_metrics()`] was not used, new
+    /// [Metrics] are created. Invalid address is reported as
+    /// [Error::Configuration] when the server is built.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_listener(mut self, address: &str) -> Self {
+        self.config.metrics_address = Some(address.to_string());
+        self.config.metrics.get_or_insert_with(Metrics::new);
+        self
+    }
+
+    /// Set maximum time of draining connections on shutdown.
+    ///
+    /// When the server is cancelled, it stops reading new requests, but
+    /// processes requests that were already received and sends their
+    /// responses before closing connections. Responses not sent within
+    /// `timeout` are lost, which is reported by [Error::DrainTimedOut] instead
+    /// of [Error::Cancelled]; [Error::Cancelled] itself carries no drain
+    /// status, so handle both variants to tell a clean shutdown from a timed
+    /// out one. Defaults to 10 seconds.
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.config.drain_timeout = timeout;
+        self
+    }
+
+    /// Set deadlines of processing requests of ABCI `method`, like
+    /// `"FinalizeBlock"`.
+    ///
+    /// Method names are the same as the `endpoint` field of spans created by
+    /// `tracing_span::span()`. When processing
+    /// exceeds the soft limit, a warning with request details is logged. When
+    /// it exceeds the hard limit, configured [DeadlineAction] is taken. Unknown
+    /// method names cause [Error::Configuration] when the server is built.
+    ///
+    /// Hard deadline does not interrupt the handler, and later requests wait
+    /// until it returns; see [Deadline] for details.
+    pub fn with_deadline(mut self, method: &str, deadline: Deadline) -> Self {
+        self.config.deadlines.insert(method.to_string(), deadline);
+        self
+    }
+
+    /// Catch panics of request dispatchers, and take `policy` action
+    /// afterwards.
+    ///
+    /// The client receives `ResponseException` with the ABCI method and
+    /// request identifier (connection ID and sequence number of the request),
+    /// and the panic is logged together with its backtrace. By default,
+    /// panics are not caught, and they stop the server.
+    pub fn with_panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.config.panic_policy = Some(policy);
+        self
+    }
+
+    /// Notify `hooks` when clients connect and disconnect.
+    ///
+    /// See [ConnectionHooks] for details. Pass an [Arc] also held by the
+    /// application to let it react to connection events.
+    pub fn with_connection_hooks<H: ConnectionHooks + 'static>(mut self, hooks: H) -> Self {
+        self.config.hooks = Some(Hooks(Arc::new(hooks)));
+        self
+    }
+
+    /// Set permissions of the Unix socket file, like `0o660`.
+    ///
+    /// By default, permissions are determined by the process umask. Not used
+    /// with Linux abstract sockets.
+    #[cfg(feature = "unix")]
+    pub fn with_unix_socket_mode(mut self, mode: u32) -> Self {
+        self.config.unix_socket_mode = Some(mode);
+        self
+    }
+
+    /// Set group ID that owns the Unix socket file.
+    ///
+    /// Together with [`ServerBuilder::with_unix_socket_mode()`], this allows
+    /// Tenderdash running as another user to connect to the socket. The
+    /// process must be a member of the group, or have appropriate privileges.
+    /// Not used with Linux abstract sockets.
+    #[cfg(feature = "unix")]
+    pub fn with_unix_socket_group(mut self, gid: u32) -> Self {
+        self.config.unix_socket_group = Some(gid);
+        self
+    }
+
+    /// Encrypt connections using TLS.
+    ///
+    /// Requires `tls://` bind address. Connections are accepted on the TCP
+    /// address and port given in the bind address, and TLS handshake is
+    /// performed before any ABCI request is read. Use
+    /// [`TlsConfig::from_pem()`] with `client_ca` to require client
+    /// certificate authentication.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls = Some(tls);
+        self
+    }
+
     /// Set tokio [Runtime](tokio::runtime::Runtime) to use.
     ///
     /// By default, current tokio runtime is used. If no runtime is active
@@ -211,6 +801,186 @@ impl<'a, App: RequestDispatcher + 'a> ServerBuilder<App> {
     }
 }
 
+#[cfg(feature = "tower")]
+impl<S> ServerBuilder<crate::service::ServiceDispatcher<S>> {
+    /// Create new server builder that processes requests with a
+    /// `tower::Service`.
+    ///
+    /// Works like [`ServerBuilder::new()`], with `service` wrapped in
+    /// [ServiceDispatcher](crate::service::ServiceDispatcher). Build the server
+    /// with [`ServerBuilder::build_async()`]. See [crate::service] for
+    /// details.
+    pub fn from_service(service: S, address: &str) -> Self {
+        Self::new(crate::service::ServiceDispatcher::new(service), address)
+    }
+}
+
+impl<'a, App: RequestDispatcher + 'a> ServerBuilder<App> {
+    /// Build and start the ABCI server.
+    ///
+    /// # Return
+    ///
+    /// Returns [`Server`] which provides [`Server::serve()`] method to
+    /// accept and process incoming connections.
+    pub fn build(self) -> Result<Box<dyn Server + 'a>, crate::Error> {
+        if self.default_layers {
+            self.with_default_layers().build_server()
+        } else {
+            self.build_server()
+        }
+    }
+
+    fn build_server(self) -> Result<Box<dyn Server + 'a>, crate::Error> {
+        let server: Box<dyn Server + 'a> = match self.bind()? {
+            #[cfg(feature = "tcp")]
+            BoundServer::Tcp(server) => Box::new(server),
+            #[cfg(feature = "unix")]
+            BoundServer::Unix(server) => Box::new(server),
+            #[cfg(feature = "grpc")]
+            BoundServer::Grpc(server) => Box::new(server),
+        };
+
+        Ok(server)
+    }
+}
+
+impl<App: AsyncRequestDispatcher + 'static> ServerBuilder<App> {
+    /// Build and start the ABCI server for an [AsyncRequestDispatcher], like
+    /// [AsyncApplication](crate::AsyncApplication).
+    ///
+    /// # Return
+    ///
+    /// Returns [`AsyncServer`] which provides async [`AsyncServer::serve()`]
+    /// method to accept and process incoming connections.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tenderdash_abci::ServePolicy;
+    ///
+    /// struct MyAbciApplication {}
+    /// impl tenderdash_abci::AsyncApplication for MyAbciApplication {}
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let app = MyAbciApplication {};
+    ///     let server = tenderdash_abci::ServerBuilder::new(app, "unix:///tmp/abci.sock")
+    ///         .build_async()
+    ///         .expect("server failed");
+    ///     server.serve(ServePolicy::new()).await.expect("server failed");
+    /// }
+    /// ```
+    pub fn build_async(self) -> Result<Box<dyn AsyncServer>, crate::Error> {
+        if self.default_layers {
+            self.with_default_layers().build_async_server()
+        } else {
+            self.build_async_server()
+        }
+    }
+
+    fn build_async_server(self) -> Result<Box<dyn AsyncServer>, crate::Error> {
+        let server: Box<dyn AsyncServer> = match self.bind()? {
+            #[cfg(feature = "tcp")]
+            BoundServer::Tcp(server) => Box::new(server),
+            #[cfg(feature = "unix")]
+            BoundServer::Unix(server) => Box::new(server),
+            #[cfg(feature = "grpc")]
+            BoundServer::Grpc(server) => Box::new(server),
+        };
+
+        Ok(server)
+    }
+}
+
+/// Start background tasks of the server, like signal handler or metrics
+/// listener, if configured.
+fn start_services(
+    config: &ServerConfig,
+    runtime: &ServerRuntime,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    #[cfg(feature = "signal")]
+    if config.handle_signals {
+        runtime.spawn(cancel_on_signal(cancel.clone()));
+    }
+    #[cfg(feature = "metrics")]
+    start_metrics_listener(config, runtime, cancel)?;
+    #[cfg(not(any(feature = "signal", feature = "metrics")))]
+    let _ = (config, runtime, cancel);
+
+    Ok(())
+}
+
+/// Cancel the server on SIGTERM or SIGINT.
+#[cfg(feature = "signal")]
+async fn cancel_on_signal(cancel: CancellationToken) {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            },
+            Err(error) => {
+                tracing::error!(?error, "cannot install SIGTERM handler");
+                futures::future::pending::<()>().await
+            },
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = futures::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => match result {
+            Ok(()) => tracing::info!("received SIGINT, shutting down"),
+            Err(error) => {
+                tracing::error!(?error, "cannot install SIGINT handler");
+                return;
+            },
+        },
+        _ = terminate => tracing::info!("received SIGTERM, shutting down"),
+        _ = cancel.cancelled() => return,
+    }
+    cancel.cancel();
+}
+
+/// Start HTTP listener that serves metrics, if configured.
+#[cfg(feature = "metrics")]
+fn start_metrics_listener(
+    config: &ServerConfig,
+    runtime: &ServerRuntime,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let (Some(address), Some(metrics)) = (&config.metrics_address, &config.metrics) else {
+        return Ok(());
+    };
+    let listener = std::net::TcpListener::bind(address.as_str()).map_err(|e| {
+        Error::Configuration(format!("cannot listen for metrics on {}: {}", address, e))
+    })?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    tracing::info!(address = ?listener.local_addr()?, "serving metrics");
+
+    let metrics = metrics.clone();
+    let cancel = cancel.clone();
+    runtime.spawn(async move {
+        if let Err(error) = metrics.serve(listener, cancel).await {
+            tracing::error!(?error, "metrics listener failed");
+        }
+    });
+    Ok(())
+}
+
+/// Server bound to a listener, before it is converted into [Server] or
+/// [AsyncServer].
+enum BoundServer<App> {
+    #[cfg(feature = "tcp")]
+    Tcp(GenericServer<App, TcpListener>),
+    #[cfg(feature = "unix")]
+    Unix(GenericServer<App, UnixListener>),
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcServer<App>),
+}
+
 /// Server runtime that must be alive for the whole lifespan of the server
 pub struct ServerRuntime {
     /// Runtime stored here to ensure it is never dropped
@@ -270,56 +1040,3 @@ where
 {
     ServerBuilder::new(app, bind_address.as_ref()).build()
 }
-#[cfg(feature = "tcp")]
-fn parse_tcp_uri(uri: url::Url) -> SocketAddr {
-    let host = uri.host_str().unwrap();
-    // remove '[' and ']' from ipv6 address, as per https://github.com/servo/rust-url/issues/770
-    let host = host.replace(['[', ']'], "");
-    let port = uri.port().expect("missing tcp port");
-
-    let ip = IpAddr::from_str(host.as_str())
-        .unwrap_or_else(|e| panic!("invalid listen address {}: {}", host, e));
-    match ip {
-        IpAddr::V4(a) => SocketAddr::V4(SocketAddrV4::new(a, port)),
-        IpAddr::V6(a) => SocketAddr::V6(SocketAddrV6::new(a, port, 0, 0)),
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::server::parse_tcp_uri;
-
-    #[test]
-    fn test_parse_tcp_uri() {
-        struct TestCase<'a> {
-            uri: &'a str,
-            expect: &'a str,
-        }
-
-        let test_cases = [
-            TestCase {
-                uri: "tcp://0.0.0.0:1234",
-                expect: "0.0.0.0:1234",
-            },
-            TestCase {
-                uri: "tcp://[::]:1234",
-                expect: "[::]:1234",
-            },
-            TestCase {
-                uri: "tcp://[::1]:1234",
-                expect: "[::1]:1234",
-            },
-            TestCase {
-                uri: "tcp://[::ffff:ac11:1]:5678",
-                expect: "[::ffff:172.17.0.1]:5678",
-            },
-        ];
-
-        for test_case in test_cases {
-            let uri = url::Url::parse(test_case.uri).unwrap();
-
-            let addr = parse_tcp_uri(uri);
-            assert_eq!(test_case.expect, addr.to_string());
-        }
-    }
-}
@@ -0,0 +1,262 @@
+//! Address the ABCI server listens on.
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use crate::Error;
+
+/// Address the ABCI server listens on.
+///
+/// Parsed from URI, like `tcp://0.0.0.0:26658`, `tls://[::1]:26658`,
+/// `tcp://localhost:26658`, `grpc://0.0.0.0:26658`, `unix:///var/run/abci.sock`
+/// or, on Linux, `unix://@abci` for abstract Unix sockets.
+///
+/// # Examples
+///
+/// ```
+/// use tenderdash_abci::BindAddress;
+///
+/// let address: BindAddress = "tcp://[::1]:26658".parse().expect("invalid address");
+/// assert_eq!(
+///     address,
+///     BindAddress::Tcp {
+///         host: "::1".to_string(),
+///         port: 26658
+///     }
+/// );
+///
+/// assert!("tcp://localhost".parse::<BindAddress>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BindAddress {
+    /// TCP address; `host` is an IP address or a DNS name.
+    Tcp { host: String, port: u16 },
+    /// TLS-encrypted TCP address; `host` is an IP address or a DNS name.
+    Tls { host: String, port: u16 },
+    /// TCP address served using gRPC instead of the socket protocol; `host` is
+    /// an IP address or a DNS name.
+    Grpc { host: String, port: u16 },
+    /// Path to Unix socket file.
+    Unix(PathBuf),
+    /// Name of Linux abstract Unix socket, without leading `@`.
+    AbstractUnix(String),
+}
+
+impl BindAddress {
+    /// Resolve TCP host and port into socket addresses.
+    ///
+    /// Returns error if this is not a TCP, TLS or gRPC address, or the host
+    /// cannot be resolved.
+    pub fn socket_addrs(&self) -> Result<Vec<SocketAddr>, Error> {
+        let (host, port) = match self {
+            Self::Tcp { host, port } | Self::Tls { host, port } | Self::Grpc { host, port } => {
+                (host, *port)
+            },
+            _ => {
+                return Err(Error::Configuration(format!(
+                    "{} is not a tcp address",
+                    self
+                )))
+            },
+        };
+
+        if let Ok(ip) = IpAddr::from_str(host) {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let addrs: Vec<SocketAddr> = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| Error::Configuration(format!("cannot resolve {}: {}", host, e)))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(Error::Configuration(format!(
+                "{} does not resolve to any address",
+                host
+            )));
+        }
+
+        Ok(addrs)
+    }
+
+    fn parse_tcp(address: &str) -> Result<(String, u16), Error> {
+        let url = url::Url::parse(address)
+            .map_err(|e| Error::Configuration(format!("invalid address {}: {}", address, e)))?;
+
+        let host = match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(ip)) => ip.to_string(),
+            Some(url::Host::Ipv6(ip)) => ip.to_string(),
+            None => {
+                return Err(Error::Configuration(format!(
+                    "missing host in address {}",
+                    address
+                )))
+            },
+        };
+        let port = url
+            .port()
+            .ok_or_else(|| Error::Configuration(format!("missing port in address {}", address)))?;
+
+        Ok((host, port))
+    }
+}
+
+impl FromStr for BindAddress {
+    type Err = Error;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = address.split_once("://").ok_or_else(|| {
+            Error::Configuration(format!("address {} must be in URI format", address))
+        })?;
+
+        match scheme {
+            "tcp" => Self::parse_tcp(address).map(|(host, port)| Self::Tcp { host, port }),
+            "tls" => Self::parse_tcp(address).map(|(host, port)| Self::Tls { host, port }),
+            "grpc" => Self::parse_tcp(address).map(|(host, port)| Self::Grpc { host, port }),
+            "unix" => match rest.strip_prefix('@') {
+                Some(name) if !name.is_empty() => Ok(Self::AbstractUnix(name.to_string())),
+                None if !rest.is_empty() => Ok(Self::Unix(PathBuf::from(rest))),
+                _ => Err(Error::Configuration(format!(
+                    "missing socket path in address {}",
+                    address
+                ))),
+            },
+            _ => Err(Error::Configuration(format!(
+                "address {} must use tcp://, tls://, grpc:// or unix:// scheme",
+                address
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&str> for BindAddress {
+    type Error = Error;
+
+    fn try_from(address: &str) -> Result<Self, Self::Error> {
+        address.parse()
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let host_port = |host: &str, port: &u16| {
+            if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            }
+        };
+
+        match self {
+            Self::Tcp { host, port } => write!(f, "tcp://{}", host_port(host, port)),
+            Self::Tls { host, port } => write!(f, "tls://{}", host_port(host, port)),
+            Self::Grpc { host, port } => write!(f, "grpc://{}", host_port(host, port)),
+            Self::Unix(path) => write!(f, "unix://{}", path.display()),
+            Self::AbstractUnix(name) => write!(f, "unix://@{}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::BindAddress;
+    use crate::Error;
+
+    #[test]
+    fn test_parse_tcp_address() {
+        struct TestCase<'a> {
+            uri: &'a str,
+            expect: &'a str,
+        }
+
+        let test_cases = [
+            TestCase {
+                uri: "tcp://0.0.0.0:1234",
+                expect: "0.0.0.0:1234",
+            },
+            TestCase {
+                uri: "tcp://[::]:1234",
+                expect: "[::]:1234",
+            },
+            TestCase {
+                uri: "tcp://[::1]:1234",
+                expect: "[::1]:1234",
+            },
+            TestCase {
+                uri: "tcp://[::ffff:ac11:1]:5678",
+                expect: "[::ffff:172.17.0.1]:5678",
+            },
+            TestCase {
+                uri: "tls://127.0.0.1:5678",
+                expect: "127.0.0.1:5678",
+            },
+            TestCase {
+                uri: "grpc://127.0.0.1:26658",
+                expect: "127.0.0.1:26658",
+            },
+        ];
+
+        for test_case in test_cases {
+            let address: BindAddress = test_case.uri.parse().unwrap();
+
+            let addrs = address.socket_addrs().unwrap();
+            assert_eq!(test_case.expect, addrs[0].to_string());
+            assert_eq!(address, address.to_string().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_hostname() {
+        let address = BindAddress::try_from("tcp://localhost:26658").unwrap();
+        assert_eq!(
+            address,
+            BindAddress::Tcp {
+                host: "localhost".to_string(),
+                port: 26658
+            }
+        );
+        assert!(!address.socket_addrs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_unix_address() {
+        assert_eq!(
+            BindAddress::try_from("unix:///var/run/abci.sock").unwrap(),
+            BindAddress::Unix(PathBuf::from("/var/run/abci.sock"))
+        );
+        assert_eq!(
+            BindAddress::try_from("unix://@abci").unwrap(),
+            BindAddress::AbstractUnix("abci".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_address() {
+        let invalid = [
+            "",
+            "/tmp/abci.sock",
+            "http://127.0.0.1:1234",
+            "tcp://127.0.0.1",
+            "tcp://:1234",
+            "tcp://[::1:1234",
+            "tcp://127.0.0.1:99999",
+            "unix://",
+            "unix://@",
+        ];
+
+        for uri in invalid {
+            let result = uri.parse::<BindAddress>();
+            assert!(
+                matches!(result, Err(Error::Configuration(_))),
+                "{}: {:?}",
+                uri,
+                result
+            );
+        }
+    }
+}
@@ -4,188 +4,696 @@
 //!
 //! [tsp]: https://github.com/tendermint/tendermint/blob/v0.34.x/spec/abci/client-server.md#tsp
 
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use futures::{SinkExt, StreamExt};
-use proto::abci::{Request, Response};
+use proto::abci::{response, Request, Response};
 use tenderdash_proto::prost::{
     encoding::{decode_varint, encode_varint},
-    Message,
+    length_delimiter_len, Message,
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::{
         mpsc::{self, Receiver, Sender},
-        Mutex,
+        Mutex, Semaphore,
     },
+    task::JoinSet,
+    time::{sleep_until, Instant},
 };
 use tokio_util::{
     codec::{Decoder, Encoder, Framed},
     net::Listener,
 };
+use tracing::Instrument;
 
-use super::ServerRuntime;
-use crate::{proto, CancellationToken, Error};
+#[cfg(feature = "metrics")]
+use super::metrics::{Metrics, Queue};
+use super::{ConnectionClass, DisconnectReason, IoStats, ServerConfig, ServerRuntime};
+use crate::{proto, CancellationToken, Error, RequestContext};
 
 /// The maximum number of bytes we expect in a varint. We use this to check if
 /// we're encountering a decoding error for a varint.
 pub const MAX_VARINT_LENGTH: usize = 16;
 
+/// Source of unique connection identifiers.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Allocate identifier of a new connection.
+pub(crate) fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Identifier of the connection that sent the request being dispatched, or
+/// `None` outside of the server.
+///
+/// Call it from a request dispatcher, like
+/// [Application](crate::Application), to relate requests to connection
+/// events reported to [ConnectionHooks](super::ConnectionHooks).
+pub fn current_connection_id() -> Option<u64> {
+    RequestContext::current().and_then(|context| context.connection_id())
+}
+
+/// Bidirectional byte stream of a client connection.
+trait Io: AsyncRead + AsyncWrite + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Send> Io for T {}
+
+/// Connection stream, possibly wrapped in TLS.
+type Stream = Pin<Box<dyn Io>>;
+
+/// Channel used to send requests to a dispatcher.
+pub(crate) type RequestSender = Sender<(Request, Responder)>;
+
+/// Handle used to send a response back to the connection that sent the
+/// request.
+///
+/// Responses can be sent in any order; the connection reorders them using
+/// sequence number of the request.
+pub struct Responder {
+    connection_id: u64,
+    sequence: u64,
+    received_at: std::time::Instant,
+    response_tx: Sender<(u64, Response)>,
+}
+
+impl Responder {
+    pub(crate) fn new(connection_id: u64, response_tx: Sender<(u64, Response)>) -> Self {
+        Self {
+            connection_id,
+            sequence: 0,
+            received_at: std::time::Instant::now(),
+            response_tx,
+        }
+    }
+
+    /// Create responder for the request with given sequence number, received
+    /// now.
+    fn with_sequence(&self, sequence: u64) -> Self {
+        Self {
+            connection_id: self.connection_id,
+            sequence,
+            received_at: std::time::Instant::now(),
+            response_tx: self.response_tx.clone(),
+        }
+    }
+
+    /// Create another responder for the same request.
+    pub(crate) fn duplicate(&self) -> Self {
+        Self {
+            connection_id: self.connection_id,
+            sequence: self.sequence,
+            received_at: self.received_at,
+            response_tx: self.response_tx.clone(),
+        }
+    }
+
+    /// Identifier of the connection that sent the request.
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    /// Time when the request was received.
+    pub(crate) fn received_at(&self) -> std::time::Instant {
+        self.received_at
+    }
+
+    /// Send response to the client; blocks until the response is queued.
+    pub fn send(self, value: Response) -> Result<(), Error> {
+        self.response_tx
+            .blocking_send((self.sequence, value))
+            .map_err(|e| Error::Async(e.to_string()))
+    }
+
+    /// Async version of [Responder::send()], to be used inside tokio runtime.
+    pub async fn send_async(self, value: Response) -> Result<(), Error> {
+        self.response_tx
+            .send((self.sequence, value))
+            .await
+            .map_err(|e| Error::Async(e.to_string()))
+    }
+}
+
+/// Selects channel of the dispatcher that should process a request.
+///
+/// Requests of [ConnectionClass]es with a dedicated dispatcher are sent to that
+/// dispatcher; all other requests go to the default one.
+#[derive(Clone)]
+pub(crate) struct Router {
+    default: RequestSender,
+    classes: BTreeMap<ConnectionClass, RequestSender>,
+}
+
+impl Router {
+    pub(crate) fn new(
+        default: RequestSender,
+        classes: BTreeMap<ConnectionClass, RequestSender>,
+    ) -> Self {
+        Self { default, classes }
+    }
+
+    /// Return channel that should receive `request`.
+    ///
+    /// When `request` is `None`, default channel is returned.
+    fn route(&self, request: Option<&Request>) -> &RequestSender {
+        request
+            .and_then(|request| request.value.as_ref())
+            .and_then(ConnectionClass::of)
+            .and_then(|class| self.classes.get(&class))
+            .unwrap_or(&self.default)
+    }
+}
+
 pub struct Codec {
-    request_rx: Receiver<Request>,
-    response_tx: Sender<Response>,
+    request_rx: Receiver<(Request, Responder)>,
+    /// Cleared when some connection was not drained cleanly on shutdown.
+    drained: Arc<AtomicBool>,
 }
 
-impl<'a> Codec {
+impl Codec {
     pub(crate) fn new<L>(
         listener: Arc<Mutex<L>>,
         cancel: CancellationToken,
         runtime: &ServerRuntime,
+        config: &ServerConfig,
+        classes: BTreeMap<ConnectionClass, RequestSender>,
     ) -> Self
     where
         L: Listener + Send + Sync + 'static,
         L::Addr: Send + Debug,
         L::Io: Send,
     {
-        let (request_tx, request_rx) = mpsc::channel::<proto::abci::Request>(1);
-        let (response_tx, response_rx) = mpsc::channel::<proto::abci::Response>(1);
+        let (request_tx, request_rx) = mpsc::channel::<(Request, Responder)>(1);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &config.metrics {
+            metrics.track_queue(Queue::Request, &request_tx);
+        }
+        let router = Router::new(request_tx, classes);
+        let drained = Arc::new(AtomicBool::new(true));
 
-        runtime
-            .handle
-            .spawn(Self::worker(listener, request_tx, response_rx, cancel));
+        runtime.handle.spawn(Self::worker(
+            listener,
+            router,
+            cancel,
+            config.clone(),
+            Arc::clone(&drained),
+        ));
 
         Self {
             request_rx,
-            response_tx,
+            drained,
         }
     }
 
-    /// Worker that bridges data between async streams and sync processing code.
+    /// Worker that accepts incoming connections and bridges data between async
+    /// streams and sync processing code.
+    ///
+    /// When `max_connections` is 1, only one connection is accepted and the
+    /// worker finishes when that connection is closed. Otherwise, connections
+    /// are accepted until the worker is cancelled, and up to
+    /// `max_connections` of them are served at once.
+    ///
+    /// When TLS is configured, TLS handshake is performed on each accepted
+    /// connection before it is passed to the codec.
+    ///
+    /// When the worker is cancelled, it stops accepting connections and waits
+    /// until open connections are drained. `drained` is cleared if any of them
+    /// was not drained cleanly.
     ///
     /// ## Error handling
     ///
-    /// Any error will cause disconnect
+    /// Any error will cause disconnect of the affected connection. Error while
+    /// accepting new connection stops the worker.
     async fn worker<L>(
         listener: Arc<Mutex<L>>,
-        request_tx: Sender<proto::abci::Request>,
-        response_rx: Receiver<proto::abci::Response>,
+        router: Router,
         cancel: CancellationToken,
+        config: ServerConfig,
+        drained: Arc<AtomicBool>,
     ) where
         L: Listener + Send + Sync,
         L::Addr: Debug,
+        L::Io: Send + 'static,
     {
         let mut listener = listener.lock().await;
-        tracing::trace!("listening for new connection");
-
-        let (stream, address) = tokio::select! {
-            conn = listener.accept() => match conn {
-                Ok(r) => r,
-                Err(error) => {
-                    tracing::error!(?error, "cannot accept connection");
-                    cancel.cancel();
-                    return;
+        let max_connections = config.max_connections;
+        let slots = Arc::new(Semaphore::new(max_connections));
+        let mut connections = JoinSet::new();
+
+        loop {
+            let permit = tokio::select! {
+                permit = Arc::clone(&slots).acquire_owned() => permit.expect("connection semaphore closed"),
+                _ = cancel.cancelled() => break,
+            };
+
+            tracing::trace!("listening for new connection");
+            let (stream, address) = tokio::select! {
+                conn = listener.accept() => match conn {
+                    Ok(r) => r,
+                    Err(error) => {
+                        tracing::error!(?error, "cannot accept connection");
+                        cancel.cancel();
+                        break;
+                    },
                 },
-            },
-            _ = cancel.cancelled() => return,
-        };
+                _ = cancel.cancelled() => break,
+            };
+
+            let connection_id = next_connection_id();
+            tracing::info!(?address, connection_id, "accepted connection");
+
+            let peer_addr = format!("{:?}", address);
+            let router = router.clone();
+            let cancel = cancel.clone();
+            let config = config.clone();
+            let drained = Arc::clone(&drained);
+            let connection = async move {
+                #[cfg(feature = "metrics")]
+                let _connection = config.metrics.as_ref().map(Metrics::connection);
+                let stream = match Self::wrap_stream(stream, &config, &cancel).await {
+                    Ok(stream) => stream,
+                    Err(Error::Cancelled()) => {
+                        tracing::debug!("server cancelled before connection was established");
+                        return;
+                    },
+                    Err(error) => {
+                        tracing::error!(?error, "cannot establish connection");
+                        return;
+                    },
+                };
+                let coder = Coder::new(config.frame_limits.clone());
+                #[cfg(feature = "metrics")]
+                let coder = coder.with_metrics(config.metrics.clone());
+                let codec = Framed::new(stream, coder);
+                let (response_tx, response_rx) = mpsc::channel::<(u64, Response)>(1);
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &config.metrics {
+                    metrics.track_queue(Queue::Response, &response_tx);
+                }
+
+                let hooks = config.hooks.clone();
+                if let Some(hooks) = &hooks {
+                    hooks.0.on_connect(&peer_addr, connection_id);
+                }
+
+                let reason = Self::process_worker_queues(
+                    codec,
+                    router,
+                    Responder::new(connection_id, response_tx),
+                    response_rx,
+                    ResponseWriter::new(config.response_batching, config.io_stats),
+                    cancel,
+                    config.drain_timeout,
+                )
+                .await;
+                if let DisconnectReason::Shutdown { drained: false } = reason {
+                    drained.store(false, Ordering::SeqCst);
+                }
+                if let Some(hooks) = &hooks {
+                    if let DisconnectReason::DecodeError(error) = &reason {
+                        hooks.0.on_decode_error(connection_id, error);
+                    }
+                    hooks.0.on_disconnect(connection_id, &reason);
+                }
+            }
+            .instrument(tracing::info_span!("connection", connection_id));
+
+            if max_connections == 1 {
+                connection.await;
+                break;
+            }
+
+            connections.spawn(async move {
+                connection.await;
+                drop(permit);
+            });
+        }
 
-        tracing::info!(?address, "accepted connection");
+        drop(listener);
+        while connections.join_next().await.is_some() {}
+    }
 
-        let stream = Box::pin(stream);
-        let codec = Framed::new(stream, Coder {});
+    /// Prepare accepted stream to be used by the codec.
+    ///
+    /// Performs TLS handshake if TLS is configured; the handshake is aborted
+    /// with [Error::Cancelled] when `cancel` is cancelled.
+    async fn wrap_stream<S>(
+        stream: S,
+        config: &ServerConfig,
+        cancel: &CancellationToken,
+    ) -> Result<Stream, Error>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &config.tls {
+            return tokio::select! {
+                stream = tls.accept(Box::pin(stream)) => Ok(Box::pin(stream?)),
+                _ = cancel.cancelled() => Err(Error::Cancelled()),
+            };
+        }
+        #[cfg(not(feature = "tls"))]
+        let _ = (config, cancel);
 
-        Self::process_worker_queues(codec, request_tx, response_rx, cancel).await;
+        Ok(Box::pin(stream))
     }
+
+    /// Bridge requests and responses between the connection and dispatchers.
+    ///
+    /// When `cancel` is cancelled, the connection is drained: no new requests
+    /// are read, responses to requests already forwarded for processing are
+    /// written and flushed, and then the connection is closed. Draining takes
+    /// at most `drain_timeout`.
+    ///
+    /// Returns reason of closing the connection;
+    /// [DisconnectReason::Shutdown] reports whether responses were lost
+    /// because the drain did not finish cleanly.
     async fn process_worker_queues<L: AsyncRead + AsyncWrite + Unpin>(
         mut codec: Framed<L, Coder>,
-        request_tx: Sender<proto::abci::Request>,
-        mut response_rx: Receiver<proto::abci::Response>,
+        router: Router,
+        responder: Responder,
+        mut response_rx: Receiver<(u64, Response)>,
+        mut writer: ResponseWriter,
         cancel: CancellationToken,
-    ) {
+        drain_timeout: Duration,
+    ) -> DisconnectReason {
+        writer.configure(&mut codec);
+
+        // Request that was read from the connection but not forwarded for
+        // processing yet.
+        let mut pending: Option<Request> = None;
+        // Requests can be processed by different dispatchers, so responses can
+        // arrive out of order. We use sequence numbers to send them in the
+        // order of requests.
+        let mut next_request_seq: u64 = 0;
+        let mut next_response_seq: u64 = 0;
+        let mut out_of_order = BTreeMap::<u64, Response>::new();
+        // Time when draining must be finished; `None` if not draining.
+        let mut drain_deadline: Option<Instant> = None;
+
         loop {
+            if drain_deadline.is_some() && next_response_seq == next_request_seq {
+                return DisconnectReason::Shutdown {
+                    drained: Self::close(codec, writer).await,
+                };
+            }
+
             tokio::select! {
-                // Only read next message if we have capacity in request_tx to process it.
+                // Only read next message when the previous one was forwarded for processing.
                 // Otherwise, we might block the codec worker on request_tx.send() and never
                 // process the next message from the response_rx stream.
-                request = codec.next(), if request_tx.capacity() > 0 => match request {
+                request = codec.next(), if pending.is_none() && drain_deadline.is_none() => match request {
                     Some(Ok(i)) => {
-                        if let Err(error) = request_tx.try_send(i) {
-                            tracing::error!(?error, "unable to forward request for processing");
-                            cancel.cancel();
-                        }
+                        writer.stats.record_request();
+                        pending = Some(i);
                     },
                     Some(Err(error)) => {
                         tracing::error!(?error, "unable to parse request");
-                        cancel.cancel();
+                        return DisconnectReason::DecodeError(error);
                     },
                     None => {
                         tracing::warn!("client connection terminated");
-                        cancel.cancel();
+                        return DisconnectReason::Closed;
+                    },
+                },
+                permit = router.route(pending.as_ref()).reserve(), if pending.is_some() && drain_deadline.is_none() => match permit {
+                    Ok(permit) => {
+                        let request = pending.take().expect("pending request must be set");
+                        permit.send((request, responder.with_sequence(next_request_seq)));
+                        next_request_seq += 1;
+                    },
+                    Err(error) => {
+                        tracing::error!(?error, "unable to forward request for processing");
+                        return DisconnectReason::DispatcherStopped;
                     },
                 },
                 response = response_rx.recv() => match response{
-                    Some(msg) => {
-                        if let Err(error) =   codec.send(msg).await {
-                            tracing::error!(?error, "unable to send response to tenderdash");
-                            cancel.cancel();
+                    Some((seq, msg)) => {
+                        out_of_order.insert(seq, msg);
+                        while let Some(msg) = out_of_order.remove(&next_response_seq) {
+                            next_response_seq += 1;
+                            if let Err(error) = writer.write(&mut codec, msg).await {
+                                tracing::error!(?error, "unable to send response to tenderdash");
+                                return Self::write_failed(error, drain_deadline);
+                            }
                         }
                     },
                     None => {
                         tracing::warn!("client connection terminated");
-                        cancel.cancel();
+                        return match drain_deadline {
+                            Some(_) => DisconnectReason::Shutdown { drained: false },
+                            None => DisconnectReason::Closed,
+                        };
                     }
                 },
-                _ = cancel.cancelled() => {
-                    tracing::debug!("codec worker shutting down");
-                    return; // stop processing
+                _ = sleep_until(writer.flush_at.unwrap_or_else(Instant::now)), if writer.flush_at.is_some() => {
+                    if let Err(error) = writer.flush(&mut codec).await {
+                        tracing::error!(?error, "unable to send response to tenderdash");
+                        return Self::write_failed(error, drain_deadline);
+                    }
+                },
+                _ = cancel.cancelled(), if drain_deadline.is_none() => {
+                    tracing::debug!(
+                        in_flight = next_request_seq - next_response_seq,
+                        "codec worker shutting down, draining connection"
+                    );
+                    // request not forwarded yet will not be processed
+                    pending = None;
+                    drain_deadline = Some(Instant::now() + drain_timeout);
+                },
+                _ = sleep_until(drain_deadline.unwrap_or_else(Instant::now)), if drain_deadline.is_some() => {
+                    tracing::warn!(
+                        lost = next_request_seq - next_response_seq,
+                        ?drain_timeout,
+                        "drain timed out, closing connection"
+                    );
+                    return DisconnectReason::Shutdown { drained: false };
                 }
             }
         }
     }
 
-    pub fn next(&mut self) -> Option<Request> {
+    /// Reason of closing the connection after writing a response failed.
+    ///
+    /// Responses are lost when this happens while draining.
+    fn write_failed(error: Error, drain_deadline: Option<Instant>) -> DisconnectReason {
+        match drain_deadline {
+            Some(_) => DisconnectReason::Shutdown { drained: false },
+            None => DisconnectReason::WriteError(error),
+        }
+    }
+
+    /// Flush buffered responses and close the connection.
+    ///
+    /// Returns `false` if buffered responses could not be written.
+    async fn close<L: AsyncRead + AsyncWrite + Unpin>(
+        mut codec: Framed<L, Coder>,
+        mut writer: ResponseWriter,
+    ) -> bool {
+        if writer.flush_at.is_some() {
+            if let Err(error) = writer.flush(&mut codec).await {
+                tracing::error!(?error, "unable to send response to tenderdash");
+                return false;
+            }
+        }
+        if let Err(error) = codec.close().await {
+            tracing::debug!(?error, "error when closing connection");
+        }
+        tracing::debug!("connection drained");
+        true
+    }
+
+    /// Receive next request, together with [Responder] that should be used to
+    /// send the response.
+    pub fn next(&mut self) -> Option<(Request, Responder)> {
         self.request_rx.blocking_recv()
     }
 
-    pub fn send(&self, value: Response) -> Result<(), Error> {
-        self.response_tx
-            .blocking_send(value)
-            .map_err(|e| Error::Async(e.to_string()))
+    /// Async version of [Codec::next()], to be used inside tokio runtime.
+    pub async fn next_async(&mut self) -> Option<(Request, Responder)> {
+        self.request_rx.recv().await
+    }
+
+    /// Whether all connections were drained cleanly on shutdown.
+    ///
+    /// Meaningful once [Codec::next()] returned `None`.
+    pub fn drained(&self) -> bool {
+        self.drained.load(Ordering::SeqCst)
     }
 }
 
-pub struct Coder;
+/// Thresholds of batched response writing.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseBatching {
+    /// Flush when this many bytes of responses are buffered.
+    pub(crate) max_bytes: usize,
+    /// Flush when the oldest buffered response waits this long.
+    pub(crate) max_delay: Duration,
+}
+
+/// Writes responses to the connection and decides when to flush it.
+///
+/// Without batching, the connection is flushed after each response. With
+/// batching, responses are buffered until a `Flush` response is written, or
+/// one of [ResponseBatching] thresholds is reached.
+pub(crate) struct ResponseWriter {
+    batching: Option<ResponseBatching>,
+    stats: IoStats,
+    /// Time when buffered responses must be flushed; `None` if nothing is
+    /// buffered.
+    flush_at: Option<Instant>,
+}
+
+impl ResponseWriter {
+    pub(crate) fn new(batching: Option<ResponseBatching>, stats: IoStats) -> Self {
+        Self {
+            batching,
+            stats,
+            flush_at: None,
+        }
+    }
+
+    /// Adjust `codec` so that it does not flush buffered responses on its own
+    /// before batching thresholds are reached.
+    fn configure<L: AsyncWrite>(&self, codec: &mut Framed<L, Coder>) {
+        if let Some(batching) = &self.batching {
+            codec.set_backpressure_boundary(batching.max_bytes);
+        }
+    }
+
+    /// Write response to the connection, flushing it if needed.
+    async fn write<L: AsyncWrite + Unpin>(
+        &mut self,
+        codec: &mut Framed<L, Coder>,
+        response: Response,
+    ) -> Result<(), Error> {
+        let is_flush = matches!(response.value, Some(response::Value::Flush(_)));
+        let encoded_len = response.encoded_len();
+
+        codec.feed(response).await?;
+        self.stats
+            .record_response(length_delimiter_len(encoded_len) + encoded_len);
+
+        match &self.batching {
+            Some(batching) if !is_flush && codec.write_buffer().len() < batching.max_bytes => {
+                if self.flush_at.is_none() {
+                    self.flush_at = Some(Instant::now() + batching.max_delay);
+                }
+                Ok(())
+            },
+            _ => self.flush(codec).await,
+        }
+    }
+
+    /// Flush buffered responses.
+    async fn flush<L: AsyncWrite + Unpin>(
+        &mut self,
+        codec: &mut Framed<L, Coder>,
+    ) -> Result<(), Error> {
+        self.flush_at = None;
+        codec.flush().await?;
+        self.stats.record_flush();
+        Ok(())
+    }
+}
+
+/// Maximum sizes of encoded requests and responses.
+///
+/// `None` means no limit.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameLimits {
+    /// Limit of requests that have no class-specific limit.
+    pub(crate) max_request_size: Option<usize>,
+    /// Limits of requests of given connection classes.
+    pub(crate) class_request_size: BTreeMap<ConnectionClass, usize>,
+    /// Limit of responses.
+    pub(crate) max_response_size: Option<usize>,
+}
+
+impl FrameLimits {
+    /// Largest request that can be accepted, regardless of its class.
+    ///
+    /// Without default limit, it is the largest class limit, so that a
+    /// client cannot make the server buffer arbitrarily large frames.
+    pub(crate) fn max_frame_size(&self) -> Option<usize> {
+        let max_class = self.class_request_size.values().max().copied();
+        match (self.max_request_size, max_class) {
+            (Some(default), Some(class)) => Some(default.max(class)),
+            (default, class) => default.or(class),
+        }
+    }
+
+    /// Limit that applies to the request.
+    pub(crate) fn request_limit(&self, request: &Request) -> Option<usize> {
+        request
+            .value
+            .as_ref()
+            .and_then(ConnectionClass::of)
+            .and_then(|class| self.class_request_size.get(&class).copied())
+            .or(self.max_request_size)
+    }
+}
+
+/// Encoder and decoder of length-delimited ABCI messages.
+pub struct Coder {
+    limits: FrameLimits,
+    /// Metrics that record sizes of frames.
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl Coder {
+    pub(crate) fn new(limits: FrameLimits) -> Self {
+        Self {
+            limits,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Record sizes of frames in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_metrics(mut self, metrics: Option<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+}
 
 impl Decoder for Coder {
     type Error = Error;
     type Item = proto::abci::Request;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let src_len = src.len();
-
-        let mut tmp = src.clone().freeze();
-        let encoded_len = match decode_varint(&mut tmp) {
-            Ok(len) => len,
-            // We've potentially only received a partial length delimiter
-            Err(_) if src_len <= MAX_VARINT_LENGTH => return Ok(None),
-            Err(e) => return Err(e.into()),
+        let Some((_delim_len, frame)) = decode_frame(src, self.limits.max_frame_size())? else {
+            return Ok(None);
         };
-        let remaining = tmp.remaining() as u64;
-        if remaining < encoded_len {
-            // We don't have enough data yet to decode the entire message
-            Ok(None)
-        } else {
-            let delim_len = src_len - tmp.remaining();
-            // We only advance the source buffer once we're sure we have enough
-            // data to try to decode the result.
-            src.advance(delim_len + (encoded_len as usize));
-
-            let mut result_bytes = BytesMut::from(tmp.split_to(encoded_len as usize).as_ref());
-            let res = proto::abci::Request::decode(&mut result_bytes)?;
-
-            Ok(Some(res))
+        let encoded_len = frame.len();
+        let request = proto::abci::Request::decode(frame)?;
+
+        if let Some(limit) = self.limits.request_limit(&request) {
+            if encoded_len > limit {
+                return Err(Error::FrameTooLarge {
+                    size: encoded_len,
+                    limit,
+                });
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_request_frame(_delim_len + encoded_len);
         }
+        Ok(Some(request))
     }
 }
 
@@ -197,22 +705,90 @@ impl Encoder<proto::abci::Response> for Coder {
         message: proto::abci::Response,
         dst: &mut BytesMut,
     ) -> Result<(), Self::Error> {
-        let mut buf = BytesMut::new();
-        message.encode(&mut buf)?;
+        let _frame_len = encode_frame(&message, self.limits.max_response_size, dst)?;
 
-        let buf = buf.freeze();
-        encode_varint(buf.len() as u64, dst);
-        dst.put(buf);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_response_frame(_frame_len);
+        }
         Ok(())
     }
 }
 
+/// Take next length-delimited frame from `src`.
+///
+/// Returns length of the delimiter and the message, or `None` when `src` does
+/// not contain the whole frame yet. Fails as soon as the delimiter is decoded
+/// when the message is larger than `limit`, before it is buffered.
+pub(crate) fn decode_frame(
+    src: &mut BytesMut,
+    limit: Option<usize>,
+) -> Result<Option<(usize, Bytes)>, Error> {
+    let mut tmp: &[u8] = src.as_ref();
+    let encoded_len = match decode_varint(&mut tmp) {
+        Ok(len) => len,
+        // We've potentially only received a partial length delimiter
+        Err(_) if src.len() <= MAX_VARINT_LENGTH => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let delim_len = src.len() - tmp.len();
+    let encoded_len = usize::try_from(encoded_len).unwrap_or(usize::MAX);
+
+    // Fail fast, before buffering the whole message
+    if let Some(limit) = limit {
+        if encoded_len > limit {
+            return Err(Error::FrameTooLarge {
+                size: encoded_len,
+                limit,
+            });
+        }
+    }
+
+    if tmp.len() < encoded_len {
+        // We don't have enough data yet to decode the entire message
+        src.reserve(encoded_len - tmp.len());
+        return Ok(None);
+    }
+
+    // We only advance the source buffer once we're sure we have enough
+    // data to try to decode the result.
+    src.advance(delim_len);
+    Ok(Some((delim_len, src.split_to(encoded_len).freeze())))
+}
+
+/// Append `message` to `dst` as length-delimited frame, unless it is larger
+/// than `limit`.
+///
+/// Returns length of the frame, including the delimiter.
+pub(crate) fn encode_frame<M: Message>(
+    message: &M,
+    limit: Option<usize>,
+    dst: &mut BytesMut,
+) -> Result<usize, Error> {
+    let encoded_len = message.encoded_len();
+    if let Some(limit) = limit {
+        if encoded_len > limit {
+            return Err(Error::FrameTooLarge {
+                size: encoded_len,
+                limit,
+            });
+        }
+    }
+
+    dst.reserve(encoded_len + MAX_VARINT_LENGTH);
+    encode_varint(encoded_len as u64, dst);
+    message.encode(dst)?;
+    Ok(length_delimiter_len(encoded_len) + encoded_len)
+}
+
 #[cfg(test)]
 mod test {
     use tenderdash_proto::{abci, prost::Message};
     use tokio::{io::AsyncWriteExt, sync::mpsc};
     use tokio_util::sync::CancellationToken;
 
+    use super::{Responder, Router};
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     /// Test if a bug in the codec receiving 2 requests without a response in
     /// between is fixed.
@@ -223,20 +799,23 @@ mod test {
             .try_init()
             .ok();
 
-        let (request_tx, mut request_rx) = mpsc::channel::<abci::Request>(1);
-        let (response_tx, response_rx) = mpsc::channel::<abci::Response>(1);
+        let (request_tx, mut request_rx) = mpsc::channel::<(abci::Request, Responder)>(1);
+        let (response_tx, response_rx) = mpsc::channel::<(u64, abci::Response)>(1);
         let cancel = CancellationToken::new();
 
         let (mut client, server) = tokio::io::duplex(10240);
 
-        let codec = tokio_util::codec::Framed::new(server, super::Coder {});
+        let codec = tokio_util::codec::Framed::new(server, super::Coder::new(Default::default()));
 
         let worker_cancel = cancel.clone();
         let hdl = tokio::spawn(super::Codec::process_worker_queues(
             codec,
-            request_tx,
+            Router::new(request_tx, Default::default()),
+            Responder::new(1, response_tx.clone()),
             response_rx,
+            super::ResponseWriter::new(None, Default::default()),
             worker_cancel,
+            std::time::Duration::ZERO,
         ));
 
         // We send 2 requests over the wire
@@ -259,13 +838,13 @@ mod test {
 
         // Then, we read one request
         tracing::debug!("MAIN THREAD: reading request 1");
-        request_rx.recv().await.expect("dequeue request 1");
+        let (_, responder) = request_rx.recv().await.expect("dequeue request 1");
         tracing::debug!("MAIN THREAD: dequeued request 1");
 
         //  Then, we send a response
         tracing::debug!("MAIN THREAD: sending response 1");
-        response_tx
-            .send(abci::Response {
+        responder
+            .send_async(abci::Response {
                 value: Some(abci::response::Value::Echo(abci::ResponseEcho {
                     message: "hello".to_string(),
                 })),
@@ -285,4 +864,222 @@ mod test {
         cancel.cancel();
         hdl.await.unwrap();
     }
+
+    #[tokio::test]
+    /// Given response batching, when responses are written, then the
+    /// connection is flushed only on `Flush` response or when batching delay
+    /// expires.
+    async fn test_response_batching() {
+        use std::time::Duration;
+
+        use bytes::{Buf, BytesMut};
+        use tokio::io::AsyncReadExt;
+
+        use super::{ResponseBatching, ResponseWriter};
+        use crate::IoStats;
+
+        let (request_tx, mut request_rx) = mpsc::channel::<(abci::Request, Responder)>(1);
+        let (response_tx, response_rx) = mpsc::channel::<(u64, abci::Response)>(1);
+        let cancel = CancellationToken::new();
+        let stats = IoStats::new();
+
+        let (mut client, server) = tokio::io::duplex(10240);
+        let codec = tokio_util::codec::Framed::new(server, super::Coder::new(Default::default()));
+        let writer = ResponseWriter::new(
+            Some(ResponseBatching {
+                max_bytes: 4096,
+                max_delay: Duration::from_millis(20),
+            }),
+            stats.clone(),
+        );
+        let hdl = tokio::spawn(super::Codec::process_worker_queues(
+            codec,
+            Router::new(request_tx, Default::default()),
+            Responder::new(1, response_tx),
+            response_rx,
+            writer,
+            cancel.clone(),
+            Duration::ZERO,
+        ));
+
+        let echo = |n: usize| {
+            abci::request::Value::Echo(abci::RequestEcho {
+                message: format!("hello {}", n),
+            })
+        };
+        let mut requests = (0..3).map(echo).collect::<Vec<_>>();
+        requests.push(abci::request::Value::Flush(Default::default()));
+        requests.push(echo(3));
+
+        for value in requests {
+            let request = abci::Request { value: Some(value) };
+            client
+                .write_all(&request.encode_length_delimited_to_vec())
+                .await
+                .unwrap();
+
+            let (request, responder) = request_rx.recv().await.expect("dequeue request");
+            let response = match request.value {
+                Some(abci::request::Value::Echo(echo)) => {
+                    abci::response::Value::Echo(abci::ResponseEcho {
+                        message: echo.message,
+                    })
+                },
+                _ => abci::response::Value::Flush(Default::default()),
+            };
+            responder
+                .send_async(abci::Response {
+                    value: Some(response),
+                })
+                .await
+                .expect("enqueue response");
+        }
+
+        // all responses are received, the last one after batching delay
+        let mut buf = BytesMut::new();
+        let mut received = 0;
+        while received < 5 {
+            client.read_buf(&mut buf).await.expect("read responses");
+            let mut tail = buf.clone().freeze();
+            while abci::Response::decode_length_delimited(&mut tail).is_ok() {
+                buf.advance(buf.len() - tail.len());
+                received += 1;
+            }
+        }
+
+        assert_eq!(stats.requests(), 5);
+        assert_eq!(stats.responses(), 5);
+        assert_eq!(stats.flushes(), 2);
+
+        cancel.cancel();
+        hdl.await.unwrap();
+    }
+
+    #[test]
+    /// Given request size limits, when a client announces too large request,
+    /// then it is rejected before the request is received.
+    fn test_decode_request_too_large() {
+        use bytes::BytesMut;
+        use tenderdash_proto::prost::encoding::encode_varint;
+        use tokio_util::codec::Decoder;
+
+        use super::{Coder, FrameLimits};
+        use crate::{ConnectionClass, Error};
+
+        let mut coder = Coder::new(FrameLimits {
+            max_request_size: Some(100),
+            class_request_size: [(ConnectionClass::Consensus, 1000)].into(),
+            max_response_size: None,
+        });
+
+        // only length delimiter is received
+        let mut buf = BytesMut::new();
+        encode_varint(1001, &mut buf);
+        assert!(matches!(
+            coder.decode(&mut buf),
+            Err(Error::FrameTooLarge {
+                size: 1001,
+                limit: 1000
+            })
+        ));
+
+        // request within class limit
+        let prepare = abci::Request {
+            value: Some(abci::request::Value::PrepareProposal(
+                abci::RequestPrepareProposal {
+                    quorum_hash: vec![0u8; 500],
+                    ..Default::default()
+                },
+            )),
+        };
+        let mut buf = BytesMut::from(prepare.encode_length_delimited_to_vec().as_slice());
+        assert_eq!(coder.decode(&mut buf).unwrap(), Some(prepare));
+        assert!(buf.is_empty());
+
+        // request of the same size, without class-specific limit
+        let echo = abci::Request {
+            value: Some(abci::request::Value::Echo(abci::RequestEcho {
+                message: "x".repeat(500),
+            })),
+        };
+        let mut buf = BytesMut::from(echo.encode_length_delimited_to_vec().as_slice());
+        assert!(matches!(
+            coder.decode(&mut buf),
+            Err(Error::FrameTooLarge { limit: 100, .. })
+        ));
+    }
+
+    #[test]
+    /// Given only class-specific request size limits, when a client announces
+    /// request larger than all of them, then it is rejected before the request
+    /// is received.
+    fn test_decode_request_too_large_class_limits() {
+        use bytes::BytesMut;
+        use tenderdash_proto::prost::encoding::encode_varint;
+        use tokio_util::codec::Decoder;
+
+        use super::{Coder, FrameLimits};
+        use crate::{ConnectionClass, Error};
+
+        let mut coder = Coder::new(FrameLimits {
+            class_request_size: [
+                (ConnectionClass::Consensus, 1000),
+                (ConnectionClass::Mempool, 100),
+            ]
+            .into(),
+            ..Default::default()
+        });
+
+        let mut buf = BytesMut::new();
+        encode_varint(1001, &mut buf);
+        assert!(matches!(
+            coder.decode(&mut buf),
+            Err(Error::FrameTooLarge {
+                size: 1001,
+                limit: 1000
+            })
+        ));
+
+        // request without class-specific limit, within the largest limit
+        let echo = abci::Request {
+            value: Some(abci::request::Value::Echo(abci::RequestEcho {
+                message: "x".repeat(500),
+            })),
+        };
+        let mut buf = BytesMut::from(echo.encode_length_delimited_to_vec().as_slice());
+        assert_eq!(coder.decode(&mut buf).unwrap(), Some(echo));
+    }
+
+    #[test]
+    /// Given response size limit, when too large response is encoded, then
+    /// error is returned.
+    fn test_encode_response_too_large() {
+        use bytes::BytesMut;
+        use tokio_util::codec::Encoder;
+
+        use super::{Coder, FrameLimits};
+        use crate::Error;
+
+        let mut coder = Coder::new(FrameLimits {
+            max_response_size: Some(100),
+            ..Default::default()
+        });
+        let response = |len: usize| abci::Response {
+            value: Some(abci::response::Value::Echo(abci::ResponseEcho {
+                message: "x".repeat(len),
+            })),
+        };
+
+        let mut buf = BytesMut::new();
+        coder.encode(response(10), &mut buf).expect("encode");
+        assert_eq!(
+            abci::Response::decode_length_delimited(buf.freeze()).unwrap(),
+            response(10)
+        );
+
+        assert!(matches!(
+            coder.encode(response(200), &mut BytesMut::new()),
+            Err(Error::FrameTooLarge { limit: 100, .. })
+        ));
+    }
 }
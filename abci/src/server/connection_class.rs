@@ -0,0 +1,211 @@
+//! Routing of ABCI requests to dispatchers by connection class.
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::{
+    sync::mpsc::{self, Receiver},
+    task::JoinHandle,
+};
+use tracing::info;
+
+#[cfg(feature = "metrics")]
+use super::metrics::Queue;
+use super::{
+    codec::{RequestSender, Responder},
+    deadline::Watchdog,
+    ServerConfig, ServerRuntime,
+};
+use crate::{
+    layer::DefaultLayers,
+    proto::abci::{request::Value, Request, Response},
+    AsyncRequestDispatcher, CancellationToken, Layer, RequestDispatcher,
+};
+
+/// Class of ABCI connection that a request belongs to.
+///
+/// Tenderdash keeps consensus, mempool, info/query and snapshot traffic on
+/// separate connections. Requests of each class can be processed by a separate
+/// dispatcher, configured with
+/// [`ServerBuilder::with_dispatcher()`](super::ServerBuilder::with_dispatcher()).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionClass {
+    /// InitChain, PrepareProposal, ProcessProposal, ExtendVote,
+    /// VerifyVoteExtension and FinalizeBlock.
+    Consensus,
+    /// CheckTx.
+    Mempool,
+    /// Info and Query.
+    Info,
+    /// ListSnapshots, OfferSnapshot, LoadSnapshotChunk and ApplySnapshotChunk.
+    Snapshot,
+}
+
+impl ConnectionClass {
+    /// Determine connection class of the request.
+    ///
+    /// Returns `None` for Echo and Flush requests, which can be sent on any
+    /// connection.
+    pub fn of(request: &Value) -> Option<Self> {
+        match request {
+            Value::InitChain(_)
+            | Value::PrepareProposal(_)
+            | Value::ProcessProposal(_)
+            | Value::ExtendVote(_)
+            | Value::VerifyVoteExtension(_)
+            | Value::FinalizeBlock(_) => Some(Self::Consensus),
+            Value::CheckTx(_) => Some(Self::Mempool),
+            Value::Info(_) | Value::Query(_) => Some(Self::Info),
+            Value::ListSnapshots(_)
+            | Value::OfferSnapshot(_)
+            | Value::LoadSnapshotChunk(_)
+            | Value::ApplySnapshotChunk(_) => Some(Self::Snapshot),
+            Value::Echo(_) | Value::Flush(_) => None,
+        }
+    }
+}
+
+/// Dispatcher that processes requests of one [ConnectionClass].
+#[derive(Clone)]
+pub(crate) enum ClassDispatcher {
+    Sync(Arc<dyn RequestDispatcher + Send + Sync>),
+    Async(Arc<dyn AsyncRequestDispatcher>),
+}
+
+impl ClassDispatcher {
+    /// Wrap the dispatcher with [DefaultLayers].
+    pub(crate) fn with_default_layers(self) -> Self {
+        match self {
+            Self::Sync(dispatcher) => Self::Sync(Arc::new(DefaultLayers.layer(Shared(dispatcher)))),
+            Self::Async(dispatcher) => {
+                Self::Async(Arc::new(DefaultLayers.layer(Shared(dispatcher))))
+            },
+        }
+    }
+}
+
+/// Shared dispatcher that can be wrapped with a [Layer].
+struct Shared<D: ?Sized>(Arc<D>);
+
+impl RequestDispatcher for Shared<dyn RequestDispatcher + Send + Sync> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        self.0.handle(request)
+    }
+}
+
+#[async_trait]
+impl AsyncRequestDispatcher for Shared<dyn AsyncRequestDispatcher> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        self.0.handle(request).await
+    }
+}
+
+impl Debug for ClassDispatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sync(_) => f.write_str("ClassDispatcher::Sync"),
+            Self::Async(_) => f.write_str("ClassDispatcher::Async"),
+        }
+    }
+}
+
+impl ClassDispatcher {
+    /// Spawn a worker that processes requests received from `requests`.
+    ///
+    /// Synchronous dispatchers run on a dedicated blocking thread, async ones
+    /// as a separate tokio task. The worker finishes when `requests` is
+    /// closed. When the dispatcher returns `None`, `cancel` is cancelled to
+    /// stop the server.
+    pub(crate) fn spawn(
+        self,
+        class: ConnectionClass,
+        mut requests: Receiver<(Request, Responder)>,
+        cancel: CancellationToken,
+        watchdog: Watchdog,
+        runtime: &ServerRuntime,
+    ) -> JoinHandle<()> {
+        match self {
+            Self::Sync(dispatcher) => runtime.handle.spawn_blocking(move || {
+                while let Some((request, responder)) = requests.blocking_recv() {
+                    let connection_id = responder.connection_id();
+                    let watch = watchdog.watch(&request, &responder);
+                    let Some(response) =
+                        watchdog.dispatch(&watch, &request, || dispatcher.handle(request.clone()))
+                    else {
+                        info!(?class, "ABCI Application is shutting down");
+                        cancel.cancel();
+                        return;
+                    };
+                    if !watch.finish(&response) {
+                        tracing::warn!(
+                            ?class,
+                            connection_id,
+                            "discarding response sent after deadline"
+                        );
+                        continue;
+                    }
+
+                    if let Err(error) = responder.send(response) {
+                        tracing::warn!(?class, connection_id, ?error, "cannot send response");
+                    }
+                }
+            }),
+            Self::Async(dispatcher) => runtime.spawn(async move {
+                while let Some((request, responder)) = requests.recv().await {
+                    let connection_id = responder.connection_id();
+                    let watch = watchdog.watch(&request, &responder);
+                    let Some(response) = watchdog
+                        .dispatch_async(&watch, &request, dispatcher.handle(request.clone()))
+                        .await
+                    else {
+                        info!(?class, "ABCI Application is shutting down");
+                        cancel.cancel();
+                        return;
+                    };
+                    if !watch.finish(&response) {
+                        tracing::warn!(
+                            ?class,
+                            connection_id,
+                            "discarding response sent after deadline"
+                        );
+                        continue;
+                    }
+
+                    if let Err(error) = responder.send_async(response).await {
+                        tracing::warn!(?class, connection_id, ?error, "cannot send response");
+                    }
+                }
+            }),
+        }
+    }
+}
+
+/// Start workers of dispatchers configured for connection classes.
+///
+/// Returns channels used to send requests to these workers. Workers stop when
+/// the channels are closed.
+pub(super) fn spawn_class_workers(
+    config: &ServerConfig,
+    runtime: &ServerRuntime,
+    cancel: &CancellationToken,
+    watchdog: &Watchdog,
+) -> BTreeMap<ConnectionClass, RequestSender> {
+    config
+        .class_dispatchers
+        .iter()
+        .map(|(class, dispatcher)| {
+            let (request_tx, request_rx) = mpsc::channel(1);
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &config.metrics {
+                metrics.track_queue(Queue::Request, &request_tx);
+            }
+            dispatcher.clone().spawn(
+                *class,
+                request_rx,
+                cancel.clone(),
+                watchdog.clone(),
+                runtime,
+            );
+            (*class, request_tx)
+        })
+        .collect()
+}
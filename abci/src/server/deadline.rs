@@ -0,0 +1,369 @@
+//! Deadlines of request processing, enforced by a watchdog.
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{runtime::Handle, time::sleep};
+use tracing::Instrument;
+
+#[cfg(feature = "metrics")]
+use super::metrics::Metrics;
+use super::{
+    codec::Responder,
+    panics::{self, CaughtPanic, PanicPolicy},
+    ServerConfig,
+};
+use crate::{
+    application::{method_name, METHOD_NAMES},
+    proto::abci::{response, Request, Response, ResponseException},
+    CancellationToken, Error, RequestContext,
+};
+
+/// Action taken when processing of a request exceeds its hard deadline.
+///
+/// Neither action interrupts the handler that exceeded the deadline; see
+/// [Deadline] for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadlineAction {
+    /// Send [ResponseException] to the client. Response returned by the
+    /// application later is discarded.
+    Exception,
+    /// Send [ResponseException] to the client and cancel the server using its
+    /// [CancellationToken]. Response returned by the application later is
+    /// discarded.
+    Cancel,
+}
+
+/// Deadlines of processing requests of one ABCI method.
+///
+/// Configured with
+/// [`ServerBuilder::with_deadline()`](super::ServerBuilder::with_deadline()).
+///
+/// # Limitations
+///
+/// Deadlines are enforced by a watchdog; the handler that exceeded its hard
+/// deadline keeps running until it returns. [RequestDispatcher]s, like
+/// [Application](crate::Application), process requests on the thread that
+/// called [`Server::next_client()`](super::Server::next_client()), or on the
+/// thread of their connection class, so requests received after the deadline
+/// wait until the hung handler returns, and the server does not finish
+/// `next_client()` on [DeadlineAction::Cancel] until then.
+///
+/// To let hung handlers stop, check
+/// [`RequestContext::cancellation_token()`], which is cancelled when the hard
+/// deadline passes, in long-running handlers.
+///
+/// [RequestDispatcher]: crate::RequestDispatcher
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use tenderdash_abci::{Deadline, DeadlineAction};
+///
+/// let deadline = Deadline::new()
+///     .with_soft_limit(Duration::from_secs(5))
+///     .with_hard_limit(Duration::from_secs(30), DeadlineAction::Cancel);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Deadline {
+    soft: Option<Duration>,
+    hard: Option<(Duration, DeadlineAction)>,
+}
+
+impl Deadline {
+    /// Create deadline without any limits.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Log a warning when processing takes longer than `limit`.
+    pub fn with_soft_limit(mut self, limit: Duration) -> Self {
+        self.soft = Some(limit);
+        self
+    }
+
+    /// Take `action` when processing takes longer than `limit`.
+    pub fn with_hard_limit(mut self, limit: Duration, action: DeadlineAction) -> Self {
+        self.hard = Some((limit, action));
+        self
+    }
+}
+
+/// Check that deadlines are configured for known ABCI methods only.
+pub(crate) fn validate_deadlines(deadlines: &BTreeMap<String, Deadline>) -> Result<(), Error> {
+    match deadlines
+        .keys()
+        .find(|method| !METHOD_NAMES.contains(&method.as_str()))
+    {
+        Some(method) => Err(Error::Configuration(format!(
+            "cannot set deadline of unknown ABCI method {}",
+            method
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Watches processing of requests and enforces their [Deadline]s.
+///
+/// When metrics are enabled, it also records processing time of requests.
+/// When [PanicPolicy] is configured, it catches panics of dispatchers.
+#[derive(Clone, Debug)]
+pub(crate) struct Watchdog {
+    deadlines: Arc<BTreeMap<String, Deadline>>,
+    /// Token of the server, cancelled on [DeadlineAction::Cancel] and
+    /// [PanicPolicy::Cancel].
+    cancel: CancellationToken,
+    handle: Handle,
+    panic_policy: Option<PanicPolicy>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(config: &ServerConfig, cancel: CancellationToken, handle: Handle) -> Self {
+        Self {
+            deadlines: Arc::new(config.deadlines.clone()),
+            cancel,
+            handle,
+            panic_policy: config.panic_policy,
+            #[cfg(feature = "metrics")]
+            metrics: config.metrics.clone(),
+        }
+    }
+
+    /// Process `request` with `dispatch`, making context of `watch` current.
+    ///
+    /// When panic policy is configured, panic of `dispatch` is turned into
+    /// exception response, and the policy is applied.
+    pub(crate) fn dispatch(
+        &self,
+        watch: &Watch,
+        request: &Request,
+        dispatch: impl FnOnce() -> Option<Response>,
+    ) -> Option<Response> {
+        let Some(policy) = self.panic_policy else {
+            return watch.context.scope(dispatch);
+        };
+        match panics::catch(|| watch.context.scope(dispatch)) {
+            Ok(response) => response,
+            Err(caught) => Some(self.panicked(policy, request, &watch.context, caught)),
+        }
+    }
+
+    /// Async version of [Watchdog::dispatch()].
+    pub(crate) async fn dispatch_async<F>(
+        &self,
+        watch: &Watch,
+        request: &Request,
+        dispatch: F,
+    ) -> Option<Response>
+    where
+        F: Future<Output = Option<Response>>,
+    {
+        let Some(policy) = self.panic_policy else {
+            return watch.context.scope_async(dispatch).await;
+        };
+        match panics::catch_async(watch.context.scope_async(dispatch)).await {
+            Ok(response) => response,
+            Err(caught) => Some(self.panicked(policy, request, &watch.context, caught)),
+        }
+    }
+
+    /// Log panic of dispatcher, apply `policy` and return exception response.
+    fn panicked(
+        &self,
+        policy: PanicPolicy,
+        request: &Request,
+        context: &RequestContext,
+        caught: CaughtPanic,
+    ) -> Response {
+        let method = request.value.as_ref().map(method_name).unwrap_or("unknown");
+        let request_id = context.request_id();
+        let backtrace = caught
+            .backtrace
+            .map(|backtrace| backtrace.to_string())
+            .unwrap_or_default();
+        tracing::error!(
+            method,
+            request_id,
+            panic = caught.message,
+            %backtrace,
+            "request dispatcher panicked"
+        );
+        if policy == PanicPolicy::Cancel {
+            tracing::error!("shutting down after panic, application state may be inconsistent");
+            self.cancel.cancel();
+        }
+
+        Response {
+            value: Some(response::Value::Exception(ResponseException {
+                error: format!(
+                    "{} request {} panicked: {}",
+                    method, request_id, caught.message
+                ),
+            })),
+        }
+    }
+
+    /// Start watching processing of `request`, and create its
+    /// [RequestContext].
+    ///
+    /// Watching stops when returned [Watch] is finished or dropped.
+    pub(crate) fn watch(&self, request: &Request, responder: &Responder) -> Watch {
+        let context = RequestContext::new(
+            request.value.as_ref(),
+            responder.connection_id(),
+            responder.received_at(),
+            self.cancel.child_token(),
+        );
+        let Some(value) = &request.value else {
+            return Watch::new(context);
+        };
+        let method = method_name(value);
+        #[cfg(feature = "metrics")]
+        let timer = self
+            .metrics
+            .clone()
+            .map(|metrics| (metrics, method, std::time::Instant::now()));
+        let Some(deadline) = self.deadlines.get(method).cloned() else {
+            return Watch {
+                answered: None,
+                done: CancellationToken::new(),
+                context,
+                #[cfg(feature = "metrics")]
+                timer,
+            };
+        };
+        let started = tokio::time::Instant::now();
+        let context = match deadline.hard {
+            Some((limit, _)) => context.with_deadline(started.into_std() + limit),
+            None => context,
+        };
+
+        let span = context.span().clone();
+        let request_cancel = context.cancellation_token().clone();
+        let watch = Watch {
+            answered: Some(Arc::new(AtomicBool::new(false))),
+            done: CancellationToken::new(),
+            context,
+            #[cfg(feature = "metrics")]
+            timer,
+        };
+        let answered = watch.answered.clone().expect("answered flag must be set");
+        let done = watch.done.clone();
+        let responder = responder.duplicate();
+        let cancel = self.cancel.clone();
+
+        let task = async move {
+            if let Some(limit) = deadline.soft {
+                tokio::select! {
+                    _ = sleep(limit) => {
+                        tracing::warn!(?limit, "request processing exceeded soft deadline");
+                    },
+                    _ = done.cancelled() => return,
+                }
+            }
+            let Some((limit, action)) = deadline.hard else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(started + limit) => {},
+                _ = done.cancelled() => return,
+            }
+            request_cancel.cancel();
+            if answered.swap(true, Ordering::SeqCst) {
+                return;
+            }
+
+            match action {
+                DeadlineAction::Exception => tracing::error!(
+                    ?limit,
+                    "request processing exceeded hard deadline, sending exception; later requests \
+                     wait until the handler returns"
+                ),
+                DeadlineAction::Cancel => tracing::error!(
+                    ?limit,
+                    "request processing exceeded hard deadline, sending exception and shutting \
+                     down when the handler returns"
+                ),
+            }
+            // Exception takes the place of the discarded response, so that
+            // responses of later requests can be sent, and connections can be
+            // drained on cancel.
+            let response = Response {
+                value: Some(response::Value::Exception(ResponseException {
+                    error: format!("{} exceeded deadline of {:?}", method, limit),
+                })),
+            };
+            if let Err(error) = responder.send_async(response).await {
+                tracing::warn!(?error, "cannot send exception");
+            }
+            if action == DeadlineAction::Cancel {
+                cancel.cancel();
+            }
+        };
+        self.handle.spawn(task.instrument(span));
+
+        watch
+    }
+}
+
+/// Processing of a request watched by [Watchdog].
+#[derive(Debug)]
+pub(crate) struct Watch {
+    /// Set when response was sent or hard deadline action was taken; `None`
+    /// when the request is not watched.
+    answered: Option<Arc<AtomicBool>>,
+    /// Cancelled when processing is finished.
+    done: CancellationToken,
+    /// Context of the request, current during its dispatching.
+    context: RequestContext,
+    /// Metrics that record processing of the request, with method name and
+    /// start time.
+    #[cfg(feature = "metrics")]
+    timer: Option<(Metrics, &'static str, std::time::Instant)>,
+}
+
+impl Watch {
+    /// Create watch of a request that has no deadlines.
+    fn new(context: RequestContext) -> Self {
+        Self {
+            answered: None,
+            done: CancellationToken::new(),
+            context,
+            #[cfg(feature = "metrics")]
+            timer: None,
+        }
+    }
+
+    /// Mark processing as finished with `response`.
+    ///
+    /// Returns `false` if the watchdog already took hard deadline action, and
+    /// the response of the application must be discarded.
+    pub(crate) fn finish(self, response: &Response) -> bool {
+        #[cfg(feature = "metrics")]
+        if let Some((metrics, method, started)) = &self.timer {
+            metrics.record_request(method, response, started.elapsed());
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = response;
+
+        match &self.answered {
+            Some(answered) => !answered.swap(true, Ordering::SeqCst),
+            None => true,
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.done.cancel();
+    }
+}
@@ -3,8 +3,13 @@
 use std::net::ToSocketAddrs;
 use std::{fmt::Debug, sync::Arc};
 #[cfg(feature = "unix")]
-use std::{fs, path::Path};
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
 
+use async_trait::async_trait;
 #[cfg(feature = "tcp")]
 use tokio::net::TcpListener;
 #[cfg(feature = "unix")]
@@ -13,46 +18,75 @@ use tokio::sync::Mutex;
 use tokio_util::net::Listener;
 use tracing::info;
 
-use super::{codec::Codec, Server, ServerRuntime};
-use crate::{CancellationToken, Error, RequestDispatcher};
+use super::{
+    codec::Codec, connection_class::spawn_class_workers, deadline::Watchdog, AsyncServer, Server,
+    ServerConfig, ServerRuntime,
+};
+use crate::{AsyncRequestDispatcher, CancellationToken, Error, RequestDispatcher};
 
 /// A TCP-based server for serving a specific ABCI application.
 ///
-/// Only one incoming connection is handled at a time.
-pub(super) struct GenericServer<App: RequestDispatcher, L: Listener> {
+/// By default, only one incoming connection is handled at a time; see
+/// [`ServerBuilder::with_max_connections()`](super::ServerBuilder::with_max_connections()).
+pub(super) struct GenericServer<App, L: Listener> {
     app: App,
     listener: Arc<Mutex<L>>,
     cancel: CancellationToken,
     runtime: ServerRuntime,
+    config: ServerConfig,
+    /// Socket file removed when the server is dropped.
+    #[cfg(feature = "unix")]
+    socket_file: Option<SocketFile>,
 }
 
-impl<App: RequestDispatcher, L: Listener> GenericServer<App, L> {
-    fn new(app: App, listener: L, cancel: CancellationToken, runtime: ServerRuntime) -> Self {
+impl<App, L: Listener> GenericServer<App, L> {
+    fn new(
+        app: App,
+        listener: L,
+        cancel: CancellationToken,
+        runtime: ServerRuntime,
+        config: ServerConfig,
+    ) -> Self {
         Self {
             app,
             listener: Arc::new(Mutex::new(listener)),
             cancel,
             runtime,
+            config,
+            #[cfg(feature = "unix")]
+            socket_file: None,
         }
     }
 }
 
 #[cfg(feature = "tcp")]
-impl<App: RequestDispatcher> GenericServer<App, TcpListener> {
+impl<App> GenericServer<App, TcpListener> {
     pub(super) fn bind<Addr>(
         app: App,
         addr: Addr,
         cancel: CancellationToken,
         runtime: ServerRuntime,
+        config: ServerConfig,
     ) -> Result<Self, Error>
     where
         Addr: ToSocketAddrs,
     {
         let std_listener = std::net::TcpListener::bind(addr)?;
+
+        Self::from_std(app, std_listener, cancel, runtime, config)
+    }
+
+    /// Create server using already bound listener.
+    pub(super) fn from_std(
+        app: App,
+        std_listener: std::net::TcpListener,
+        cancel: CancellationToken,
+        runtime: ServerRuntime,
+        config: ServerConfig,
+    ) -> Result<Self, Error> {
         std_listener.set_nonblocking(true)?;
         let listener = TcpListener::from_std(std_listener)?;
 
-        // let listener = TcpListener::bind(addr).await?;
         let local_addr = listener.local_addr()?;
         info!(
             "ABCI TCP server  {} with proto {} running at {}",
@@ -61,28 +95,78 @@ impl<App: RequestDispatcher> GenericServer<App, TcpListener> {
             local_addr
         );
 
-        let server = Self::new(app, listener, cancel, runtime);
+        let server = Self::new(app, listener, cancel, runtime, config);
         Ok(server)
     }
 }
 
 #[cfg(feature = "unix")]
-impl<App: RequestDispatcher> GenericServer<App, UnixListener> {
+impl<App> GenericServer<App, UnixListener> {
+    /// Bind to Unix socket file at `addr`.
+    ///
+    /// Existing socket file is replaced; any other file at `addr` causes an
+    /// error. The socket file is removed when the server is dropped.
     pub(super) fn bind<Addr>(
         app: App,
         addr: Addr,
         cancel: CancellationToken,
         runtime: ServerRuntime,
+        config: ServerConfig,
     ) -> Result<Self, Error>
     where
         Addr: AsRef<Path>,
     {
-        let socket_file = addr.as_ref();
-        fs::remove_file(socket_file).ok();
+        let path = addr.as_ref();
+        remove_stale_socket(path)?;
+
+        let std_listener = std::os::unix::net::UnixListener::bind(path)?;
+        let socket_file = SocketFile::new(path)?;
+
+        if let Some(mode) = config.unix_socket_mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if let Some(gid) = config.unix_socket_group {
+            std::os::unix::fs::chown(path, None, Some(gid))?;
+        }
+
+        let mut server = Self::from_std(app, std_listener, cancel, runtime, config)?;
+        server.socket_file = Some(socket_file);
+        Ok(server)
+    }
+
+    /// Bind to Linux abstract Unix socket with given name.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(super) fn bind_abstract(
+        app: App,
+        name: &str,
+        cancel: CancellationToken,
+        runtime: ServerRuntime,
+        config: ServerConfig,
+    ) -> Result<Self, Error> {
+        #[cfg(target_os = "android")]
+        use std::os::android::net::SocketAddrExt;
+        #[cfg(target_os = "linux")]
+        use std::os::linux::net::SocketAddrExt;
+
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        let std_listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
 
-        let listener = UnixListener::bind(addr)?;
+        Self::from_std(app, std_listener, cancel, runtime, config)
+    }
+
+    /// Create server using already bound listener.
+    ///
+    /// Socket file of the listener, if any, is not managed by the server.
+    pub(super) fn from_std(
+        app: App,
+        std_listener: std::os::unix::net::UnixListener,
+        cancel: CancellationToken,
+        runtime: ServerRuntime,
+        config: ServerConfig,
+    ) -> Result<Self, Error> {
+        std_listener.set_nonblocking(true)?;
+        let listener = UnixListener::from_std(std_listener)?;
 
-        // let listener = TcpListener::bind(addr).await?;
         let local_addr = listener.local_addr()?;
         info!(
             "ABCI Unix server {} with proto {} running at {:?}",
@@ -91,12 +175,12 @@ impl<App: RequestDispatcher> GenericServer<App, UnixListener> {
             local_addr
         );
 
-        let server = Self::new(app, listener, cancel, runtime);
+        let server = Self::new(app, listener, cancel, runtime, config);
         Ok(server)
     }
 }
 
-impl<'a, App: RequestDispatcher + 'a, L: Listener> Server for GenericServer<App, L>
+impl<App: RequestDispatcher, L: Listener> Server for GenericServer<App, L>
 where
     L: Listener + Send + Sync + 'static,
     L::Addr: Send + Debug,
@@ -107,32 +191,189 @@ where
         let cancel_token = self.cancel.child_token();
         let listener = Arc::clone(&self.listener);
 
-        let mut codec = Codec::new(listener, cancel_token.clone(), &self.runtime);
-        while !cancel_token.is_cancelled() {
-            let Some(request) = codec.next() else {
-                tracing::error!("client terminated stream");
-                return Ok(());
-            };
+        let watchdog = self.watchdog();
+        let classes = spawn_class_workers(&self.config, &self.runtime, &cancel_token, &watchdog);
+        let mut codec = Codec::new(
+            listener,
+            cancel_token.clone(),
+            &self.runtime,
+            &self.config,
+            classes,
+        );
+        // After cancellation, requests already received from connections that
+        // are being drained are still processed.
+        while let Some((request, responder)) = codec.next() {
+            let connection_id = responder.connection_id();
 
-            let Some(response) = self.app.handle(request.clone()) else {
+            let watch = watchdog.watch(&request, &responder);
+            let Some(response) =
+                watchdog.dispatch(&watch, &request, || self.app.handle(request.clone()))
+            else {
                 // `RequestDispatcher` decided to stop receiving new requests:
                 info!("ABCI Application is shutting down");
                 return Ok(());
             };
+            if !watch.finish(&response) {
+                tracing::warn!(connection_id, "discarding response sent after deadline");
+                continue;
+            }
+
+            if let Err(error) = responder.send(response) {
+                self.handle_send_error(connection_id, error, &cancel_token)?;
+            }
+        }
+
+        Self::codec_finished(&codec, &cancel_token)
+    }
+}
+
+#[async_trait]
+impl<App: AsyncRequestDispatcher, L: Listener> AsyncServer for GenericServer<App, L>
+where
+    L: Listener + Send + Sync + 'static,
+    L::Addr: Send + Debug,
+    L::Io: Send,
+{
+    async fn next_client(&self) -> Result<(), Error> {
+        // we create child token to stop the codec but not kill the app
+        let cancel_token = self.cancel.child_token();
+        let listener = Arc::clone(&self.listener);
+
+        let watchdog = self.watchdog();
+        let classes = spawn_class_workers(&self.config, &self.runtime, &cancel_token, &watchdog);
+        let mut codec = Codec::new(
+            listener,
+            cancel_token.clone(),
+            &self.runtime,
+            &self.config,
+            classes,
+        );
+        // After cancellation, requests already received from connections that
+        // are being drained are still processed.
+        while let Some((request, responder)) = codec.next_async().await {
+            let connection_id = responder.connection_id();
 
-            if let Some(crate::proto::abci::response::Value::Exception(ex)) = response.value.clone()
-            {
-                tracing::error!(error = ex.error, ?request, "error processing request")
+            let watch = watchdog.watch(&request, &responder);
+            let Some(response) = watchdog
+                .dispatch_async(&watch, &request, self.app.handle(request.clone()))
+                .await
+            else {
+                // `AsyncRequestDispatcher` decided to stop receiving new requests:
+                info!("ABCI Application is shutting down");
+                return Ok(());
             };
+            if !watch.finish(&response) {
+                tracing::warn!(connection_id, "discarding response sent after deadline");
+                continue;
+            }
+
+            if let Err(error) = responder.send_async(response).await {
+                self.handle_send_error(connection_id, error, &cancel_token)?;
+            }
+        }
+
+        Self::codec_finished(&codec, &cancel_token)
+    }
+}
+
+impl<App, L: Listener> GenericServer<App, L> {
+    /// Result of [Server::next_client()] when `codec` has no more requests.
+    fn codec_finished(codec: &Codec, cancel: &CancellationToken) -> Result<(), Error> {
+        if cancel.is_cancelled() {
+            Err(Error::cancelled(codec.drained()))
+        } else {
+            tracing::error!("client terminated stream");
+            Ok(())
+        }
+    }
+
+    /// Create watchdog that enforces configured deadlines.
+    fn watchdog(&self) -> Watchdog {
+        Watchdog::new(
+            &self.config,
+            self.cancel.clone(),
+            self.runtime.handle.clone(),
+        )
+    }
 
-            codec.send(response)?;
+    /// Handle error when sending response to a client.
+    ///
+    /// When serving multiple connections, or when shutting down, a closed
+    /// connection must not stop processing of other requests, so the error is
+    /// only logged.
+    fn handle_send_error(
+        &self,
+        connection_id: u64,
+        error: Error,
+        cancel: &CancellationToken,
+    ) -> Result<(), Error> {
+        if self.config.max_connections > 1 || cancel.is_cancelled() {
+            tracing::warn!(
+                connection_id,
+                ?error,
+                "cannot send response, connection closed"
+            );
+            Ok(())
+        } else {
+            Err(error)
         }
+    }
+}
+
+/// Remove socket file left by previous server instance.
+///
+/// Returns error if `path` exists and is not a Unix socket.
+#[cfg(feature = "unix")]
+fn remove_stale_socket(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(fs::remove_file(path)?),
+        Ok(_) => Err(Error::Configuration(format!(
+            "cannot bind to {:?}: file exists and is not a socket",
+            path
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
 
-        Err(Error::Cancelled())
+/// Unix socket file created by the server.
+///
+/// The file is removed on drop, unless it was replaced in the meantime (eg. by
+/// another server bound to the same path).
+#[cfg(feature = "unix")]
+struct SocketFile {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(feature = "unix")]
+impl SocketFile {
+    fn new(path: &Path) -> Result<Self, Error> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+}
+
+#[cfg(feature = "unix")]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        match fs::symlink_metadata(&self.path) {
+            Ok(metadata) if metadata.dev() == self.dev && metadata.ino() == self.ino => {
+                if let Err(error) = fs::remove_file(&self.path) {
+                    tracing::warn!(?error, path = ?self.path, "cannot remove socket file");
+                }
+            },
+            _ => tracing::debug!(path = ?self.path, "socket file already removed or replaced"),
+        }
     }
 }
 
-impl<App: RequestDispatcher, L: Listener> Drop for GenericServer<App, L> {
+impl<App, L: Listener> Drop for GenericServer<App, L> {
     fn drop(&mut self) {
         tracing::debug!("ABCI server shut down")
     }
@@ -0,0 +1,521 @@
+//! gRPC transport of the ABCI server.
+//!
+//! Adapts request dispatchers to the tonic [AbciApplication] service generated
+//! by `tenderdash-proto`, so the same application can be served using the
+//! socket protocol or gRPC.
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::Stream;
+use tenderdash_proto::{
+    prost::Message,
+    tonic::{self, transport::server::Connected, Status},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex as AsyncMutex,
+    },
+    task::{JoinError, JoinHandle},
+};
+use tracing::info;
+
+#[cfg(feature = "metrics")]
+use super::metrics::{Metrics, Queue};
+use super::{
+    codec::{next_connection_id, FrameLimits, RequestSender, Responder},
+    connection_class::{spawn_class_workers, ClassDispatcher},
+    deadline::Watchdog,
+    AsyncServer, ConnectionClass, Server, ServerConfig, ServerRuntime,
+};
+use crate::{
+    proto::abci::{
+        self,
+        abci_application_server::{AbciApplication, AbciApplicationServer},
+        request, response, Request,
+    },
+    AsyncRequestDispatcher, CancellationToken, Error, RequestDispatcher,
+};
+
+/// Delay before accepting next connection after accept failed.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Task of the gRPC service; returns `false` when it was not drained in time.
+type ServiceTask = JoinHandle<Result<bool, Error>>;
+
+/// Adapter that serves [RequestDispatcher] or [AsyncRequestDispatcher], like
+/// [Application](crate::Application), as tonic [AbciApplication] service.
+///
+/// [ServerBuilder](super::ServerBuilder) uses it for `grpc://` addresses. Use
+/// it directly to add the application to your own tonic server.
+///
+/// Requests are processed one at a time, in the order they were received, like
+/// in the socket server. Exceptions returned by the application are sent as
+/// [Status] with `Unknown` code; when the dispatcher is shutting down, requests
+/// fail with `Unavailable` code. Like in the server, the dispatcher is wrapped
+/// with [DefaultLayers](crate::layer::DefaultLayers), so requests are processed
+/// inside a tracing span and exceptions are logged as errors.
+///
+/// Request size limits of [ServerBuilder](super::ServerBuilder), including
+/// limits of connection classes, apply to `grpc://` servers, where requests
+/// over the limit fail with `ResourceExhausted` code. When the bridge is added
+/// to your own tonic server, configure limits of the service instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tenderdash_abci::{proto::tonic::transport::Server, Application, GrpcBridge};
+///
+/// struct MyApp;
+/// impl Application for MyApp {}
+///
+/// # async fn serve() {
+/// Server::builder()
+///     .add_service(GrpcBridge::new(MyApp).into_service())
+///     .serve("127.0.0.1:26658".parse().unwrap())
+///     .await
+///     .expect("server failed");
+/// # }
+/// ```
+pub struct GrpcBridge {
+    route: Route,
+}
+
+/// Destination of requests received by [GrpcBridge].
+enum Route {
+    /// Dispatcher called directly by the bridge.
+    Direct {
+        dispatcher: ClassDispatcher,
+        /// Ensures requests are processed one at a time.
+        lock: AsyncMutex<()>,
+    },
+    /// Workers of [GrpcServer] that process requests of the main dispatcher
+    /// and of dispatchers of connection classes.
+    Server {
+        main: RequestSender,
+        classes: BTreeMap<ConnectionClass, RequestSender>,
+        limits: FrameLimits,
+        /// Metrics that track depth of response channels.
+        #[cfg(feature = "metrics")]
+        metrics: Option<Metrics>,
+    },
+}
+
+impl GrpcBridge {
+    /// Serve synchronous `dispatcher`.
+    ///
+    /// Requests are processed on tokio blocking threads.
+    pub fn new<D: RequestDispatcher + Send + Sync + 'static>(dispatcher: D) -> Self {
+        Self::direct(ClassDispatcher::Sync(Arc::new(dispatcher)))
+    }
+
+    /// Serve asynchronous `dispatcher`.
+    pub fn new_async<D: AsyncRequestDispatcher + 'static>(dispatcher: D) -> Self {
+        Self::direct(ClassDispatcher::Async(Arc::new(dispatcher)))
+    }
+
+    fn direct(dispatcher: ClassDispatcher) -> Self {
+        Self {
+            route: Route::Direct {
+                dispatcher: dispatcher.with_default_layers(),
+                lock: AsyncMutex::new(()),
+            },
+        }
+    }
+
+    /// Wrap the bridge into tonic service.
+    pub fn into_service(self) -> AbciApplicationServer<Self> {
+        AbciApplicationServer::new(self)
+    }
+
+    /// Dispatch request received on connection `connection_id`.
+    async fn call(
+        &self,
+        connection_id: Option<u64>,
+        value: request::Value,
+    ) -> Result<response::Value, Status> {
+        let class = ConnectionClass::of(&value);
+        let request = Request { value: Some(value) };
+
+        let response = match &self.route {
+            Route::Direct { dispatcher, lock } => {
+                let _guard = lock.lock().await;
+                match dispatcher {
+                    ClassDispatcher::Sync(dispatcher) => {
+                        let dispatcher = Arc::clone(dispatcher);
+                        tokio::task::spawn_blocking(move || dispatcher.handle(request))
+                            .await
+                            .map_err(|e| Status::internal(e.to_string()))?
+                    },
+                    ClassDispatcher::Async(dispatcher) => dispatcher.handle(request).await,
+                }
+            },
+            Route::Server {
+                main,
+                classes,
+                limits,
+                #[cfg(feature = "metrics")]
+                metrics,
+            } => {
+                // tonic only enforces the largest limit, so check limit of the request class
+                if let Some(limit) = limits.request_limit(&request) {
+                    let size = request.encoded_len();
+                    if size > limit {
+                        return Err(Status::resource_exhausted(
+                            Error::FrameTooLarge { size, limit }.to_string(),
+                        ));
+                    }
+                }
+                let requests = class.and_then(|class| classes.get(&class)).unwrap_or(main);
+                let connection_id = connection_id.unwrap_or_default();
+
+                let (response_tx, mut response_rx) = mpsc::channel(1);
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = metrics {
+                    metrics.track_queue(Queue::Response, &response_tx);
+                }
+                requests
+                    .send((request, Responder::new(connection_id, response_tx)))
+                    .await
+                    .map_err(|_| shutting_down())?;
+                response_rx.recv().await.map(|(_, response)| response)
+            },
+        };
+
+        match response.ok_or_else(shutting_down)?.value {
+            Some(response::Value::Exception(exception)) => Err(Status::unknown(exception.error)),
+            Some(value) => Ok(value),
+            None => Err(Status::internal("empty response")),
+        }
+    }
+}
+
+fn shutting_down() -> Status {
+    Status::unavailable("ABCI application is shutting down")
+}
+
+/// Define methods of [AbciApplication] implemented by [GrpcBridge].
+macro_rules! grpc_methods {
+    ($($method:ident($variant:ident, $request:ident) -> $response:ident;)*) => {
+        #[async_trait]
+        impl AbciApplication for GrpcBridge {
+            $(
+                async fn $method(
+                    &self,
+                    request: tonic::Request<abci::$request>,
+                ) -> Result<tonic::Response<abci::$response>, Status> {
+                    let connection_id = request.extensions().get::<ConnectionId>().map(|id| id.0);
+                    match self
+                        .call(connection_id, request::Value::$variant(request.into_inner()))
+                        .await?
+                    {
+                        response::Value::$variant(response) => Ok(tonic::Response::new(response)),
+                        other => Err(Status::internal(format!(
+                            "unexpected response: {:?}",
+                            other
+                        ))),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+grpc_methods! {
+    echo(Echo, RequestEcho) -> ResponseEcho;
+    flush(Flush, RequestFlush) -> ResponseFlush;
+    info(Info, RequestInfo) -> ResponseInfo;
+    check_tx(CheckTx, RequestCheckTx) -> ResponseCheckTx;
+    query(Query, RequestQuery) -> ResponseQuery;
+    init_chain(InitChain, RequestInitChain) -> ResponseInitChain;
+    list_snapshots(ListSnapshots, RequestListSnapshots) -> ResponseListSnapshots;
+    offer_snapshot(OfferSnapshot, RequestOfferSnapshot) -> ResponseOfferSnapshot;
+    load_snapshot_chunk(LoadSnapshotChunk, RequestLoadSnapshotChunk) -> ResponseLoadSnapshotChunk;
+    apply_snapshot_chunk(ApplySnapshotChunk, RequestApplySnapshotChunk) -> ResponseApplySnapshotChunk;
+    prepare_proposal(PrepareProposal, RequestPrepareProposal) -> ResponsePrepareProposal;
+    process_proposal(ProcessProposal, RequestProcessProposal) -> ResponseProcessProposal;
+    extend_vote(ExtendVote, RequestExtendVote) -> ResponseExtendVote;
+    verify_vote_extension(VerifyVoteExtension, RequestVerifyVoteExtension) -> ResponseVerifyVoteExtension;
+    finalize_block(FinalizeBlock, RequestFinalizeBlock) -> ResponseFinalizeBlock;
+}
+
+/// Identifier of a connection accepted by [GrpcServer], available in
+/// extensions of its requests.
+#[derive(Clone, Copy, Debug)]
+struct ConnectionId(u64);
+
+/// TCP connection accepted by [GrpcServer].
+struct GrpcConnection {
+    stream: TcpStream,
+    id: u64,
+}
+
+impl Connected for GrpcConnection {
+    type ConnectInfo = ConnectionId;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        ConnectionId(self.id)
+    }
+}
+
+impl AsyncRead for GrpcConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GrpcConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// ABCI server using gRPC transport.
+///
+/// Serves all connections until the server is cancelled. Requests are
+/// processed by the dispatcher on the thread or task that called
+/// `next_client()`, or by dispatchers of connection classes.
+pub(super) struct GrpcServer<App> {
+    app: App,
+    listener: Arc<TcpListener>,
+    cancel: CancellationToken,
+    runtime: ServerRuntime,
+    config: ServerConfig,
+}
+
+impl<App> GrpcServer<App> {
+    pub(super) fn bind(
+        app: App,
+        addrs: &[SocketAddr],
+        cancel: CancellationToken,
+        runtime: ServerRuntime,
+        config: ServerConfig,
+    ) -> Result<Self, Error> {
+        let std_listener = std::net::TcpListener::bind(addrs)?;
+        std_listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(std_listener)?;
+
+        info!(
+            "ABCI gRPC server {} with proto {} running at {}",
+            env!("CARGO_PKG_VERSION"),
+            tenderdash_proto::ABCI_VERSION,
+            listener.local_addr()?
+        );
+
+        Ok(Self {
+            app,
+            listener: Arc::new(listener),
+            cancel,
+            runtime,
+            config,
+        })
+    }
+
+    /// Create watchdog that enforces configured deadlines.
+    fn watchdog(&self) -> Watchdog {
+        Watchdog::new(
+            &self.config,
+            self.cancel.clone(),
+            self.runtime.handle.clone(),
+        )
+    }
+
+    /// Start gRPC service.
+    ///
+    /// Returns channel of requests to be processed by the main dispatcher, and
+    /// the task of the service. The task finishes when `cancel` is cancelled
+    /// and in-flight requests are processed, or drain timeout elapses; it
+    /// returns `false` in the latter case.
+    fn serve(
+        &self,
+        cancel: &CancellationToken,
+        watchdog: &Watchdog,
+    ) -> (Receiver<(Request, Responder)>, ServiceTask) {
+        let (request_tx, request_rx) = mpsc::channel(1);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.config.metrics {
+            metrics.track_queue(Queue::Request, &request_tx);
+        }
+        let bridge = GrpcBridge {
+            route: Route::Server {
+                main: request_tx,
+                classes: spawn_class_workers(&self.config, &self.runtime, cancel, watchdog),
+                limits: self.config.frame_limits.clone(),
+                #[cfg(feature = "metrics")]
+                metrics: self.config.metrics.clone(),
+            },
+        };
+        let limits = &self.config.frame_limits;
+        let service = bridge
+            .into_service()
+            .max_decoding_message_size(limits.max_frame_size().unwrap_or(usize::MAX))
+            .max_encoding_message_size(limits.max_response_size.unwrap_or(usize::MAX));
+
+        let incoming = incoming(Arc::clone(&self.listener));
+        let cancel = cancel.clone();
+        let drain_timeout = self.config.drain_timeout;
+        let task = self.runtime.spawn(async move {
+            let server = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, cancel.cancelled());
+
+            tokio::select! {
+                result = server => result.map(|_| true).map_err(|e| Error::Connection(io::Error::other(e))),
+                _ = async {
+                    cancel.cancelled().await;
+                    tokio::time::sleep(drain_timeout).await
+                } => Ok(false),
+            }
+        });
+
+        (request_rx, task)
+    }
+
+    /// Result of `next_client()` when the service task finishes.
+    fn finished(
+        result: Result<Result<bool, Error>, JoinError>,
+        cancel: &CancellationToken,
+    ) -> Result<(), Error> {
+        let drained = result.map_err(|e| Error::Async(e.to_string()))??;
+        if cancel.is_cancelled() {
+            Err(Error::cancelled(drained))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Stream of connections accepted on `listener`.
+///
+/// Each connection gets a new identifier, passed to the dispatcher in the
+/// [RequestContext](crate::RequestContext) of its requests.
+fn incoming(listener: Arc<TcpListener>) -> impl Stream<Item = Result<GrpcConnection, io::Error>> {
+    futures::stream::unfold(listener, |listener| async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    stream.set_nodelay(true).ok();
+                    let id = next_connection_id();
+                    tracing::debug!(?addr, connection_id = id, "accepted gRPC connection");
+                    return Some((Ok(GrpcConnection { stream, id }), listener));
+                },
+                Err(error) => {
+                    tracing::warn!(?error, "cannot accept gRPC connection");
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                },
+            }
+        }
+    })
+}
+
+impl<App: RequestDispatcher> Server for GrpcServer<App> {
+    fn next_client(&self) -> Result<(), Error> {
+        let cancel_token = self.cancel.child_token();
+        let watchdog = self.watchdog();
+        let (mut requests, service) = self.serve(&cancel_token, &watchdog);
+
+        while let Some((request, responder)) = requests.blocking_recv() {
+            let connection_id = responder.connection_id();
+
+            let watch = watchdog.watch(&request, &responder);
+            let Some(response) =
+                watchdog.dispatch(&watch, &request, || self.app.handle(request.clone()))
+            else {
+                // `RequestDispatcher` decided to stop receiving new requests:
+                info!("ABCI Application is shutting down");
+                cancel_token.cancel();
+                return Ok(());
+            };
+            if !watch.finish(&response) {
+                tracing::warn!(connection_id, "discarding response sent after deadline");
+                continue;
+            }
+
+            if let Err(error) = responder.send(response) {
+                tracing::warn!(
+                    connection_id,
+                    ?error,
+                    "cannot send response, request cancelled"
+                );
+            }
+        }
+
+        Self::finished(self.runtime.block_on(service), &cancel_token)
+    }
+}
+
+#[async_trait]
+impl<App: AsyncRequestDispatcher> AsyncServer for GrpcServer<App> {
+    async fn next_client(&self) -> Result<(), Error> {
+        let cancel_token = self.cancel.child_token();
+        let watchdog = self.watchdog();
+        let (mut requests, service) = self.serve(&cancel_token, &watchdog);
+
+        while let Some((request, responder)) = requests.recv().await {
+            let connection_id = responder.connection_id();
+
+            let watch = watchdog.watch(&request, &responder);
+            let Some(response) = watchdog
+                .dispatch_async(&watch, &request, self.app.handle(request.clone()))
+                .await
+            else {
+                // `AsyncRequestDispatcher` decided to stop receiving new requests:
+                info!("ABCI Application is shutting down");
+                cancel_token.cancel();
+                return Ok(());
+            };
+            if !watch.finish(&response) {
+                tracing::warn!(connection_id, "discarding response sent after deadline");
+                continue;
+            }
+
+            if let Err(error) = responder.send_async(response).await {
+                tracing::warn!(
+                    connection_id,
+                    ?error,
+                    "cannot send response, request cancelled"
+                );
+            }
+        }
+
+        Self::finished(service.await, &cancel_token)
+    }
+}
@@ -0,0 +1,126 @@
+//! Callbacks notified about lifecycle of client connections.
+use std::{fmt::Debug, sync::Arc};
+
+use crate::Error;
+
+/// Reason why a client connection was closed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// Client closed the connection.
+    Closed,
+    /// Request received from the client could not be decoded, or exceeded
+    /// size limit.
+    DecodeError(Error),
+    /// Response could not be written to the connection.
+    WriteError(Error),
+    /// Dispatcher stopped receiving requests.
+    DispatcherStopped,
+    /// Server was cancelled and the connection was drained. `drained` is
+    /// `false` when some responses were lost.
+    Shutdown { drained: bool },
+}
+
+/// Callbacks notified about lifecycle of client connections, like Tenderdash
+/// connecting or disconnecting.
+///
+/// Configured with
+/// [`ServerBuilder::with_connection_hooks()`](super::ServerBuilder::with_connection_hooks()).
+/// Callbacks are executed on tokio runtime of the server, in the task that
+/// serves the connection, so they should return quickly.
+///
+/// Connection IDs are the same as returned by [current_connection_id()] while
+/// a request of the connection is dispatched, so the application can relate
+/// requests to connection events. To react to these events in the
+/// application, share state between the application and the hooks, for
+/// example using [Arc]; `ConnectionHooks` is implemented for `Arc<T>`.
+///
+/// Hooks are called for connections of socket transports (`unix://`,
+/// `tcp://` and `tls://` addresses) only.
+///
+/// [current_connection_id()]: crate::current_connection_id()
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::{
+///     atomic::{AtomicBool, Ordering},
+///     Arc,
+/// };
+///
+/// use tenderdash_abci::{Application, ConnectionHooks, DisconnectReason, ServerBuilder};
+///
+/// #[derive(Default)]
+/// struct Connected(AtomicBool);
+///
+/// impl ConnectionHooks for Connected {
+///     fn on_connect(&self, _peer_addr: &str, _connection_id: u64) {
+///         self.0.store(true, Ordering::SeqCst);
+///     }
+///
+///     fn on_disconnect(&self, _connection_id: u64, _reason: &DisconnectReason) {
+///         // reset uncommitted block state here
+///         self.0.store(false, Ordering::SeqCst);
+///     }
+/// }
+///
+/// struct MyApp {
+///     connected: Arc<Connected>,
+/// }
+/// impl Application for MyApp {}
+///
+/// let connected = Arc::new(Connected::default());
+/// let app = MyApp {
+///     connected: Arc::clone(&connected),
+/// };
+/// let server = ServerBuilder::new(app, "unix:///tmp/abci.sock")
+///     .with_connection_hooks(connected)
+///     .build()
+///     .expect("server failed");
+/// ```
+pub trait ConnectionHooks: Send + Sync {
+    /// Client connected from `peer_addr`; requests of this connection will be
+    /// dispatched with `connection_id`.
+    ///
+    /// Called after TLS handshake, if TLS is used.
+    fn on_connect(&self, peer_addr: &str, connection_id: u64) {
+        let _ = (peer_addr, connection_id);
+    }
+
+    /// Connection `connection_id` was closed.
+    fn on_disconnect(&self, connection_id: u64, reason: &DisconnectReason) {
+        let _ = (connection_id, reason);
+    }
+
+    /// Request received on connection `connection_id` could not be decoded.
+    ///
+    /// The connection is closed, and [ConnectionHooks::on_disconnect()] is
+    /// called with [DisconnectReason::DecodeError] afterwards.
+    fn on_decode_error(&self, connection_id: u64, error: &Error) {
+        let _ = (connection_id, error);
+    }
+}
+
+impl<T: ConnectionHooks + ?Sized> ConnectionHooks for Arc<T> {
+    fn on_connect(&self, peer_addr: &str, connection_id: u64) {
+        (**self).on_connect(peer_addr, connection_id)
+    }
+
+    fn on_disconnect(&self, connection_id: u64, reason: &DisconnectReason) {
+        (**self).on_disconnect(connection_id, reason)
+    }
+
+    fn on_decode_error(&self, connection_id: u64, error: &Error) {
+        (**self).on_decode_error(connection_id, error)
+    }
+}
+
+/// Shared [ConnectionHooks], stored in server configuration.
+#[derive(Clone)]
+pub(crate) struct Hooks(pub(crate) Arc<dyn ConnectionHooks>);
+
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Hooks")
+    }
+}
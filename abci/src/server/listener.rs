@@ -0,0 +1,157 @@
+//! Listeners created outside of the server, like sockets passed by systemd.
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+
+use crate::Error;
+
+/// First file descriptor passed by systemd socket activation.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Listener created outside of the ABCI server, passed to
+/// [`ServerBuilder::with_listener()`](super::ServerBuilder::with_listener()).
+#[derive(Debug)]
+pub enum StdListener {
+    #[cfg(feature = "tcp")]
+    Tcp(std::net::TcpListener),
+    #[cfg(feature = "unix")]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+#[cfg(feature = "tcp")]
+impl From<std::net::TcpListener> for StdListener {
+    fn from(listener: std::net::TcpListener) -> Self {
+        Self::Tcp(listener)
+    }
+}
+
+#[cfg(feature = "unix")]
+impl From<std::os::unix::net::UnixListener> for StdListener {
+    fn from(listener: std::os::unix::net::UnixListener) -> Self {
+        Self::Unix(listener)
+    }
+}
+
+/// Source of the listener used by the server, when it does not bind to the
+/// address itself.
+#[derive(Debug)]
+pub(crate) enum ListenerSource {
+    /// Listener provided by the user.
+    Std(StdListener),
+    /// Listener passed by systemd socket activation, if any.
+    #[cfg(unix)]
+    Systemd,
+}
+
+impl ListenerSource {
+    /// Return the listener, or `None` if the server should bind to its address.
+    pub(crate) fn take(self) -> Result<Option<StdListener>, Error> {
+        match self {
+            Self::Std(listener) => Ok(Some(listener)),
+            #[cfg(unix)]
+            Self::Systemd => systemd_listener(),
+        }
+    }
+}
+
+/// Take listener passed by systemd in `LISTEN_FDS`.
+///
+/// Returns `None` when the process was not started with socket activation.
+/// Environment variables used by the protocol are removed, so that child
+/// processes do not inherit them.
+#[cfg(unix)]
+fn systemd_listener() -> Result<Option<StdListener>, Error> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(None);
+    };
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        tracing::debug!(pid, "LISTEN_PID does not match current process, ignoring");
+        return Ok(None);
+    }
+    let fds: RawFd = fds
+        .parse()
+        .map_err(|e| Error::Configuration(format!("invalid LISTEN_FDS {}: {}", fds, e)))?;
+    match fds {
+        0 => return Ok(None),
+        1 => {},
+        _ => tracing::warn!(
+            fds,
+            "systemd passed more than one socket, using the first one"
+        ),
+    }
+
+    // SAFETY: systemd passes ownership of listening sockets starting at
+    // SD_LISTEN_FDS_START, and we take it only once as the variables are removed.
+    Ok(Some(unsafe { from_raw_socket(SD_LISTEN_FDS_START) }?))
+}
+
+/// Create listener from raw file descriptor of a listening socket.
+///
+/// # Safety
+///
+/// `fd` must be an open socket file descriptor owned by the caller.
+#[cfg(unix)]
+unsafe fn from_raw_socket(raw_fd: RawFd) -> Result<StdListener, Error> {
+    let fd = OwnedFd::from_raw_fd(raw_fd);
+
+    // local_addr() fails if socket family does not match listener type
+    #[cfg(feature = "unix")]
+    let fd = {
+        let listener = std::os::unix::net::UnixListener::from(fd);
+        if listener.local_addr().is_ok() {
+            return Ok(StdListener::Unix(listener));
+        }
+        OwnedFd::from(listener)
+    };
+    #[cfg(feature = "tcp")]
+    let fd = {
+        let listener = std::net::TcpListener::from(fd);
+        if listener.local_addr().is_ok() {
+            return Ok(StdListener::Tcp(listener));
+        }
+        OwnedFd::from(listener)
+    };
+    drop(fd);
+
+    Err(Error::Configuration(format!(
+        "file descriptor {} is not a supported listening socket",
+        raw_fd
+    )))
+}
+
+#[cfg(all(test, unix, feature = "tcp", feature = "unix"))]
+mod tests {
+    use std::os::fd::IntoRawFd;
+
+    use super::{from_raw_socket, systemd_listener, StdListener};
+
+    #[test]
+    fn test_from_raw_socket() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listener = unsafe { from_raw_socket(tcp.into_raw_fd()) }.unwrap();
+        assert!(matches!(listener, StdListener::Tcp(_)));
+
+        let path = "/tmp/abci-listener-from-raw-socket.sock";
+        std::fs::remove_file(path).ok();
+        let unix = std::os::unix::net::UnixListener::bind(path).unwrap();
+        let listener = unsafe { from_raw_socket(unix.into_raw_fd()) }.unwrap();
+        assert!(matches!(listener, StdListener::Unix(_)));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_systemd_listener_other_process() {
+        // LISTEN_PID of another process means the sockets are not for us
+        std::env::set_var("LISTEN_PID", (std::process::id() + 1).to_string());
+        std::env::set_var("LISTEN_FDS", "1");
+
+        assert!(systemd_listener().unwrap().is_none());
+        assert!(std::env::var("LISTEN_FDS").is_err());
+    }
+}
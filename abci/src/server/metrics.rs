@@ -0,0 +1,616 @@
+//! Prometheus metrics of the ABCI server.
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{Sender, WeakSender},
+    task::JoinSet,
+};
+
+use crate::{
+    application::METHOD_NAMES,
+    proto::abci::{response, Response},
+    CancellationToken, Error,
+};
+
+/// Content type of Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of buckets of request latency histograms, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds of buckets of frame size histograms, in bytes.
+const FRAME_BUCKETS: &[f64] = &[
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+
+/// Maximum size of HTTP request head read by the metrics listener.
+const MAX_HTTP_REQUEST: usize = 8192;
+/// Maximum time of reading HTTP request and writing the response by the
+/// metrics listener.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Metrics of request processing, exposed in Prometheus text format.
+///
+/// Configured with
+/// [`ServerBuilder::with_metrics()`](super::ServerBuilder::with_metrics()).
+/// Metrics are shared between clones, so a clone can be used to render them
+/// with [Metrics::render()] while the server is running, or to serve them
+/// using [Metrics::serve()]. The server can also start a built-in listener;
+/// see
+/// [`ServerBuilder::with_metrics_listener()`](super::ServerBuilder::with_metrics_listener()).
+///
+/// Following metrics are recorded:
+///
+/// * `abci_requests_total{method}` - number of processed requests,
+/// * `abci_exceptions_total{method}` - number of requests that resulted in
+///   `ResponseException`,
+/// * `abci_request_duration_seconds{method}` - histogram of request processing
+///   time,
+/// * `abci_request_frame_bytes`, `abci_response_frame_bytes` - histograms of
+///   encoded frame sizes read and written on socket connections, including
+///   length delimiters,
+/// * `abci_connections_accepted_total`, `abci_connections_closed_total` -
+///   socket connections accepted and closed,
+/// * `abci_queue_depth{queue}` - number of requests waiting for a dispatcher
+///   (`queue="request"`) and responses waiting to be written
+///   (`queue="response"`).
+///
+/// # Examples
+///
+/// ```no_run
+/// use tenderdash_abci::{Application, Metrics, ServerBuilder};
+///
+/// struct MyApp {}
+/// impl Application for MyApp {}
+///
+/// let metrics = Metrics::new();
+/// let server = ServerBuilder::new(MyApp {}, "unix:///tmp/abci.sock")
+///     .with_metrics(metrics.clone())
+///     .build()
+///     .expect("server failed");
+///
+/// println!("{}", metrics.render());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+#[derive(Debug)]
+struct Registry {
+    methods: BTreeMap<&'static str, MethodMetrics>,
+    request_frames: Histogram,
+    response_frames: Histogram,
+    connections_accepted: AtomicU64,
+    connections_closed: AtomicU64,
+    queues: Mutex<Vec<TrackedQueue>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            methods: METHOD_NAMES
+                .iter()
+                .map(|method| (*method, MethodMetrics::default()))
+                .collect(),
+            request_frames: Histogram::new(FRAME_BUCKETS, 1.0),
+            response_frames: Histogram::new(FRAME_BUCKETS, 1.0),
+            connections_accepted: Default::default(),
+            connections_closed: Default::default(),
+            queues: Default::default(),
+        }
+    }
+}
+
+/// Metrics of one ABCI method.
+#[derive(Debug)]
+struct MethodMetrics {
+    requests: AtomicU64,
+    exceptions: AtomicU64,
+    latency: Histogram,
+}
+
+impl Default for MethodMetrics {
+    fn default() -> Self {
+        Self {
+            requests: Default::default(),
+            exceptions: Default::default(),
+            // latency is observed in nanoseconds and exposed in seconds
+            latency: Histogram::new(LATENCY_BUCKETS, 1e-9),
+        }
+    }
+}
+
+/// Histogram with fixed buckets.
+///
+/// Observations are integers in some base unit (like nanoseconds), and are
+/// exposed multiplied by `unit` (like `1e-9` to get seconds).
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    unit: f64,
+    /// Number of observations in each bucket, not cumulative; last bucket is
+    /// `+Inf`.
+    buckets: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64], unit: f64) -> Self {
+        Self {
+            bounds,
+            unit,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: u64) {
+        let scaled = value as f64 * self.unit;
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| scaled <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Write samples of the histogram; `labels` are prepended to `le` label.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut count = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let le = match self.bounds.get(index) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, le, count
+            );
+        }
+        let sum = self.sum.load(Ordering::Relaxed) as f64 * self.unit;
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+    }
+}
+
+/// Kind of channel whose depth is tracked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Queue {
+    /// Requests waiting for a dispatcher.
+    Request,
+    /// Responses waiting to be written to the connection.
+    Response,
+}
+
+impl Queue {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::Response => "response",
+        }
+    }
+}
+
+/// Channel whose depth is tracked; removed when the channel is closed.
+struct TrackedQueue {
+    queue: Queue,
+    /// Returns number of queued messages, or `None` if the channel is closed.
+    depth: Box<dyn Fn() -> Option<usize> + Send + Sync>,
+}
+
+impl std::fmt::Debug for TrackedQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedQueue")
+            .field("queue", &self.queue)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Counts a connection as closed when dropped.
+pub(crate) struct ConnectionGuard {
+    metrics: Metrics,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .registry
+            .connections_closed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Create new metrics, all set to zero.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record processing of request of ABCI `method` that took `elapsed` and
+    /// produced `response`.
+    pub(crate) fn record_request(&self, method: &str, response: &Response, elapsed: Duration) {
+        let Some(method) = self.registry.methods.get(method) else {
+            return;
+        };
+        method.requests.fetch_add(1, Ordering::Relaxed);
+        method
+            .latency
+            .observe(u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX));
+        if let Some(response::Value::Exception(_)) = response.value {
+            method.exceptions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record size of request frame read from a connection.
+    pub(crate) fn record_request_frame(&self, bytes: usize) {
+        self.registry.request_frames.observe(bytes as u64);
+    }
+
+    /// Record size of response frame written to a connection.
+    pub(crate) fn record_response_frame(&self, bytes: usize) {
+        self.registry.response_frames.observe(bytes as u64);
+    }
+
+    /// Record accepted connection; it is counted as closed when returned
+    /// guard is dropped.
+    pub(crate) fn connection(&self) -> ConnectionGuard {
+        self.registry
+            .connections_accepted
+            .fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Include messages queued in channel of `sender` in depth of `queue`.
+    ///
+    /// The channel is tracked until all its senders are dropped.
+    pub(crate) fn track_queue<T: Send + 'static>(&self, queue: Queue, sender: &Sender<T>) {
+        let weak: WeakSender<T> = sender.downgrade();
+        let depth = move || {
+            weak.upgrade()
+                .map(|sender| sender.max_capacity() - sender.capacity())
+        };
+        let mut queues = self.queues();
+        queues.retain(|tracked| (tracked.depth)().is_some());
+        queues.push(TrackedQueue {
+            queue,
+            depth: Box::new(depth),
+        });
+    }
+
+    fn queues(&self) -> std::sync::MutexGuard<'_, Vec<TrackedQueue>> {
+        self.registry
+            .queues
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Number of messages currently queued in channels of `queue` kind.
+    fn queue_depth(&self, queue: Queue) -> usize {
+        self.queues()
+            .iter()
+            .filter(|tracked| tracked.queue == queue)
+            .filter_map(|tracked| (tracked.depth)())
+            .sum()
+    }
+
+    /// Render metrics in Prometheus text exposition format.
+    ///
+    /// Serve the result over HTTP with [CONTENT_TYPE] content type.
+    pub fn render(&self) -> String {
+        let registry = &self.registry;
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "abci_requests_total",
+            "counter",
+            "Number of processed ABCI requests.",
+        );
+        for (method, metrics) in &registry.methods {
+            let _ = writeln!(
+                out,
+                "abci_requests_total{{method=\"{}\"}} {}",
+                method,
+                metrics.requests.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "abci_exceptions_total",
+            "counter",
+            "Number of ABCI requests that resulted in an exception.",
+        );
+        for (method, metrics) in &registry.methods {
+            let _ = writeln!(
+                out,
+                "abci_exceptions_total{{method=\"{}\"}} {}",
+                method,
+                metrics.exceptions.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "abci_request_duration_seconds",
+            "histogram",
+            "Time of processing ABCI requests.",
+        );
+        for (method, metrics) in &registry.methods {
+            metrics.latency.render(
+                &mut out,
+                "abci_request_duration_seconds",
+                &format!("method=\"{}\"", method),
+            );
+        }
+
+        header(
+            &mut out,
+            "abci_request_frame_bytes",
+            "histogram",
+            "Size of request frames read from connections.",
+        );
+        registry
+            .request_frames
+            .render(&mut out, "abci_request_frame_bytes", "");
+
+        header(
+            &mut out,
+            "abci_response_frame_bytes",
+            "histogram",
+            "Size of response frames written to connections.",
+        );
+        registry
+            .response_frames
+            .render(&mut out, "abci_response_frame_bytes", "");
+
+        header(
+            &mut out,
+            "abci_connections_accepted_total",
+            "counter",
+            "Number of accepted connections.",
+        );
+        let _ = writeln!(
+            out,
+            "abci_connections_accepted_total {}",
+            registry.connections_accepted.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "abci_connections_closed_total",
+            "counter",
+            "Number of closed connections.",
+        );
+        let _ = writeln!(
+            out,
+            "abci_connections_closed_total {}",
+            registry.connections_closed.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "abci_queue_depth",
+            "gauge",
+            "Number of messages waiting in server queues.",
+        );
+        for queue in [Queue::Request, Queue::Response] {
+            let _ = writeln!(
+                out,
+                "abci_queue_depth{{queue=\"{}\"}} {}",
+                queue.label(),
+                self.queue_depth(queue)
+            );
+        }
+
+        out
+    }
+
+    /// Serve metrics over HTTP on `listener` until `cancel` is cancelled.
+    ///
+    /// Responds to `GET /metrics` with output of [Metrics::render()], and to
+    /// other requests with `404 Not Found`. Connections that do not complete
+    /// the exchange within 10 seconds are closed, and all connections are
+    /// closed when `cancel` is cancelled.
+    pub async fn serve(
+        self,
+        listener: TcpListener,
+        cancel: CancellationToken,
+    ) -> Result<(), Error> {
+        // Dropping the set aborts tasks of connections still being served.
+        let mut connections = JoinSet::new();
+        loop {
+            let (stream, address) = tokio::select! {
+                conn = listener.accept() => conn?,
+                Some(_) = connections.join_next() => continue,
+                _ = cancel.cancelled() => return Ok(()),
+            };
+            let metrics = self.clone();
+            connections.spawn(async move {
+                if let Err(error) = metrics.respond(stream).await {
+                    tracing::debug!(?address, ?error, "cannot serve metrics");
+                }
+            });
+        }
+    }
+
+    /// Respond to single HTTP request, within [HTTP_TIMEOUT].
+    async fn respond(&self, stream: TcpStream) -> Result<(), Error> {
+        tokio::time::timeout(HTTP_TIMEOUT, self.exchange(stream))
+            .await
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "metrics request timed out")
+            })?
+    }
+
+    /// Read HTTP request from `stream` and write the response.
+    async fn exchange(&self, mut stream: TcpStream) -> Result<(), Error> {
+        let mut head = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            head.extend_from_slice(&buf[..read]);
+            if head.len() > MAX_HTTP_REQUEST {
+                break;
+            }
+        }
+
+        let request_line = head.split(|byte| *byte == b'\n').next().unwrap_or_default();
+        let mut parts = request_line.split(|byte| *byte == b' ');
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some(b"GET"), Some(b"/metrics")) => ("200 OK", self.render()),
+            _ => ("404 Not Found", String::new()),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: \
+             close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Write `HELP` and `TYPE` lines of metric `name`.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use super::{Metrics, Queue};
+    use crate::{
+        proto::abci::{response, Response, ResponseEcho, ResponseException},
+        CancellationToken,
+    };
+
+    #[tokio::test]
+    /// Given recorded requests and queued messages, when metrics are
+    /// rendered, then they are reported in Prometheus text format.
+    async fn test_render() {
+        let metrics = Metrics::new();
+        let echo = Response {
+            value: Some(response::Value::Echo(ResponseEcho::default())),
+        };
+        let exception = Response {
+            value: Some(response::Value::Exception(ResponseException::default())),
+        };
+        metrics.record_request("Echo", &echo, Duration::from_millis(3));
+        metrics.record_request("Echo", &exception, Duration::from_secs(20));
+        metrics.record_request_frame(100);
+        let guard = metrics.connection();
+        drop(guard);
+        let _guard = metrics.connection();
+
+        let (tx, _rx) = mpsc::channel::<u8>(4);
+        metrics.track_queue(Queue::Request, &tx);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+
+        let rendered = metrics.render();
+        for line in [
+            "# TYPE abci_requests_total counter",
+            "abci_requests_total{method=\"Echo\"} 2",
+            "abci_requests_total{method=\"Info\"} 0",
+            "abci_exceptions_total{method=\"Echo\"} 1",
+            "# TYPE abci_request_duration_seconds histogram",
+            "abci_request_duration_seconds_bucket{method=\"Echo\",le=\"0.0025\"} 0",
+            "abci_request_duration_seconds_bucket{method=\"Echo\",le=\"0.005\"} 1",
+            "abci_request_duration_seconds_bucket{method=\"Echo\",le=\"10\"} 1",
+            "abci_request_duration_seconds_bucket{method=\"Echo\",le=\"+Inf\"} 2",
+            "abci_request_duration_seconds_count{method=\"Echo\"} 2",
+            "abci_request_frame_bytes_bucket{le=\"256\"} 1",
+            "abci_request_frame_bytes_count 1",
+            "abci_response_frame_bytes_count 0",
+            "abci_connections_accepted_total 2",
+            "abci_connections_closed_total 1",
+            "abci_queue_depth{queue=\"request\"} 2",
+            "abci_queue_depth{queue=\"response\"} 0",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing {:?} in:\n{}",
+                line,
+                rendered
+            );
+        }
+
+        let sum = rendered
+            .lines()
+            .find_map(|l| l.strip_prefix("abci_request_duration_seconds_sum{method=\"Echo\"} "))
+            .expect("latency sum");
+        assert!(
+            (sum.parse::<f64>().unwrap() - 20.003).abs() < 1e-6,
+            "{}",
+            sum
+        );
+
+        drop(tx);
+        assert!(metrics
+            .render()
+            .lines()
+            .any(|l| l == "abci_queue_depth{queue=\"request\"} 0"));
+    }
+
+    #[tokio::test]
+    /// Given a metrics connection that sends no request, when the listener is
+    /// cancelled, then it stops and the connection is closed.
+    async fn test_serve_cancel_closes_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let cancel = CancellationToken::new();
+        let serve = tokio::spawn(Metrics::new().serve(listener, cancel.clone()));
+
+        let mut idle = TcpStream::connect(address).await.unwrap();
+        // Let the listener accept the connection
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.cancel();
+        serve.await.unwrap().expect("serve failed");
+
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(1), idle.read(&mut buf))
+            .await
+            .expect("connection still open");
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    }
+}
@@ -0,0 +1,127 @@
+//! Capture of panics raised by request dispatchers.
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+    task::Poll,
+};
+
+/// Action taken after a request dispatcher panicked.
+///
+/// Configured with
+/// [`ServerBuilder::with_panic_policy()`](super::ServerBuilder::with_panic_policy()).
+/// In both cases, the panic is logged together with its backtrace, and the
+/// client receives `ResponseException` with the ABCI method and request
+/// identifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Continue processing requests.
+    Continue,
+    /// Cancel the server using its
+    /// [CancellationToken](super::CancellationToken), as the state of the
+    /// application may be inconsistent.
+    Cancel,
+}
+
+/// Panic caught by [catch()] or [catch_async()].
+pub(crate) struct CaughtPanic {
+    pub(crate) message: String,
+    pub(crate) backtrace: Option<Backtrace>,
+}
+
+thread_local! {
+    /// Set while a panic raised on this thread will be caught.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    /// Backtrace of the last caught panic.
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Install panic hook that captures backtraces of panics that will be caught,
+/// and delegates other panics to the previous hook.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CAPTURING.get() {
+                BACKTRACE.set(Some(Backtrace::force_capture()));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Run `f` with panic capture enabled on this thread.
+fn capturing<R>(f: impl FnOnce() -> R) -> Result<R, CaughtPanic> {
+    let outer = CAPTURING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CAPTURING.set(outer);
+
+    result.map_err(|payload| CaughtPanic {
+        message: panic_message(payload.as_ref()),
+        backtrace: BACKTRACE.take(),
+    })
+}
+
+/// Run `f`, catching its panic.
+pub(crate) fn catch<R>(f: impl FnOnce() -> R) -> Result<R, CaughtPanic> {
+    install_hook();
+    capturing(f)
+}
+
+/// Await `future`, catching its panic.
+pub(crate) async fn catch_async<F: Future>(future: F) -> Result<F::Output, CaughtPanic> {
+    install_hook();
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(move |cx| match capturing(|| future.as_mut().poll(cx)) {
+        Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(caught) => Poll::Ready(Err(caught)),
+    })
+    .await
+}
+
+/// Message of panic `payload`, if it is a string.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catch, catch_async};
+
+    #[test]
+    /// Given a function that panics, when it is run by catch(), then the panic
+    /// message and backtrace are returned.
+    fn test_catch() {
+        assert_eq!(catch(|| 42).ok(), Some(42));
+
+        let caught = catch(|| panic!("boom {}", 1)).expect_err("panic not caught");
+        assert_eq!(caught.message, "boom 1");
+        assert!(caught.backtrace.is_some());
+    }
+
+    #[tokio::test]
+    /// Given a future that panics after await point, when it is run by
+    /// catch_async(), then the panic is caught.
+    async fn test_catch_async() {
+        let caught = catch_async(async {
+            tokio::task::yield_now().await;
+            panic!("async boom");
+        })
+        .await
+        .expect_err("panic not caught");
+        assert_eq!(caught.message, "async boom");
+    }
+}
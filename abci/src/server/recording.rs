@@ -0,0 +1,584 @@
+//! Recording and replay of ABCI sessions.
+//!
+//! [Recorder] wraps a dispatcher and writes every request, together with the
+//! response of the application, to a file. [Replayer] feeds recorded requests
+//! into another dispatcher, like a build of the application with debug
+//! logging, and reports the first response that differs from the recording.
+//! This helps to reproduce app hash divergence observed on a live network.
+//!
+//! Recording is a sequence of [Record]s, each preceded by its length encoded
+//! as varint, the same framing as used by the ABCI socket protocol.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tenderdash_abci::{recording::Replayer, Application, Recorder, ServerBuilder};
+//!
+//! struct MyApp;
+//! impl Application for MyApp {}
+//!
+//! // Record session of the application
+//! let app = Recorder::create(MyApp, "/tmp/abci.rec").expect("cannot create recording");
+//! let server = ServerBuilder::new(app, "unix:///tmp/abci.sock")
+//!     .build()
+//!     .expect("server failed");
+//! server.next_client().ok();
+//!
+//! // Replay it later
+//! let replayer = Replayer::open("/tmp/abci.rec").expect("cannot read recording");
+//! if let Some(divergence) = replayer.replay(&MyApp) {
+//!     println!("{}", divergence);
+//! }
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Display},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::{Buf, BufMut};
+use tenderdash_proto::prost::{
+    encoding::{message, skip_field, uint64, DecodeContext, WireType},
+    DecodeError, Message,
+};
+
+use super::codec::current_connection_id;
+use crate::{
+    application::method_name,
+    proto::{
+        abci::{Request, Response},
+        google::protobuf::Timestamp,
+    },
+    AsyncRequestDispatcher, Error, RequestDispatcher,
+};
+
+/// Request processed by the application, together with its response.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    /// Identifier of the connection that sent the request; 0 when the
+    /// request was not received by the server.
+    pub connection_id: u64,
+    /// Time when the request was dispatched.
+    pub time: Option<Timestamp>,
+    /// Request sent to the application.
+    pub request: Option<Request>,
+    /// Response of the application; `None` when it was shutting down.
+    pub response: Option<Response>,
+}
+
+impl Message for Record {
+    fn encode_raw(&self, buf: &mut impl BufMut) {
+        if self.connection_id != 0 {
+            uint64::encode(1, &self.connection_id, buf);
+        }
+        if let Some(time) = &self.time {
+            message::encode(2, time, buf);
+        }
+        if let Some(request) = &self.request {
+            message::encode(3, request, buf);
+        }
+        if let Some(response) = &self.response {
+            message::encode(4, response, buf);
+        }
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut impl Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => uint64::merge(wire_type, &mut self.connection_id, buf, ctx),
+            2 => message::merge(
+                wire_type,
+                self.time.get_or_insert_with(Default::default),
+                buf,
+                ctx,
+            ),
+            3 => message::merge(
+                wire_type,
+                self.request.get_or_insert_with(Default::default),
+                buf,
+                ctx,
+            ),
+            4 => message::merge(
+                wire_type,
+                self.response.get_or_insert_with(Default::default),
+                buf,
+                ctx,
+            ),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if self.connection_id != 0 {
+            len += uint64::encoded_len(1, &self.connection_id);
+        }
+        len += self
+            .time
+            .as_ref()
+            .map_or(0, |time| message::encoded_len(2, time));
+        len += self
+            .request
+            .as_ref()
+            .map_or(0, |request| message::encoded_len(3, request));
+        len += self
+            .response
+            .as_ref()
+            .map_or(0, |response| message::encoded_len(4, response));
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Dispatcher that records all requests and responses of the wrapped
+/// dispatcher.
+///
+/// Works with both [RequestDispatcher]s and [AsyncRequestDispatcher]s. Each
+/// record is written and flushed as soon as the response is produced, so the
+/// recording survives a crash of the application. Write errors are logged and
+/// do not affect processing of requests.
+pub struct Recorder<D> {
+    inner: D,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl<D> Recorder<D> {
+    /// Record session of `inner` dispatcher to `output`.
+    pub fn new(inner: D, output: impl Write + Send + 'static) -> Self {
+        Self {
+            inner,
+            output: Mutex::new(Box::new(output)),
+        }
+    }
+
+    /// Record session of `inner` dispatcher to a new file at `path`.
+    pub fn create(inner: D, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            Error::Configuration(format!("cannot create recording {}: {}", path.display(), e))
+        })?;
+
+        Ok(Self::new(inner, BufWriter::new(file)))
+    }
+
+    /// Wrapped dispatcher.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    fn record(&self, record: Record) {
+        let encoded = record.encode_length_delimited_to_vec();
+        let mut output = self
+            .output
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Err(error) = output.write_all(&encoded).and_then(|_| output.flush()) {
+            tracing::error!(?error, "cannot write ABCI session recording");
+        }
+    }
+}
+
+impl<D: RequestDispatcher> RequestDispatcher for Recorder<D> {
+    fn handle(&self, request: Request) -> Option<Response> {
+        let connection_id = current_connection_id().unwrap_or_default();
+        let time = now();
+        let response = self.inner.handle(request.clone());
+
+        self.record(Record {
+            connection_id,
+            time: Some(time),
+            request: Some(request),
+            response: response.clone(),
+        });
+        response
+    }
+}
+
+#[async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for Recorder<D> {
+    async fn handle(&self, request: Request) -> Option<Response> {
+        let connection_id = current_connection_id().unwrap_or_default();
+        let time = now();
+        let response = self.inner.handle(request.clone()).await;
+
+        self.record(Record {
+            connection_id,
+            time: Some(time),
+            request: Some(request),
+            response: response.clone(),
+        });
+        response
+    }
+}
+
+/// Current time as protobuf timestamp.
+fn now() -> Timestamp {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Timestamp {
+        seconds: since_epoch.as_secs() as i64,
+        nanos: since_epoch.subsec_nanos() as i32,
+    }
+}
+
+/// Replays recorded ABCI session.
+///
+/// Requests are sent to the dispatcher one by one, in the recorded order.
+#[derive(Clone, Debug, Default)]
+pub struct Replayer {
+    records: Vec<Record>,
+}
+
+impl Replayer {
+    /// Read recording from file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| {
+            Error::Configuration(format!("cannot read recording {}: {}", path.display(), e))
+        })?;
+
+        Self::decode(data.as_slice())
+    }
+
+    /// Decode recording from `data`.
+    pub fn decode(mut data: impl Buf) -> Result<Self, Error> {
+        let mut records = Vec::new();
+        while data.has_remaining() {
+            records.push(Record::decode_length_delimited(&mut data)?);
+        }
+
+        Ok(Self { records })
+    }
+
+    /// Recorded requests and responses.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Replay the recording with `dispatcher`.
+    ///
+    /// Stops at the first response that differs from the recorded one and
+    /// returns details of the difference. Returns `None` if all responses
+    /// match.
+    pub fn replay<D: RequestDispatcher>(&self, dispatcher: &D) -> Option<Divergence> {
+        self.requests().find_map(|(index, record, request)| {
+            Divergence::check(index, record, dispatcher.handle(request))
+        })
+    }
+
+    /// Async version of [Replayer::replay()].
+    pub async fn replay_async<D: AsyncRequestDispatcher>(
+        &self,
+        dispatcher: &D,
+    ) -> Option<Divergence> {
+        for (index, record, request) in self.requests() {
+            let divergence = Divergence::check(index, record, dispatcher.handle(request).await);
+            if divergence.is_some() {
+                return divergence;
+            }
+        }
+
+        None
+    }
+
+    /// Records that contain request, with their indexes.
+    fn requests(&self) -> impl Iterator<Item = (usize, &Record, Request)> {
+        self.records
+            .iter()
+            .enumerate()
+            .filter_map(|(index, record)| Some((index, record, record.request.clone()?)))
+    }
+}
+
+/// Response that differs from the recorded one, returned by [Replayer].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Index of the record in the recording.
+    pub index: usize,
+    /// Recorded request and response.
+    pub record: Record,
+    /// Response returned during replay.
+    pub actual: Option<Response>,
+    /// Fields that differ.
+    pub fields: Vec<FieldDiff>,
+}
+
+impl Divergence {
+    fn check(index: usize, record: &Record, actual: Option<Response>) -> Option<Self> {
+        if record.response == actual {
+            return None;
+        }
+
+        Some(Self {
+            index,
+            fields: diff(&record.response, &actual),
+            record: record.clone(),
+            actual,
+        })
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method = self
+            .record
+            .request
+            .as_ref()
+            .and_then(|request| request.value.as_ref())
+            .map_or("empty request", method_name);
+        write!(
+            f,
+            "response #{} to {} (connection {}) differs",
+            self.index, method, self.record.connection_id
+        )?;
+        for field in &self.fields {
+            write!(f, "\n  {}", field)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Field of a response that differs from the recorded one.
+///
+/// Values are formatted with [Debug].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Path to the field, like `value.FinalizeBlock.app_hash`.
+    pub path: String,
+    /// Recorded value; `None` if the field was not present.
+    pub expected: Option<String>,
+    /// Value returned during replay; `None` if the field is not present.
+    pub actual: Option<String>,
+}
+
+impl Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.path,
+            self.expected.as_deref().unwrap_or("<missing>"),
+            self.actual.as_deref().unwrap_or("<missing>")
+        )
+    }
+}
+
+/// Compare fields of `expected` and `actual`.
+fn diff<T: Debug>(expected: &T, actual: &T) -> Vec<FieldDiff> {
+    let expected = flatten(expected);
+    let actual = flatten(actual);
+    let expected_values = expected.iter().cloned().collect::<BTreeMap<_, _>>();
+    let actual_values = actual.iter().cloned().collect::<BTreeMap<_, _>>();
+
+    let mut fields = Vec::new();
+    for (path, value) in expected {
+        let actual = actual_values.get(&path);
+        if actual != Some(&value) {
+            fields.push(FieldDiff {
+                path,
+                expected: Some(value),
+                actual: actual.cloned(),
+            });
+        }
+    }
+    for (path, value) in actual {
+        if !expected_values.contains_key(&path) {
+            fields.push(FieldDiff {
+                path,
+                expected: None,
+                actual: Some(value),
+            });
+        }
+    }
+
+    fields
+}
+
+/// Flatten `value` into `(path, value)` pairs of its fields.
+///
+/// Parses pretty-printed [Debug] output, so it works for any message without
+/// reflection. Lists of scalars, like byte arrays, are kept as one value.
+fn flatten<T: Debug>(value: &T) -> Vec<(String, String)> {
+    /// Struct, list or tuple being parsed.
+    struct Scope {
+        path: String,
+        list: bool,
+        items: usize,
+        /// Index of the first field of the scope.
+        start: usize,
+        nested: bool,
+    }
+
+    let text = format!("{:#?}", value);
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut scopes = vec![Scope {
+        path: String::new(),
+        list: false,
+        items: 0,
+        start: 0,
+        nested: false,
+    }];
+
+    for line in text.lines() {
+        let line = line.trim().trim_end_matches(',');
+        if matches!(line, "}" | "]" | ")") {
+            let Some(scope) = scopes.pop() else { break };
+            if scope.list && !scope.nested && fields.len() > scope.start {
+                let items = fields
+                    .drain(scope.start..)
+                    .map(|(_, item)| item)
+                    .collect::<Vec<_>>();
+                fields.push((scope.path, format!("[{}]", items.join(", "))));
+            }
+            continue;
+        }
+
+        let Some(parent) = scopes.last_mut() else {
+            break;
+        };
+        let (path, value) = if parent.list {
+            parent.items += 1;
+            (format!("{}[{}]", parent.path, parent.items - 1), line)
+        } else {
+            match line.split_once(": ") {
+                Some((name, value)) if is_field_name(name) => (join(&parent.path, name), value),
+                _ => (parent.path.clone(), line),
+            }
+        };
+
+        let opened = if value.ends_with(" {") {
+            Some((path, false))
+        } else if value == "[" {
+            Some((path, true))
+        } else if let Some(name) = value.strip_suffix('(') {
+            // `Some` is transparent; enum variants become part of the path
+            match name {
+                "Some" => Some((path, false)),
+                _ => Some((join(&path, name), false)),
+            }
+        } else {
+            fields.push((path, value.to_string()));
+            None
+        };
+
+        if let Some((path, list)) = opened {
+            parent.nested = true;
+            scopes.push(Scope {
+                path,
+                list,
+                items: 0,
+                start: fields.len(),
+                nested: false,
+            });
+        }
+    }
+
+    fields
+}
+
+fn is_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '#')
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::abci::{response, ResponseFinalizeBlock, ResponseInfo};
+
+    #[test]
+    fn test_record_roundtrip() {
+        let record = Record {
+            connection_id: 7,
+            time: Some(Timestamp {
+                seconds: 1,
+                nanos: 2,
+            }),
+            request: Some(Request {
+                value: Some(crate::proto::abci::request::Value::Info(Default::default())),
+            }),
+            response: None,
+        };
+
+        let mut encoded = Vec::new();
+        record.encode_length_delimited(&mut encoded).unwrap();
+        record.encode_length_delimited(&mut encoded).unwrap();
+
+        let replayer = Replayer::decode(encoded.as_slice()).unwrap();
+        assert_eq!(replayer.records(), &[record.clone(), record]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let expected = Some(Response {
+            value: Some(response::Value::FinalizeBlock(ResponseFinalizeBlock {
+                retain_height: 1,
+                ..Default::default()
+            })),
+        });
+        let actual = Some(Response {
+            value: Some(response::Value::FinalizeBlock(ResponseFinalizeBlock {
+                retain_height: 2,
+                ..Default::default()
+            })),
+        });
+
+        assert_eq!(
+            diff(&expected, &actual),
+            vec![FieldDiff {
+                path: "value.FinalizeBlock.retain_height".to_string(),
+                expected: Some("1".to_string()),
+                actual: Some("2".to_string()),
+            }]
+        );
+
+        let expected = Some(Response {
+            value: Some(response::Value::Info(ResponseInfo {
+                last_block_app_hash: vec![1, 2, 3],
+                ..Default::default()
+            })),
+        });
+        let actual = Some(Response {
+            value: Some(response::Value::Info(ResponseInfo {
+                last_block_app_hash: vec![1, 2, 4],
+                ..Default::default()
+            })),
+        });
+
+        assert_eq!(
+            diff(&expected, &actual),
+            vec![FieldDiff {
+                path: "value.Info.last_block_app_hash".to_string(),
+                expected: Some("[1, 2, 3]".to_string()),
+                actual: Some("[1, 2, 4]".to_string()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Run loop of the server, accepting clients one after another.
+use std::{
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+/// Default delay before accepting next client after the first error.
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// Default maximum delay before accepting next client after errors.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How long [`Server::serve()`](super::Server::serve()) keeps accepting new
+/// clients after a client disconnects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reconnect {
+    /// Serve only one client; return when it disconnects.
+    Never,
+    /// Accept new clients until the server is cancelled.
+    Always,
+    /// Accept new clients for given time since serving started.
+    For(Duration),
+}
+
+/// Policy of [`Server::serve()`](super::Server::serve()) and
+/// [`AsyncServer::serve()`](super::AsyncServer::serve()).
+///
+/// By default, new clients are accepted until the server is cancelled, errors
+/// are logged and retried without limit, and retries are delayed starting
+/// from 100 ms, doubled after each consecutive error up to 5 seconds.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use tenderdash_abci::{Reconnect, ServePolicy};
+///
+/// let policy = ServePolicy::new()
+///     .with_reconnect(Reconnect::For(Duration::from_secs(3600)))
+///     .with_max_consecutive_errors(5)
+///     .with_backoff(Duration::from_millis(50), Duration::from_secs(1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServePolicy {
+    reconnect: Reconnect,
+    max_consecutive_errors: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for ServePolicy {
+    fn default() -> Self {
+        Self {
+            reconnect: Reconnect::Always,
+            max_consecutive_errors: None,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+impl ServePolicy {
+    /// Create default policy.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set how long new clients are accepted.
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Stop serving and return the error when `next_client()` fails `limit`
+    /// times in a row.
+    pub fn with_max_consecutive_errors(mut self, limit: u32) -> Self {
+        self.max_consecutive_errors = Some(limit);
+        self
+    }
+
+    /// Delay accepting next client by `initial` after an error, doubling the
+    /// delay after each consecutive error up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Start serving using this policy.
+    pub(crate) fn start(self) -> ServeLoop {
+        ServeLoop {
+            started: Instant::now(),
+            errors: 0,
+            backoff: self.initial_backoff,
+            policy: self,
+        }
+    }
+}
+
+/// State of the run loop.
+pub(crate) struct ServeLoop {
+    policy: ServePolicy,
+    started: Instant,
+    /// Number of consecutive errors.
+    errors: u32,
+    /// Delay after next error.
+    backoff: Duration,
+}
+
+impl ServeLoop {
+    /// Decide what to do after `next_client()` returned `result`.
+    ///
+    /// Returns delay before accepting next client, or result of the run loop.
+    pub(crate) fn next(
+        &mut self,
+        result: Result<(), Error>,
+    ) -> ControlFlow<Result<(), Error>, Duration> {
+        let delay = match result {
+            Ok(()) => {
+                self.errors = 0;
+                self.backoff = self.policy.initial_backoff;
+                Duration::ZERO
+            },
+            Err(Error::Cancelled()) => {
+                tracing::info!("server cancelled, stopping");
+                return ControlFlow::Break(Ok(()));
+            },
+            Err(error @ Error::DrainTimedOut) => return ControlFlow::Break(Err(error)),
+            Err(error) => {
+                self.errors += 1;
+                if self
+                    .policy
+                    .max_consecutive_errors
+                    .is_some_and(|limit| self.errors >= limit)
+                {
+                    tracing::error!(?error, errors = self.errors, "too many errors, stopping");
+                    return ControlFlow::Break(Err(error));
+                }
+                if !self.reconnect_allowed() {
+                    return ControlFlow::Break(Err(error));
+                }
+                tracing::warn!(?error, delay = ?self.backoff, "client failed, retrying");
+                let delay = self.backoff;
+                self.backoff = (self.backoff * 2).min(self.policy.max_backoff);
+                return ControlFlow::Continue(delay);
+            },
+        };
+
+        if self.reconnect_allowed() {
+            ControlFlow::Continue(delay)
+        } else {
+            tracing::info!("client disconnected, not accepting new clients");
+            ControlFlow::Break(Ok(()))
+        }
+    }
+
+    fn reconnect_allowed(&self) -> bool {
+        match self.policy.reconnect {
+            Reconnect::Never => false,
+            Reconnect::Always => true,
+            Reconnect::For(duration) => self.started.elapsed() < duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, io, time::Duration};
+
+    use super::{Reconnect, ServePolicy};
+    use crate::{Error, Server};
+
+    /// Server that returns prepared results of `next_client()`.
+    struct MockServer(RefCell<VecDeque<Result<(), Error>>>);
+
+    impl MockServer {
+        fn new(results: Vec<Result<(), Error>>) -> Self {
+            Self(RefCell::new(results.into()))
+        }
+
+        fn remaining(&self) -> usize {
+            self.0.borrow().len()
+        }
+    }
+
+    impl Server for MockServer {
+        fn next_client(&self) -> Result<(), Error> {
+            self.0
+                .borrow_mut()
+                .pop_front()
+                .expect("unexpected next_client() call")
+        }
+    }
+
+    fn failure() -> Result<(), Error> {
+        Err(Error::Connection(io::Error::other("failure")))
+    }
+
+    fn policy() -> ServePolicy {
+        ServePolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(2))
+    }
+
+    #[test]
+    /// Given clients that disconnect or fail, when the server is cancelled,
+    /// then serve() returns Ok.
+    fn test_serve_until_cancelled() {
+        let server = MockServer::new(vec![Ok(()), failure(), Ok(()), Err(Error::Cancelled())]);
+        server.serve(policy()).expect("serve failed");
+        assert_eq!(server.remaining(), 0);
+    }
+
+    #[test]
+    /// Given consecutive errors limit, when the limit is reached, then serve()
+    /// returns the last error; successful client resets the counter.
+    fn test_max_consecutive_errors() {
+        let server = MockServer::new(vec![failure(), Ok(()), failure(), failure(), Ok(())]);
+        let result = server.serve(policy().with_max_consecutive_errors(2));
+        assert!(matches!(result, Err(Error::Connection(_))), "{:?}", result);
+        assert_eq!(server.remaining(), 1);
+    }
+
+    #[test]
+    /// Given Reconnect::Never, when the first client disconnects, then serve()
+    /// returns Ok.
+    fn test_reconnect_never() {
+        let server = MockServer::new(vec![Ok(()), Ok(())]);
+        server
+            .serve(policy().with_reconnect(Reconnect::Never))
+            .expect("serve failed");
+        assert_eq!(server.remaining(), 1);
+    }
+
+    #[test]
+    /// Given cancellation with lost responses, when serving, then the error is
+    /// returned.
+    fn test_cancelled_not_drained() {
+        let server = MockServer::new(vec![Err(Error::DrainTimedOut)]);
+        assert!(matches!(server.serve(policy()), Err(Error::DrainTimedOut)));
+    }
+}
@@ -0,0 +1,71 @@
+//! Throughput counters of ABCI connections.
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Counters of requests and responses processed by the server.
+///
+/// The counters are shared between clones, so a clone passed to
+/// [`ServerBuilder::with_io_stats()`](super::ServerBuilder::with_io_stats())
+/// can be used to read them while the server is running. Counters are
+/// cumulative for all connections.
+///
+/// Comparing [IoStats::flushes()] with [IoStats::responses()] shows how well
+/// responses are batched; see
+/// [`ServerBuilder::with_response_batching()`](super::ServerBuilder::with_response_batching()).
+#[derive(Clone, Debug, Default)]
+pub struct IoStats {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicU64,
+    responses: AtomicU64,
+    bytes_written: AtomicU64,
+    flushes: AtomicU64,
+}
+
+impl IoStats {
+    /// Create new counters, all set to zero.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of requests received.
+    pub fn requests(&self) -> u64 {
+        self.counters.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of responses written.
+    pub fn responses(&self) -> u64 {
+        self.counters.responses.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes of encoded responses written, including length
+    /// delimiters.
+    pub fn bytes_written(&self) -> u64 {
+        self.counters.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the connection was flushed.
+    pub fn flushes(&self) -> u64 {
+        self.counters.flushes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_request(&self) {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_response(&self, bytes: usize) {
+        self.counters.responses.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_flush(&self) {
+        self.counters.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,180 @@
+//! TLS support for the TCP transport.
+//!
+//! Re-exports [rustls] so that custom [rustls::ServerConfig] can be passed to
+//! [`TlsConfig::new()`].
+use std::{fmt::Debug, fs, io, path::Path, sync::Arc, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+pub use tokio_rustls::rustls;
+use tokio_rustls::{
+    rustls::{
+        crypto::{ring, CryptoProvider},
+        pki_types::CertificateDer,
+        server::WebPkiClientVerifier,
+        RootCertStore,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::Error;
+
+/// Default maximum time of TLS handshake.
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS configuration of the ABCI server.
+///
+/// Used with `tls://` bind addresses, configured with
+/// [`ServerBuilder::with_tls()`](super::ServerBuilder::with_tls()).
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tenderdash_abci::{Application, ServerBuilder, TlsConfig};
+///
+/// struct MyApp {}
+/// impl Application for MyApp {}
+///
+/// let tls = TlsConfig::from_pem_files(
+///     "/etc/abci/server.crt",
+///     "/etc/abci/server.key",
+///     Some(Path::new("/etc/abci/client-ca.crt")),
+/// )
+/// .expect("invalid TLS configuration");
+///
+/// let server = ServerBuilder::new(MyApp {}, "tls://0.0.0.0:26658")
+///     .with_tls(tls)
+///     .build()
+///     .expect("server failed");
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+}
+
+impl TlsConfig {
+    /// Create TLS configuration from custom [rustls::ServerConfig].
+    pub fn new(config: rustls::ServerConfig) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
+    }
+
+    /// Set maximum time of TLS handshake.
+    ///
+    /// Connections of clients that do not complete the handshake within
+    /// `timeout`, like health checks that only open a TCP connection, are
+    /// closed, so that they do not occupy connection slots of the server.
+    /// Defaults to 10 seconds.
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Create TLS configuration from PEM-encoded certificate chain and private
+    /// key of the server.
+    ///
+    /// When `client_ca` is provided, clients must authenticate with a
+    /// certificate signed by one of the PEM-encoded certificate authorities
+    /// it contains.
+    pub fn from_pem(cert: &[u8], key: &[u8], client_ca: Option<&[u8]>) -> Result<Self, Error> {
+        let provider = Arc::new(ring::default_provider());
+
+        let cert_chain = parse_certs(cert)?;
+        let key = rustls_pemfile::private_key(&mut &key[..])
+            .map_err(|e| Error::Configuration(format!("cannot parse TLS private key: {}", e)))?
+            .ok_or_else(|| Error::Configuration("TLS private key not found".to_string()))?;
+
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+
+        let builder = match client_ca {
+            Some(client_ca) => {
+                builder.with_client_cert_verifier(client_verifier(client_ca, provider)?)
+            },
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(cert_chain, key)
+            .map_err(tls_error)?;
+
+        Ok(Self::new(config))
+    }
+
+    /// Create TLS configuration from PEM files, as in
+    /// [`TlsConfig::from_pem()`].
+    pub fn from_pem_files<P: AsRef<Path>>(
+        cert: P,
+        key: P,
+        client_ca: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let client_ca = client_ca.map(read_file).transpose()?;
+
+        Self::from_pem(
+            &read_file(cert.as_ref())?,
+            &read_file(key.as_ref())?,
+            client_ca.as_deref(),
+        )
+    }
+
+    /// Perform TLS handshake on accepted stream, within the handshake
+    /// timeout.
+    pub(crate) async fn accept<S>(&self, stream: S) -> Result<TlsStream<S>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        tokio::time::timeout(self.handshake_timeout, self.acceptor.accept(stream))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+            .map_err(Error::from)
+    }
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Build verifier that accepts client certificates signed by `client_ca`.
+fn client_verifier(
+    client_ca: &[u8],
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in parse_certs(client_ca)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| Error::Configuration(format!("invalid TLS client CA: {}", e)))
+}
+
+fn parse_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::Configuration(format!("cannot parse TLS certificate: {}", e)))?;
+    if certs.is_empty() {
+        return Err(Error::Configuration(
+            "TLS certificate not found".to_string(),
+        ));
+    }
+
+    Ok(certs)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| Error::Configuration(format!("cannot read {:?}: {}", path, e)))
+}
+
+fn tls_error(error: rustls::Error) -> Error {
+    Error::Configuration(format!("invalid TLS configuration: {}", error))
+}
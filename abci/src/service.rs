@@ -0,0 +1,280 @@
+//! Interoperability with [tower](https://docs.rs/tower) services.
+//!
+//! [ApplicationService] and [BlockingApplicationService] adapt request
+//! dispatchers, like [AsyncApplication](crate::AsyncApplication) and
+//! [Application](crate::Application), to `tower::Service<abci::Request>`, so
+//! that tower middleware (timeouts, rate limiting, load shedding) can be put in
+//! front of them. [ServiceDispatcher] works the other way round, and serves any
+//! `tower::Service<abci::Request, Response = abci::Response>`; use
+//! [`ServerBuilder::from_service()`](crate::ServerBuilder::from_service()) to
+//! create a server for such a service.
+//!
+//! Errors returned by services are sent to the client as `ResponseException`,
+//! except [Error::DispatcherStopped], which closes the connection like
+//! dispatchers returning `None` do.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use tenderdash_abci::{service::ApplicationService, AsyncApplication, ServerBuilder};
+//! use tower::ServiceBuilder;
+//!
+//! struct MyApp {}
+//! impl AsyncApplication for MyApp {}
+//!
+//! let service = ServiceBuilder::new()
+//!     .timeout(Duration::from_secs(5))
+//!     .service(ApplicationService::new(MyApp {}));
+//! let server = ServerBuilder::from_service(service, "unix:///tmp/abci.sock")
+//!     .build_async()
+//!     .expect("server failed");
+//! ```
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use tower_service::Service;
+
+use crate::{
+    proto::abci::{response, Request, Response, ResponseException},
+    AsyncRequestDispatcher, Error, RequestContext, RequestDispatcher,
+};
+
+/// Type-erased error returned by tower services.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Future returned by services in this module.
+pub type ResponseFuture = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+/// `tower::Service` that processes requests with an
+/// [AsyncRequestDispatcher].
+#[derive(Debug)]
+pub struct ApplicationService<D> {
+    dispatcher: Arc<D>,
+}
+
+impl<D> ApplicationService<D> {
+    /// Create service that processes requests with `dispatcher`.
+    pub fn new(dispatcher: D) -> Self {
+        Self::from_arc(Arc::new(dispatcher))
+    }
+
+    /// Create service that processes requests with shared `dispatcher`.
+    pub fn from_arc(dispatcher: Arc<D>) -> Self {
+        Self { dispatcher }
+    }
+}
+
+impl<D> Clone for ApplicationService<D> {
+    fn clone(&self) -> Self {
+        Self::from_arc(self.dispatcher.clone())
+    }
+}
+
+impl<D: AsyncRequestDispatcher + 'static> Service<Request> for ApplicationService<D> {
+    type Response = Response;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let dispatcher = self.dispatcher.clone();
+        Box::pin(async move {
+            dispatcher
+                .handle(request)
+                .await
+                .ok_or(Error::DispatcherStopped)
+        })
+    }
+}
+
+/// `tower::Service` that processes requests with a [RequestDispatcher] on the
+/// blocking thread pool of the tokio runtime.
+///
+/// [RequestContext] current when the service is called remains current for
+/// the dispatcher.
+#[derive(Debug)]
+pub struct BlockingApplicationService<D> {
+    dispatcher: Arc<D>,
+}
+
+impl<D> BlockingApplicationService<D> {
+    /// Create service that processes requests with `dispatcher`.
+    pub fn new(dispatcher: D) -> Self {
+        Self::from_arc(Arc::new(dispatcher))
+    }
+
+    /// Create service that processes requests with shared `dispatcher`.
+    pub fn from_arc(dispatcher: Arc<D>) -> Self {
+        Self { dispatcher }
+    }
+}
+
+impl<D> Clone for BlockingApplicationService<D> {
+    fn clone(&self) -> Self {
+        Self::from_arc(self.dispatcher.clone())
+    }
+}
+
+impl<D> Service<Request> for BlockingApplicationService<D>
+where
+    D: RequestDispatcher + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let dispatcher = self.dispatcher.clone();
+        let context = RequestContext::current();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || match context {
+                Some(context) => context.scope(|| dispatcher.handle(request)),
+                None => dispatcher.handle(request),
+            })
+            .await
+            .map_err(|e| Error::Async(e.to_string()))?
+            .ok_or(Error::DispatcherStopped)
+        })
+    }
+}
+
+/// [AsyncRequestDispatcher] that processes requests with a `tower::Service`.
+///
+/// The service is cloned for each request, as tower services that can be
+/// shared (like `tower::buffer::Buffer`) expect. Errors of the service,
+/// including readiness errors, are converted to `ResponseException`.
+#[derive(Debug)]
+pub struct ServiceDispatcher<S> {
+    service: Mutex<S>,
+}
+
+impl<S> ServiceDispatcher<S> {
+    /// Create dispatcher that processes requests with `service`.
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Mutex::new(service),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> AsyncRequestDispatcher for ServiceDispatcher<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    async fn handle(&self, request: Request) -> Option<Response> {
+        let mut service = self
+            .service
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        if let Err(error) = poll_fn(|cx| service.poll_ready(cx)).await {
+            return exception(error.into());
+        }
+        match service.call(request).await {
+            Ok(response) => Some(response),
+            Err(error) => exception(error.into()),
+        }
+    }
+}
+
+/// Convert service `error` to a response.
+///
+/// Returns `None` if the error means that the dispatcher stopped.
+fn exception(error: BoxError) -> Option<Response> {
+    if let Some(Error::DispatcherStopped) = error.downcast_ref::<Error>() {
+        return None;
+    }
+
+    Some(Response {
+        value: Some(response::Value::Exception(ResponseException {
+            error: error.to_string(),
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_service::Service;
+
+    use super::{ApplicationService, BlockingApplicationService, ServiceDispatcher};
+    use crate::{
+        proto::abci::{request, response, Request, RequestEcho, Response},
+        Application, AsyncApplication, AsyncRequestDispatcher, RequestDispatcher,
+    };
+
+    struct App;
+    impl Application for App {}
+
+    struct AsyncApp;
+    impl AsyncApplication for AsyncApp {}
+
+    /// Dispatcher that stops on each request.
+    struct Stop;
+    impl RequestDispatcher for Stop {
+        fn handle(&self, _request: Request) -> Option<Response> {
+            None
+        }
+    }
+
+    fn echo(message: &str) -> Request {
+        Request {
+            value: Some(request::Value::Echo(RequestEcho {
+                message: message.to_string(),
+            })),
+        }
+    }
+
+    fn echo_message(response: Option<Response>) -> String {
+        match response.and_then(|r| r.value) {
+            Some(response::Value::Echo(echo)) => echo.message,
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    /// Given applications adapted to services, when the services are served
+    /// by ServiceDispatcher, then responses of the applications are returned.
+    async fn test_round_trip() {
+        let mut service = ApplicationService::new(AsyncApp);
+        let response = service.call(echo("direct")).await.expect("call failed");
+        assert_eq!(echo_message(Some(response)), "direct");
+
+        let dispatcher = ServiceDispatcher::new(service);
+        assert_eq!(
+            echo_message(dispatcher.handle(echo("async")).await),
+            "async"
+        );
+
+        let dispatcher = ServiceDispatcher::new(BlockingApplicationService::new(App));
+        assert_eq!(
+            echo_message(dispatcher.handle(echo("blocking")).await),
+            "blocking"
+        );
+    }
+
+    #[tokio::test]
+    /// Given a dispatcher that stops, when it is served by ServiceDispatcher,
+    /// then the ServiceDispatcher stops as well.
+    async fn test_dispatcher_stopped() {
+        let dispatcher = ServiceDispatcher::new(BlockingApplicationService::new(Stop));
+        assert!(dispatcher.handle(echo("stop")).await.is_none());
+    }
+}
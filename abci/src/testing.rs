@@ -0,0 +1,646 @@
+//! In-process Tenderdash simulator for integration tests.
+//!
+//! [Simulator] drives one or more [RequestDispatcher]s, acting as nodes of a
+//! network, through the same ABCI++ flows as Tenderdash: InitChain, CheckTx,
+//! consensus rounds of each height and Info handshake with block replay. It
+//! checks that all nodes agree on `app_hash`, so no Docker or Tenderdash
+//! binary is needed to test an application end to end.
+//!
+//! # Examples
+//!
+//! ```
+//! use tenderdash_abci::{
+//!     proto::abci::{self, response_process_proposal::ProposalStatus, tx_record::TxAction},
+//!     testing::Simulator,
+//!     Application,
+//! };
+//!
+//! /// Application accepting all transactions, with constant app hash.
+//! struct MyApp;
+//!
+//! impl Application for MyApp {
+//!     fn prepare_proposal(
+//!         &self,
+//!         request: abci::RequestPrepareProposal,
+//!     ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+//!         let tx_records = request
+//!             .txs
+//!             .into_iter()
+//!             .map(|tx| abci::TxRecord {
+//!                 action: TxAction::Unmodified.into(),
+//!                 tx,
+//!             })
+//!             .collect();
+//!         Ok(abci::ResponsePrepareProposal {
+//!             tx_records,
+//!             ..Default::default()
+//!         })
+//!     }
+//!
+//!     fn process_proposal(
+//!         &self,
+//!         _request: abci::RequestProcessProposal,
+//!     ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+//!         Ok(abci::ResponseProcessProposal {
+//!             status: ProposalStatus::Accept.into(),
+//!             ..Default::default()
+//!         })
+//!     }
+//! }
+//!
+//! let mut simulator = Simulator::new(vec![MyApp]).with_rounds(2);
+//! simulator.init_chain().expect("init chain failed");
+//! simulator.inject_tx(b"tx".to_vec());
+//! let block = simulator.run_height().expect("block not committed");
+//! assert_eq!(block.height, 1);
+//! assert_eq!(block.txs, vec![b"tx".to_vec()]);
+//! ```
+use crate::{
+    layer::DefaultLayers,
+    proto::{
+        abci::{
+            self, request, response, response_process_proposal::ProposalStatus,
+            response_verify_vote_extension::VerifyStatus, tx_record::TxAction,
+        },
+        google::protobuf::Timestamp,
+        types,
+    },
+    Error, Layer, RequestDispatcher,
+};
+
+/// Node of the network, borrowed by the [Simulator] to wrap it with
+/// [DefaultLayers].
+struct Node<'a, D>(&'a D);
+
+impl<D: RequestDispatcher> RequestDispatcher for Node<'_, D> {
+    fn handle(&self, request: abci::Request) -> Option<abci::Response> {
+        self.0.handle(request)
+    }
+}
+
+/// Default chain ID of simulated network.
+const DEFAULT_CHAIN_ID: &str = "test-chain";
+/// Time of the genesis block, in seconds since Unix epoch.
+const GENESIS_TIME: i64 = 1_700_000_000;
+
+/// Error returned by the [Simulator].
+#[derive(Debug, thiserror::Error)]
+pub enum SimulationError {
+    /// Simulated network failed to reach consensus at `height`.
+    #[error("simulation failed at height {height}: {reason}")]
+    Consensus { height: i64, reason: String },
+    /// Node returned an exception or an unexpected response.
+    #[error(transparent)]
+    Abci(#[from] Error),
+}
+
+/// Block committed by the [Simulator].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommittedBlock {
+    /// Height of the block.
+    pub height: i64,
+    /// Round in which the block was committed.
+    pub round: i32,
+    /// Index of the node that proposed the block.
+    pub proposer: usize,
+    /// Transactions included in the block.
+    pub txs: Vec<Vec<u8>>,
+    /// App hash agreed by all nodes after executing the block.
+    pub app_hash: Vec<u8>,
+}
+
+/// Simulated Tenderdash network that drives ABCI applications.
+///
+/// Each node of the network is a [RequestDispatcher], like an
+/// [Application](crate::Application). Proposers rotate between nodes with
+/// every height and round. Returns [SimulationError::Consensus] when nodes
+/// disagree, or an application rejects valid consensus data; exceptions
+/// returned by applications are reported as [Error::Exception] wrapped in
+/// [SimulationError::Abci]. Like in the server, requests are processed by
+/// nodes wrapped with [DefaultLayers], so exceptions are also logged as
+/// errors.
+pub struct Simulator<D: RequestDispatcher> {
+    nodes: Vec<D>,
+    chain_id: String,
+    initial_height: i64,
+    rounds: i32,
+    max_tx_bytes: i64,
+    /// App hash returned by InitChain.
+    genesis_app_hash: Option<Vec<u8>>,
+    /// Transactions waiting for inclusion in a block.
+    mempool: Vec<Vec<u8>>,
+    /// Committed blocks, used to replay them during handshake.
+    blocks: Vec<CommittedBlock>,
+}
+
+impl<D: RequestDispatcher> Simulator<D> {
+    /// Create simulator of a network consisting of `nodes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `nodes` is empty.
+    pub fn new(nodes: Vec<D>) -> Self {
+        assert!(!nodes.is_empty(), "simulator needs at least one node");
+        Self {
+            nodes,
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            initial_height: 1,
+            rounds: 1,
+            max_tx_bytes: 1024 * 1024,
+            genesis_app_hash: None,
+            mempool: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Set chain ID sent in InitChain.
+    pub fn with_chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = chain_id.to_string();
+        self
+    }
+
+    /// Set height of the first block.
+    pub fn with_initial_height(mut self, initial_height: i64) -> Self {
+        self.initial_height = initial_height;
+        self
+    }
+
+    /// Set number of rounds of each height.
+    ///
+    /// Proposals of all rounds but the last one are prepared and processed,
+    /// but the round fails as if votes were not collected in time. The block
+    /// is committed in the last round. Defaults to 1.
+    pub fn with_rounds(mut self, rounds: i32) -> Self {
+        self.rounds = rounds.max(1);
+        self
+    }
+
+    /// Set maximum size of transactions passed to PrepareProposal.
+    pub fn with_max_tx_bytes(mut self, max_tx_bytes: i64) -> Self {
+        self.max_tx_bytes = max_tx_bytes;
+        self
+    }
+
+    /// Nodes of the network.
+    pub fn nodes(&self) -> &[D] {
+        &self.nodes
+    }
+
+    /// Replace node at `index`, like after node restart, and return the old
+    /// one.
+    ///
+    /// Use [Simulator::handshake()] to bring the new node up to date.
+    pub fn replace_node(&mut self, index: usize, node: D) -> D {
+        std::mem::replace(&mut self.nodes[index], node)
+    }
+
+    /// Height of the last committed block; `initial_height - 1` before the
+    /// first block.
+    pub fn height(&self) -> i64 {
+        self.blocks
+            .last()
+            .map(|block| block.height)
+            .unwrap_or(self.initial_height - 1)
+    }
+
+    /// App hash after the last committed block, or genesis app hash.
+    pub fn app_hash(&self) -> Option<&[u8]> {
+        self.blocks
+            .last()
+            .map(|block| block.app_hash.as_slice())
+            .or(self.genesis_app_hash.as_deref())
+    }
+
+    /// Committed blocks.
+    pub fn blocks(&self) -> &[CommittedBlock] {
+        &self.blocks
+    }
+
+    /// Transactions waiting for inclusion in a block.
+    pub fn mempool(&self) -> &[Vec<u8>] {
+        &self.mempool
+    }
+
+    /// Send InitChain to all nodes and check that they return the same app
+    /// hash.
+    pub fn init_chain(&mut self) -> Result<abci::ResponseInitChain, SimulationError> {
+        let mut result: Option<abci::ResponseInitChain> = None;
+        for index in 0..self.nodes.len() {
+            let response = self.init_node(index)?;
+            if let Some(first) = &result {
+                self.check_app_hash(self.initial_height - 1, &first.app_hash, &response.app_hash)?;
+            }
+            result.get_or_insert(response);
+        }
+
+        let response = result.expect("simulator has at least one node");
+        self.genesis_app_hash = Some(response.app_hash.to_vec());
+        Ok(response)
+    }
+
+    fn init_node(&self, index: usize) -> Result<abci::ResponseInitChain, SimulationError> {
+        let request = abci::RequestInitChain {
+            time: Some(self.block_time(self.initial_height - 1)),
+            chain_id: self.chain_id.clone(),
+            initial_height: self.initial_height,
+            ..Default::default()
+        };
+        match self.call(index, request::Value::InitChain(request))? {
+            response::Value::InitChain(response) => Ok(response),
+            other => Err(unexpected_response(other)),
+        }
+    }
+
+    /// Send CheckTx with `tx` to all nodes.
+    ///
+    /// The transaction is added to the mempool if all nodes accept it.
+    /// Returns response of the first node.
+    pub fn check_tx(&mut self, tx: Vec<u8>) -> Result<abci::ResponseCheckTx, SimulationError> {
+        let mut result: Option<abci::ResponseCheckTx> = None;
+        let mut accepted = true;
+        for index in 0..self.nodes.len() {
+            let request = abci::RequestCheckTx {
+                tx: field(tx.clone()),
+                ..Default::default()
+            };
+            let response = match self.call(index, request::Value::CheckTx(request))? {
+                response::Value::CheckTx(response) => response,
+                other => return Err(unexpected_response(other)),
+            };
+            accepted &= response.code == 0;
+            result.get_or_insert(response);
+        }
+
+        if accepted {
+            self.mempool.push(tx);
+        }
+        Ok(result.expect("simulator has at least one node"))
+    }
+
+    /// Add `tx` to the mempool without checking it, like a transaction
+    /// received from a faulty peer.
+    pub fn inject_tx(&mut self, tx: Vec<u8>) {
+        self.mempool.push(tx);
+    }
+
+    /// Run consensus rounds of the next height and commit the block.
+    pub fn run_height(&mut self) -> Result<CommittedBlock, SimulationError> {
+        let height = self.height() + 1;
+
+        for round in 0..self.rounds {
+            let proposer = self.proposer(height, round);
+            let (txs, app_hash) = self.propose(height, round, proposer)?;
+
+            if round + 1 < self.rounds {
+                tracing::debug!(height, round, "simulated round failed");
+                continue;
+            }
+
+            let block = CommittedBlock {
+                height,
+                round,
+                proposer,
+                txs,
+                app_hash,
+            };
+            self.vote(&block)?;
+            for index in 0..self.nodes.len() {
+                self.finalize(index, &block)?;
+            }
+
+            self.mempool.retain(|tx| !block.txs.contains(tx));
+            self.blocks.push(block.clone());
+            return Ok(block);
+        }
+
+        unreachable!("at least one round is always run")
+    }
+
+    /// Run `heights` consecutive heights.
+    pub fn run(&mut self, heights: usize) -> Result<Vec<CommittedBlock>, SimulationError> {
+        (0..heights).map(|_| self.run_height()).collect()
+    }
+
+    /// Perform Info handshake with node at `index`, like Tenderdash does on
+    /// start.
+    ///
+    /// Node without any block is initialized with InitChain. Then, blocks
+    /// committed by the network but missing in the node are replayed, and
+    /// app hash of the node is checked. Returns Info response received
+    /// before the replay.
+    pub fn handshake(&mut self, index: usize) -> Result<abci::ResponseInfo, SimulationError> {
+        let request = abci::RequestInfo {
+            abci_version: crate::proto::ABCI_VERSION.to_string(),
+            ..Default::default()
+        };
+        let info = match self.call(index, request::Value::Info(request))? {
+            response::Value::Info(response) => response,
+            other => return Err(unexpected_response(other)),
+        };
+
+        let app_height = info.last_block_height;
+        if app_height > self.height() {
+            return Err(SimulationError::Consensus {
+                height: app_height,
+                reason: format!("node {} is ahead of the network", index),
+            });
+        }
+
+        if app_height < self.initial_height {
+            let response = self.init_node(index)?;
+            if let Some(genesis_app_hash) = &self.genesis_app_hash {
+                self.check_app_hash(app_height, genesis_app_hash, &response.app_hash)?;
+            }
+        } else if let Some(block) = self.block(app_height) {
+            self.check_app_hash(app_height, &block.app_hash, &info.last_block_app_hash)?;
+        }
+
+        let missing = self
+            .blocks
+            .iter()
+            .filter(|block| block.height > app_height)
+            .cloned()
+            .collect::<Vec<_>>();
+        for block in missing {
+            tracing::debug!(index, height = block.height, "replaying block");
+            let app_hash = self.process(index, &block)?;
+            self.check_app_hash(block.height, &block.app_hash, &app_hash)?;
+            self.finalize(index, &block)?;
+        }
+
+        Ok(info)
+    }
+
+    /// Prepare proposal on the proposer and process it on other nodes.
+    ///
+    /// Returns transactions of the proposed block and its app hash.
+    fn propose(
+        &self,
+        height: i64,
+        round: i32,
+        proposer: usize,
+    ) -> Result<(Vec<Vec<u8>>, Vec<u8>), SimulationError> {
+        let mut size = 0;
+        let txs = self
+            .mempool
+            .iter()
+            .take_while(|tx| {
+                size += tx.len() as i64;
+                size <= self.max_tx_bytes
+            })
+            .map(|tx| field(tx.clone()))
+            .collect();
+        let request = abci::RequestPrepareProposal {
+            max_tx_bytes: self.max_tx_bytes,
+            txs,
+            height,
+            round,
+            time: Some(self.block_time(height)),
+            proposer_pro_tx_hash: pro_tx_hash(proposer),
+            ..Default::default()
+        };
+        let prepared = match self.call(proposer, request::Value::PrepareProposal(request))? {
+            response::Value::PrepareProposal(response) => response,
+            other => return Err(unexpected_response(other)),
+        };
+
+        let txs = prepared
+            .tx_records
+            .iter()
+            .filter(|record| {
+                matches!(
+                    TxAction::try_from(record.action),
+                    Ok(TxAction::Unmodified) | Ok(TxAction::Added)
+                )
+            })
+            .map(|record| record.tx.to_vec())
+            .collect::<Vec<_>>();
+        let block = CommittedBlock {
+            height,
+            round,
+            proposer,
+            txs,
+            app_hash: prepared.app_hash.to_vec(),
+        };
+
+        for index in (0..self.nodes.len()).filter(|index| *index != proposer) {
+            let app_hash = self.process(index, &block)?;
+            self.check_app_hash(height, &block.app_hash, &app_hash)?;
+        }
+
+        Ok((block.txs, block.app_hash))
+    }
+
+    /// Send ProcessProposal with `block` to node at `index`; returns app hash.
+    fn process(&self, index: usize, block: &CommittedBlock) -> Result<Vec<u8>, SimulationError> {
+        let request = abci::RequestProcessProposal {
+            txs: block.txs.iter().cloned().map(field).collect(),
+            hash: self.block_hash(block),
+            height: block.height,
+            round: block.round,
+            time: Some(self.block_time(block.height)),
+            proposer_pro_tx_hash: pro_tx_hash(block.proposer),
+            ..Default::default()
+        };
+        let response = match self.call(index, request::Value::ProcessProposal(request))? {
+            response::Value::ProcessProposal(response) => response,
+            other => return Err(unexpected_response(other)),
+        };
+
+        if response.status != ProposalStatus::Accept as i32 {
+            return Err(SimulationError::Consensus {
+                height: block.height,
+                reason: format!("node {} rejected proposal", index),
+            });
+        }
+        Ok(response.app_hash.to_vec())
+    }
+
+    /// Collect vote extensions from all nodes and verify them on all other
+    /// nodes.
+    fn vote(&self, block: &CommittedBlock) -> Result<(), SimulationError> {
+        let hash = self.block_hash(block);
+        for index in 0..self.nodes.len() {
+            let request = abci::RequestExtendVote {
+                hash: hash.clone(),
+                height: block.height,
+                round: block.round,
+            };
+            let extensions = match self.call(index, request::Value::ExtendVote(request))? {
+                response::Value::ExtendVote(response) => response.vote_extensions,
+                other => return Err(unexpected_response(other)),
+            };
+
+            for verifier in (0..self.nodes.len()).filter(|verifier| *verifier != index) {
+                let request = abci::RequestVerifyVoteExtension {
+                    hash: hash.clone(),
+                    validator_pro_tx_hash: pro_tx_hash(index),
+                    height: block.height,
+                    round: block.round,
+                    vote_extensions: extensions.clone(),
+                };
+                let status =
+                    match self.call(verifier, request::Value::VerifyVoteExtension(request))? {
+                        response::Value::VerifyVoteExtension(response) => response.status,
+                        other => return Err(unexpected_response(other)),
+                    };
+                if status != VerifyStatus::Accept as i32 {
+                    return Err(SimulationError::Consensus {
+                        height: block.height,
+                        reason: format!(
+                            "node {} rejected vote extensions of node {}",
+                            verifier, index
+                        ),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send FinalizeBlock with `block` to node at `index`.
+    fn finalize(&self, index: usize, block: &CommittedBlock) -> Result<(), SimulationError> {
+        let hash = self.block_hash(block);
+        let header = types::Header {
+            chain_id: self.chain_id.clone(),
+            height: block.height,
+            time: Some(self.block_time(block.height)),
+            app_hash: block.app_hash.clone(),
+            proposer_pro_tx_hash: pro_tx_hash(block.proposer),
+            ..Default::default()
+        };
+        let request = abci::RequestFinalizeBlock {
+            commit: Some(abci::CommitInfo {
+                round: block.round,
+                ..Default::default()
+            }),
+            hash: hash.clone(),
+            height: block.height,
+            round: block.round,
+            block: Some(types::Block {
+                header: Some(header),
+                data: Some(types::Data {
+                    txs: block.txs.clone(),
+                }),
+                ..Default::default()
+            }),
+            block_id: Some(types::BlockId {
+                hash,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match self.call(index, request::Value::FinalizeBlock(request))? {
+            response::Value::FinalizeBlock(_) => Ok(()),
+            other => Err(unexpected_response(other)),
+        }
+    }
+
+    /// Send request to node at `index`.
+    fn call(
+        &self,
+        index: usize,
+        value: request::Value,
+    ) -> Result<response::Value, SimulationError> {
+        let request = abci::Request { value: Some(value) };
+        let response = DefaultLayers
+            .layer(Node(&self.nodes[index]))
+            .handle(request)
+            .ok_or(SimulationError::Consensus {
+                height: self.height() + 1,
+                reason: format!("node {} is shutting down", index),
+            })?;
+
+        match response.value {
+            Some(response::Value::Exception(exception)) => {
+                Err(Error::Exception(exception.error).into())
+            },
+            Some(value) => Ok(value),
+            None => Err(Error::UnexpectedResponse("empty response".to_string()).into()),
+        }
+    }
+
+    fn check_app_hash(
+        &self,
+        height: i64,
+        expected: &[u8],
+        actual: &[u8],
+    ) -> Result<(), SimulationError> {
+        if expected != actual {
+            return Err(SimulationError::Consensus {
+                height,
+                reason: format!(
+                    "app hash mismatch: expected {}, got {}",
+                    hex::encode(expected),
+                    hex::encode(actual)
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn proposer(&self, height: i64, round: i32) -> usize {
+        (height + round as i64).rem_euclid(self.nodes.len() as i64) as usize
+    }
+
+    fn block(&self, height: i64) -> Option<&CommittedBlock> {
+        self.blocks.iter().find(|block| block.height == height)
+    }
+
+    fn block_time(&self, height: i64) -> Timestamp {
+        Timestamp {
+            seconds: GENESIS_TIME + height,
+            nanos: 0,
+        }
+    }
+
+    /// Deterministic 32-byte hash identifying the block: SHA-256 of chain ID,
+    /// height, round and hashes of transactions.
+    fn block_hash(&self, block: &CommittedBlock) -> Vec<u8> {
+        let mut data = Vec::new();
+        for part in [
+            self.chain_id.as_bytes(),
+            &block.height.to_be_bytes(),
+            &block.round.to_be_bytes(),
+        ] {
+            data.extend_from_slice(&(part.len() as u64).to_be_bytes());
+            data.extend_from_slice(part);
+        }
+        for tx in &block.txs {
+            data.extend_from_slice(&lhash::sha256(tx));
+        }
+        lhash::sha256(&data).to_vec()
+    }
+}
+
+/// ProTxHash of node at `index`.
+fn pro_tx_hash(index: usize) -> Vec<u8> {
+    lhash::sha256(&(index as u64).to_be_bytes()).to_vec()
+}
+
+/// Convert to type of a bytes proto field, which is `Bytes` when the
+/// `zero-copy` feature is enabled.
+fn field<T: From<Vec<u8>>>(value: Vec<u8>) -> T {
+    T::from(value)
+}
+
+fn unexpected_response(response: response::Value) -> SimulationError {
+    Error::UnexpectedResponse(format!("{:?}", response)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pro_tx_hash;
+
+    #[test]
+    /// Given many nodes, when their ProTxHashes are derived, then they are
+    /// unique.
+    fn test_pro_tx_hash_unique() {
+        let hashes = (0..=256)
+            .map(pro_tx_hash)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(hashes.len(), 257);
+        assert!(hashes.iter().all(|hash| hash.len() == 32));
+    }
+}
@@ -48,12 +48,49 @@ pub fn span<T>(request: T) -> tracing::span::EnteredSpan
 where
     T: Into<Value>,
 {
-    let value = request.into();
+    request_span(request).entered()
+}
+
+/// Creates a new span for tracing, without entering it.
+///
+/// Works like [span()], but returns a [tracing::Span] that is not entered yet.
+/// Use it in async code together with [tracing::Instrument], as
+/// [tracing::span::EnteredSpan] must not be held across `.await` points.
+///
+/// # Examples
+///
+/// ```
+/// # use tenderdash_proto::abci::{RequestInfo, request};
+/// # use tenderdash_abci::tracing_span::request_span;
+/// use tracing::Instrument;
+///
+/// let request = request::Value::Info(RequestInfo::default());
+/// let future = async { /* handle request */ }.instrument(request_span(request));
+/// ```
+pub fn request_span<T>(request: T) -> tracing::Span
+where
+    T: Into<Value>,
+{
+    request_span_with_id(&request.into(), &uuid::Uuid::new_v4().to_string())
+}
 
-    let endpoint = abci_method_name(&value);
-    let request_id = uuid::Uuid::new_v4().to_string();
+/// Span of the request being dispatched by the server, or a new span of
+/// `request` when called outside of the server.
+pub(crate) fn current_span(request: &Value) -> tracing::Span {
+    crate::RequestContext::current()
+        .map(|context| context.span().clone())
+        .unwrap_or_else(|| request_span_with_id(request, &uuid::Uuid::new_v4().to_string()))
+}
+
+/// Creates a new span for tracing with given `request_id`, without entering
+/// it.
+///
+/// Works like [request_span()], but uses provided request ID instead of a new
+/// one, like [`RequestContext::request_id()`](crate::RequestContext::request_id()).
+pub fn request_span_with_id(request: &Value, request_id: &str) -> tracing::Span {
+    let endpoint = crate::application::method_name(request);
 
-    let span = match value {
+    match request {
         Value::Info(_r) => tracing::span!(LEVEL, SPAN_NAME, endpoint, request_id),
         Value::InitChain(_r) => {
             tracing::span!(LEVEL, SPAN_NAME, endpoint, request_id)
@@ -70,28 +107,5 @@ where
             tracing::span!(LEVEL, SPAN_NAME, endpoint, request_id, path = r.path)
         },
         _ => tracing::span!(LEVEL, SPAN_NAME, endpoint, request_id),
-    };
-
-    span.entered()
-}
-
-fn abci_method_name(request: &Value) -> String {
-    match request {
-        Value::ApplySnapshotChunk(_) => "ApplySnapshotChunk",
-        Value::CheckTx(_) => "CheckTx",
-        Value::Echo(_) => "Echo",
-        Value::ExtendVote(_) => "ExtendVote",
-        Value::FinalizeBlock(_) => "FinalizeBlock",
-        Value::Flush(_) => "Flush",
-        Value::Info(_) => "Info",
-        Value::InitChain(_) => "InitChain",
-        Value::ListSnapshots(_) => "ListSnapshots",
-        Value::LoadSnapshotChunk(_) => "LoadSnapshotChunk",
-        Value::OfferSnapshot(_) => "OfferSnapshot",
-        Value::PrepareProposal(_) => "PrepareProposal",
-        Value::ProcessProposal(_) => "ProcessProposal",
-        Value::Query(_) => "Query",
-        Value::VerifyVoteExtension(_) => "VerifyVoteExtension",
     }
-    .to_string()
 }
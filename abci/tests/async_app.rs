@@ -0,0 +1,105 @@
+//! Test [AsyncApplication] served by the async server.
+#![cfg(feature = "unix")]
+
+use tenderdash_abci::{
+    proto::{
+        abci,
+        prost::{bytes::BytesMut, Message},
+    },
+    AsyncApplication, CancellationToken, ServerBuilder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const SOCKET: &str = "/tmp/abci-async.sock";
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Async ABCI application
+///
+/// * Given that we have AsyncApplication served with the async server
+/// * When a client sends Echo and Info requests
+/// * Then the async handlers are executed and responses are sent back
+async fn test_async_application() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init()
+        .ok();
+
+    let cancel = CancellationToken::new();
+    let bind_address = format!("unix://{}", SOCKET);
+    let server = ServerBuilder::new(AsyncEchoApp {}, &bind_address)
+        .with_cancel_token(cancel.clone())
+        .build_async()
+        .expect("server failed");
+
+    let server_handle = tokio::spawn(async move { server.next_client().await });
+
+    let mut stream = UnixStream::connect(SOCKET).await.expect("connect");
+
+    let response = roundtrip(
+        &mut stream,
+        abci::request::Value::Echo(abci::RequestEcho {
+            message: "hello".to_string(),
+        }),
+    )
+    .await;
+    assert_eq!(
+        response,
+        abci::response::Value::Echo(abci::ResponseEcho {
+            message: "hello async".to_string(),
+        })
+    );
+
+    // Version check of default `info` implementation
+    let response = roundtrip(
+        &mut stream,
+        abci::request::Value::Info(abci::RequestInfo {
+            abci_version: "0.0.1".to_string(),
+            ..Default::default()
+        }),
+    )
+    .await;
+    assert!(matches!(response, abci::response::Value::Exception(_)));
+
+    cancel.cancel();
+    drop(stream);
+    server_handle.await.expect("server task panicked").ok();
+}
+
+/// Send request and read response, using length-delimited encoding.
+async fn roundtrip(
+    stream: &mut UnixStream,
+    request: abci::request::Value,
+) -> abci::response::Value {
+    let encoded = abci::Request {
+        value: Some(request),
+    }
+    .encode_length_delimited_to_vec();
+    stream.write_all(&encoded).await.expect("write request");
+
+    let mut buf = BytesMut::new();
+    loop {
+        if let Ok(response) = abci::Response::decode_length_delimited(buf.clone()) {
+            return response.value.expect("empty response");
+        }
+        stream.read_buf(&mut buf).await.expect("read response");
+    }
+}
+
+struct AsyncEchoApp {}
+
+#[async_trait::async_trait]
+impl AsyncApplication for AsyncEchoApp {
+    async fn echo(
+        &self,
+        request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        tokio::task::yield_now().await;
+
+        Ok(abci::ResponseEcho {
+            message: format!("{} async", request.message),
+        })
+    }
+}
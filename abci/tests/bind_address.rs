@@ -0,0 +1,86 @@
+//! Test server bind addresses.
+
+use tenderdash_abci::{Application, ServerBuilder};
+
+#[test]
+/// Given invalid bind address, when the server is built, then configuration
+/// error is returned instead of panic.
+fn test_invalid_bind_address() {
+    for address in ["tcp://127.0.0.1", "tcp://[::1:1234", "udp://127.0.0.1:1234"] {
+        let result = ServerBuilder::new(EchoApp {}, address).build();
+        assert!(
+            matches!(result, Err(tenderdash_abci::Error::Configuration(_))),
+            "{}",
+            address
+        );
+    }
+}
+
+#[cfg(all(feature = "unix", target_os = "linux"))]
+#[test]
+/// Feature: Linux abstract Unix sockets
+///
+/// * Given that we have a server listening on an abstract Unix socket
+/// * When a client connects to it and sends Echo request
+/// * Then it receives the echo response
+fn test_abstract_unix_socket() {
+    use std::{
+        io::{Read, Write},
+        os::{
+            linux::net::SocketAddrExt,
+            unix::net::{SocketAddr, UnixStream},
+        },
+        thread,
+    };
+
+    use tenderdash_abci::proto::{
+        abci,
+        prost::{bytes::BytesMut, Message},
+    };
+
+    const NAME: &str = "tenderdash-abci-test";
+
+    let server = ServerBuilder::new(EchoApp {}, &format!("unix://@{}", NAME))
+        .build()
+        .expect("server failed");
+
+    let client = thread::spawn(move || {
+        let addr = SocketAddr::from_abstract_name(NAME).expect("socket address");
+        let mut stream = UnixStream::connect_addr(&addr).expect("connect");
+
+        let request = abci::Request {
+            value: Some(abci::request::Value::Echo(abci::RequestEcho {
+                message: "abstract".to_string(),
+            })),
+        };
+        stream
+            .write_all(&request.encode_length_delimited_to_vec())
+            .expect("write request");
+
+        let mut buf = BytesMut::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            if let Ok(response) = abci::Response::decode_length_delimited(buf.clone()) {
+                break response.value;
+            }
+            let n = stream.read(&mut chunk).expect("read response");
+            assert!(n > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    });
+
+    // Connection is closed when the client finishes
+    server.next_client().expect("connection failed");
+    let response = client.join().expect("client panicked");
+
+    assert_eq!(
+        response,
+        Some(abci::response::Value::Echo(abci::ResponseEcho {
+            message: "abstract".to_string(),
+        }))
+    );
+}
+
+struct EchoApp {}
+
+impl Application for EchoApp {}
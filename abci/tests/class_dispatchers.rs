@@ -0,0 +1,111 @@
+//! Test routing of requests to dispatchers of connection classes.
+#![cfg(feature = "unix")]
+
+mod common;
+
+use std::{
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use common::RawClient;
+use tenderdash_abci::{
+    proto::abci, Application, CancellationToken, ConnectionClass, ServerBuilder,
+};
+
+const SOCKET: &str = "/tmp/abci-class-dispatchers.sock";
+
+#[test]
+/// Feature: Separate dispatchers for connection classes
+///
+/// * Given that we have a server with a separate, slow mempool dispatcher
+/// * When a client sends CheckTx followed by Echo, and another client sends
+///   PrepareProposal
+/// * Then PrepareProposal is processed while CheckTx is still in progress
+/// * And responses on the first connection are sent in the order of requests
+fn test_class_dispatchers() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init()
+        .ok();
+
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let mempool = BlockingMempoolApp {
+        release: Mutex::new(release_rx),
+    };
+
+    let cancel = CancellationToken::new();
+    let bind_address = format!("unix://{}", SOCKET);
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let server = ServerBuilder::new(ConsensusApp {}, &bind_address)
+            .with_cancel_token(server_cancel)
+            .with_max_connections(2)
+            .with_dispatcher(ConnectionClass::Mempool, mempool)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut mempool_client = RawClient::connect(SOCKET);
+    let mut consensus_client = RawClient::connect(SOCKET);
+
+    // CheckTx blocks until released; Echo is pipelined after it
+    mempool_client.send(abci::request::Value::CheckTx(Default::default()));
+    mempool_client.send(abci::request::Value::Echo(abci::RequestEcho {
+        message: "after check tx".to_string(),
+    }));
+
+    // Consensus requests are not blocked by the mempool
+    consensus_client.send(abci::request::Value::PrepareProposal(Default::default()));
+    assert!(matches!(
+        consensus_client.recv(),
+        abci::response::Value::PrepareProposal(_)
+    ));
+
+    release_tx.send(()).expect("release check tx");
+
+    match mempool_client.recv() {
+        abci::response::Value::CheckTx(response) => assert_eq!(response.priority, 42),
+        value => panic!("unexpected response: {:?}", value),
+    }
+    assert_eq!(
+        mempool_client.recv(),
+        abci::response::Value::Echo(abci::ResponseEcho {
+            message: "after check tx".to_string(),
+        })
+    );
+
+    cancel.cancel();
+    drop(mempool_client);
+    drop(consensus_client);
+    server_thread.join().expect("server thread panicked").ok();
+}
+
+struct ConsensusApp {}
+
+impl Application for ConsensusApp {}
+
+/// Mempool application that does not respond to CheckTx until released.
+struct BlockingMempoolApp {
+    release: Mutex<mpsc::Receiver<()>>,
+}
+
+impl Application for BlockingMempoolApp {
+    fn check_tx(
+        &self,
+        _request: abci::RequestCheckTx,
+    ) -> Result<abci::ResponseCheckTx, abci::ResponseException> {
+        self.release
+            .lock()
+            .expect("lock poisoned")
+            .recv()
+            .expect("release signal");
+
+        Ok(abci::ResponseCheckTx {
+            priority: 42,
+            ..Default::default()
+        })
+    }
+}
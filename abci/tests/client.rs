@@ -0,0 +1,200 @@
+//! Test ABCI socket client.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use common::{connect, connect_blocking};
+use tenderdash_abci::{
+    proto::abci, Application, CancellationToken, ClientBuilder, Error, ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Async ABCI client
+///
+/// * Given that we have a running ABCI server
+/// * When the client sends concurrent requests over one connection
+/// * Then each request receives its own response
+/// * And exception returned by the application is reported as error
+async fn test_async_client() {
+    const SOCKET: &str = "/tmp/abci-client-async.sock";
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, cancel.clone());
+
+    let client = connect(SOCKET).await;
+
+    let info = client.info(Default::default()).await.expect("info failed");
+    assert_eq!(info.data, "client-test");
+
+    let echoes = (0..10).map(|i| {
+        let client = client.clone();
+        async move {
+            let message = format!("hello {}", i);
+            let response = client
+                .echo(abci::RequestEcho {
+                    message: message.clone(),
+                })
+                .await
+                .expect("echo failed");
+            assert_eq!(response.message, message);
+        }
+    });
+    futures::future::join_all(echoes).await;
+
+    assert!(matches!(
+        client.query(Default::default()).await,
+        Err(Error::Exception(error)) if error == "query not supported"
+    ));
+
+    cancel.cancel();
+    drop(client);
+    tokio::task::spawn_blocking(move || server.join().expect("server thread panicked"))
+        .await
+        .expect("join server thread");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Given a running ABCI server, when the client pipelines more large requests
+/// than socket buffers can hold, then responses are read while requests are
+/// written, and each request receives its response.
+async fn test_async_client_large_requests() {
+    const SOCKET: &str = "/tmp/abci-client-large.sock";
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, cancel.clone());
+
+    let client = connect(SOCKET).await;
+
+    let echoes = (0..64).map(|i| {
+        let client = client.clone();
+        async move {
+            let message = format!("{}{}", i, "x".repeat(256 * 1024));
+            let response = client
+                .echo(abci::RequestEcho {
+                    message: message.clone(),
+                })
+                .await
+                .expect("echo failed");
+            assert_eq!(response.message, message);
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(30), futures::future::join_all(echoes))
+        .await
+        .expect("client and server blocked each other");
+
+    cancel.cancel();
+    drop(client);
+    tokio::task::spawn_blocking(move || server.join().expect("server thread panicked"))
+        .await
+        .expect("join server thread");
+}
+
+#[test]
+/// Given a running ABCI server, when the blocking client sends requests, then
+/// it receives their responses.
+fn test_blocking_client() {
+    const SOCKET: &str = "/tmp/abci-client-blocking.sock";
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, cancel.clone());
+
+    let client = connect_blocking(SOCKET);
+
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "blocking".to_string(),
+        })
+        .expect("echo failed");
+    assert_eq!(response.message, "blocking");
+    assert!(matches!(
+        client.query(Default::default()),
+        Err(Error::Exception(_))
+    ));
+
+    cancel.cancel();
+    drop(client);
+    server.join().expect("server thread panicked");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Given client with maximum response size, when the server sends larger
+/// response, then the request fails with `FrameTooLarge` error.
+async fn test_client_max_response_size() {
+    const SOCKET: &str = "/tmp/abci-client-max-response.sock";
+    const LIMIT: usize = 100;
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, cancel.clone());
+
+    let mut client = None;
+    for _ in 0..100 {
+        let builder = ClientBuilder::new(&format!("unix://{}", SOCKET));
+        if let Ok(connected) = builder.with_max_response_size(LIMIT).connect().await {
+            client = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let client = client.expect("cannot connect to server");
+
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "small".to_string(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.message, "small");
+
+    let result = client
+        .echo(abci::RequestEcho {
+            message: "x".repeat(LIMIT),
+        })
+        .await;
+    assert!(
+        matches!(result, Err(Error::FrameTooLarge { limit: LIMIT, .. })),
+        "{:?}",
+        result
+    );
+
+    cancel.cancel();
+    drop(client);
+    tokio::task::spawn_blocking(move || server.join().expect("server thread panicked"))
+        .await
+        .expect("join server thread");
+}
+
+/// Start server on Unix socket in a separate thread.
+fn start_server(socket: &'static str, cancel: CancellationToken) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", socket))
+            .with_cancel_token(cancel)
+            .build()
+            .expect("server failed");
+
+        server.next_client().ok();
+    })
+}
+
+struct TestApp {}
+
+impl Application for TestApp {
+    fn info(
+        &self,
+        _request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        Ok(abci::ResponseInfo {
+            data: "client-test".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        Err(abci::ResponseException {
+            error: "query not supported".to_string(),
+        })
+    }
+}
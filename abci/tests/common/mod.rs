@@ -1,2 +1,117 @@
+//! Helpers shared by integration tests.
+// Each test uses only some of the helpers.
+#![allow(dead_code)]
+
 #[cfg(feature = "docker-tests")]
 pub mod docker;
+
+#[cfg(feature = "unix")]
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "unix")]
+use tenderdash_abci::proto::{
+    abci,
+    prost::{
+        bytes::{Buf, BytesMut},
+        length_delimiter_len, Message,
+    },
+};
+#[cfg(all(feature = "client", feature = "unix"))]
+use tenderdash_abci::{AbciClient, BlockingAbciClient};
+
+/// Number of connection attempts before giving up.
+const CONNECT_ATTEMPTS: usize = 100;
+/// Delay between connection attempts.
+#[cfg(feature = "unix")]
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Connect to the server listening on unix `socket`, retrying until the
+/// socket is ready.
+#[cfg(all(feature = "client", feature = "unix"))]
+pub async fn connect(socket: &str) -> AbciClient {
+    for _ in 0..CONNECT_ATTEMPTS {
+        if let Ok(client) = AbciClient::connect(&format!("unix://{}", socket)).await {
+            return client;
+        }
+        tokio::time::sleep(CONNECT_RETRY_DELAY).await;
+    }
+    panic!("cannot connect to {}", socket);
+}
+
+/// Blocking version of [connect()].
+#[cfg(all(feature = "client", feature = "unix"))]
+pub fn connect_blocking(socket: &str) -> BlockingAbciClient {
+    for _ in 0..CONNECT_ATTEMPTS {
+        if let Ok(client) = BlockingAbciClient::connect(&format!("unix://{}", socket)) {
+            return client;
+        }
+        thread::sleep(CONNECT_RETRY_DELAY);
+    }
+    panic!("cannot connect to {}", socket);
+}
+
+/// Blocking client that sends raw length-delimited requests over unix socket,
+/// to control exactly what the server receives.
+#[cfg(feature = "unix")]
+pub struct RawClient {
+    stream: UnixStream,
+    buf: BytesMut,
+}
+
+#[cfg(feature = "unix")]
+impl RawClient {
+    /// Connect to the server listening on `socket`, retrying until the socket
+    /// is ready.
+    pub fn connect(socket: &str) -> Self {
+        for _ in 0..CONNECT_ATTEMPTS {
+            if let Ok(stream) = UnixStream::connect(socket) {
+                return Self {
+                    stream,
+                    buf: BytesMut::new(),
+                };
+            }
+            thread::sleep(CONNECT_RETRY_DELAY);
+        }
+        panic!("cannot connect to {}", socket);
+    }
+
+    pub fn send(&mut self, request: abci::request::Value) {
+        let encoded = abci::Request {
+            value: Some(request),
+        }
+        .encode_length_delimited_to_vec();
+        self.stream.write_all(&encoded).expect("write request");
+    }
+
+    /// Receive next response; panics when the connection is closed.
+    pub fn recv(&mut self) -> abci::response::Value {
+        let mut chunk = [0u8; 1024];
+        loop {
+            if let Ok(response) = abci::Response::decode_length_delimited(self.buf.clone()) {
+                let len = response.encoded_len();
+                self.buf.advance(len + length_delimiter_len(len));
+                return response.value.expect("empty response");
+            }
+            let n = self.stream.read(&mut chunk).expect("read response");
+            assert!(n > 0, "connection closed");
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Check that the server closed the connection.
+    pub fn closed(&mut self) -> bool {
+        let mut chunk = [0u8; 1];
+        matches!(self.stream.read(&mut chunk), Ok(0))
+    }
+}
+
+/// Convert to type of a `bytes` proto field, which is `Bytes` when the
+/// `zero-copy` feature is enabled.
+pub fn bytes_field<T: From<Vec<u8>>>(value: Vec<u8>) -> T {
+    T::from(value)
+}
@@ -0,0 +1,72 @@
+//! Test serving multiple concurrent connections with one server.
+#![cfg(feature = "unix")]
+
+mod common;
+
+use std::thread;
+
+use common::RawClient;
+use tenderdash_abci::{proto::abci, Application, CancellationToken, ServerBuilder};
+
+const SOCKET: &str = "/tmp/abci-connections.sock";
+
+#[test]
+/// Feature: Multiple concurrent connections
+///
+/// * Given that we have a server configured to accept 2 connections at once
+/// * When two clients connect and send requests in interleaved order
+/// * Then both of them receive responses from the same application
+fn test_concurrent_connections() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init()
+        .ok();
+
+    let cancel = CancellationToken::new();
+    let bind_address = format!("unix://{}", SOCKET);
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let server = ServerBuilder::new(EchoApp {}, &bind_address)
+            .with_cancel_token(server_cancel)
+            .with_max_connections(2)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut first = RawClient::connect(SOCKET);
+    let mut second = RawClient::connect(SOCKET);
+
+    assert_eq!(echo(&mut first, "first 1"), "first 1");
+    assert_eq!(echo(&mut second, "second 1"), "second 1");
+    assert_eq!(echo(&mut first, "first 2"), "first 2");
+
+    // Closing one connection does not affect the other one
+    drop(first);
+    assert_eq!(echo(&mut second, "second 2"), "second 2");
+
+    // New connection can be established in place of the closed one
+    let mut third = RawClient::connect(SOCKET);
+    assert_eq!(echo(&mut third, "third 1"), "third 1");
+
+    cancel.cancel();
+    drop(second);
+    drop(third);
+    server_thread.join().expect("server thread panicked").ok();
+}
+
+/// Send echo request and return message from the response.
+fn echo(client: &mut RawClient, message: &str) -> String {
+    client.send(abci::request::Value::Echo(abci::RequestEcho {
+        message: message.to_string(),
+    }));
+    match client.recv() {
+        abci::response::Value::Echo(echo) => echo.message,
+        value => panic!("unexpected response: {:?}", value),
+    }
+}
+
+struct EchoApp {}
+
+impl Application for EchoApp {}
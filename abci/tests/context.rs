@@ -0,0 +1,117 @@
+//! Test request context passed to applications.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::connect;
+use tenderdash_abci::{
+    current_connection_id, proto::abci, AsyncApplication, CancellationToken, Deadline,
+    DeadlineAction, Error, RequestContext, ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Request context
+///
+/// * Given an application with context-aware handlers
+/// * When the client sends requests
+/// * Then each request gets a context with connection ID and unique request ID
+/// * And the context's cancellation token is cancelled when the hard deadline
+///   of the request passes
+async fn test_request_context() {
+    const SOCKET: &str = "/tmp/abci-context.sock";
+
+    let deadline_cancelled = Arc::new(AtomicBool::new(false));
+    let app = TestApp {
+        deadline_cancelled: deadline_cancelled.clone(),
+    };
+    let cancel = CancellationToken::new();
+    let server = ServerBuilder::new(app, &format!("unix://{}", SOCKET))
+        .with_deadline(
+            "Query",
+            Deadline::new().with_hard_limit(Duration::from_millis(100), DeadlineAction::Exception),
+        )
+        .with_cancel_token(cancel.clone())
+        .build_async()
+        .expect("server failed");
+    let server = tokio::spawn(async move { server.next_client().await });
+
+    let client = connect(SOCKET).await;
+    let first = client
+        .echo(abci::RequestEcho::default())
+        .await
+        .expect("echo failed")
+        .message;
+    let second = client
+        .echo(abci::RequestEcho::default())
+        .await
+        .expect("echo failed")
+        .message;
+    assert!(!first.is_empty());
+    assert_ne!(first, second);
+
+    let Err(Error::Exception(error)) = client.query(Default::default()).await else {
+        panic!("query must exceed deadline");
+    };
+    assert!(error.contains("exceeded deadline"), "{}", error);
+    for _ in 0..100 {
+        if deadline_cancelled.load(Ordering::SeqCst) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(deadline_cancelled.load(Ordering::SeqCst));
+
+    cancel.cancel();
+    drop(client);
+    let result = server.await.expect("join server task");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+struct TestApp {
+    /// Set when the query handler observed cancellation of its context.
+    deadline_cancelled: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl AsyncApplication for TestApp {
+    /// Respond with request ID of the context.
+    async fn echo_with_context(
+        &self,
+        context: &RequestContext,
+        _request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        assert_eq!(context.connection_id(), current_connection_id());
+        assert!(context.connection_id().is_some());
+        assert!(context.deadline().is_none());
+        assert!(!context.cancellation_token().is_cancelled());
+
+        Ok(abci::ResponseEcho {
+            message: context.request_id().to_string(),
+        })
+    }
+
+    /// Wait until the hard deadline cancels the context.
+    async fn query_with_context(
+        &self,
+        context: &RequestContext,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        assert!(context.deadline().is_some());
+        tokio::select! {
+            _ = context.cancellation_token().cancelled() => {
+                self.deadline_cancelled.store(true, Ordering::SeqCst);
+            },
+            _ = tokio::time::sleep(Duration::from_secs(5)) => {},
+        }
+
+        Ok(Default::default())
+    }
+}
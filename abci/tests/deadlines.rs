@@ -0,0 +1,132 @@
+//! Test deadlines of request processing.
+#![cfg(feature = "unix")]
+
+mod common;
+
+use std::{thread, time::Duration};
+
+use common::RawClient;
+use tenderdash_abci::{
+    proto::abci, Application, CancellationToken, Deadline, DeadlineAction, Error, ServerBuilder,
+};
+
+#[test]
+/// Feature: Hard deadline with exception
+///
+/// * Given that FinalizeBlock takes longer than its hard deadline
+/// * When a client sends FinalizeBlock followed by Echo
+/// * Then the client receives an exception instead of FinalizeBlock response
+/// * And Echo is processed when FinalizeBlock finishes
+fn test_deadline_exception() {
+    const SOCKET: &str = "/tmp/abci-deadline-exception.sock";
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let deadline = Deadline::new()
+            .with_soft_limit(Duration::from_millis(50))
+            .with_hard_limit(Duration::from_millis(100), DeadlineAction::Exception);
+        let server = ServerBuilder::new(SlowApp {}, &format!("unix://{}", SOCKET))
+            .with_cancel_token(server_cancel)
+            .with_deadline("FinalizeBlock", deadline)
+            .with_drain_timeout(Duration::from_millis(100))
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut client = RawClient::connect(SOCKET);
+    client.send(abci::request::Value::FinalizeBlock(Default::default()));
+    client.send(abci::request::Value::Echo(abci::RequestEcho {
+        message: "after finalize block".to_string(),
+    }));
+
+    match client.recv() {
+        abci::response::Value::Exception(exception) => {
+            assert!(
+                exception.error.contains("FinalizeBlock"),
+                "{}",
+                exception.error
+            )
+        },
+        value => panic!("unexpected response: {:?}", value),
+    }
+    assert_eq!(
+        client.recv(),
+        abci::response::Value::Echo(abci::ResponseEcho {
+            message: "after finalize block".to_string(),
+        })
+    );
+
+    cancel.cancel();
+    drop(client);
+    server_thread.join().expect("server thread panicked").ok();
+}
+
+#[test]
+/// Feature: Hard deadline with cancel action
+///
+/// * Given that FinalizeBlock takes longer than its hard deadline
+/// * When a client sends FinalizeBlock
+/// * Then the client receives an exception instead of FinalizeBlock response
+/// * And the server is cancelled after the connection is drained
+fn test_deadline_cancel() {
+    const SOCKET: &str = "/tmp/abci-deadline-cancel.sock";
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let deadline =
+            Deadline::new().with_hard_limit(Duration::from_millis(100), DeadlineAction::Cancel);
+        let server = ServerBuilder::new(SlowApp {}, &format!("unix://{}", SOCKET))
+            .with_cancel_token(server_cancel)
+            .with_deadline("FinalizeBlock", deadline)
+            .with_drain_timeout(Duration::from_secs(1))
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut client = RawClient::connect(SOCKET);
+    client.send(abci::request::Value::FinalizeBlock(Default::default()));
+
+    match client.recv() {
+        abci::response::Value::Exception(exception) => {
+            assert!(
+                exception.error.contains("FinalizeBlock"),
+                "{}",
+                exception.error
+            )
+        },
+        value => panic!("unexpected response: {:?}", value),
+    }
+    let result = server_thread.join().expect("server thread panicked");
+    assert!(cancel.is_cancelled());
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+#[test]
+/// Given deadline of unknown method, when the server is built, then
+/// configuration error is returned.
+fn test_deadline_unknown_method() {
+    let result = ServerBuilder::new(SlowApp {}, "tcp://127.0.0.1:0")
+        .with_deadline("FinalizeBlok", Deadline::new())
+        .build();
+
+    assert!(matches!(result, Err(Error::Configuration(_))));
+}
+
+/// Application that takes 300 ms to finalize a block.
+struct SlowApp {}
+
+impl Application for SlowApp {
+    fn finalize_block(
+        &self,
+        _request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        thread::sleep(Duration::from_millis(300));
+        Ok(Default::default())
+    }
+}
@@ -0,0 +1,60 @@
+//! Test draining of connections on shutdown.
+#![cfg(feature = "unix")]
+
+mod common;
+
+use std::{thread, time::Duration};
+
+use common::RawClient;
+use tenderdash_abci::{proto::abci, Application, CancellationToken, Error, ServerBuilder};
+
+const SOCKET: &str = "/tmp/abci-drain.sock";
+
+#[test]
+/// Feature: Graceful drain on shutdown
+///
+/// * Given that the server is processing FinalizeBlock
+/// * When the server is cancelled
+/// * Then the client receives FinalizeBlock response before the connection is
+///   closed
+/// * And the server reports that the drain finished cleanly
+fn test_drain_on_cancel() {
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let server = ServerBuilder::new(SlowApp {}, &format!("unix://{}", SOCKET))
+            .with_cancel_token(server_cancel)
+            .with_drain_timeout(Duration::from_secs(5))
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut client = RawClient::connect(SOCKET);
+    client.send(abci::request::Value::FinalizeBlock(Default::default()));
+    thread::sleep(Duration::from_millis(100));
+    cancel.cancel();
+
+    assert!(matches!(
+        client.recv(),
+        abci::response::Value::FinalizeBlock(_)
+    ));
+    assert!(client.closed(), "connection not closed after drain");
+
+    let result = server_thread.join().expect("server thread panicked");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+/// Application that takes 300 ms to finalize a block.
+struct SlowApp {}
+
+impl Application for SlowApp {
+    fn finalize_block(
+        &self,
+        _request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        thread::sleep(Duration::from_millis(300));
+        Ok(Default::default())
+    }
+}
@@ -0,0 +1,255 @@
+//! Test tracking of block execution against simulated Tenderdash network.
+#![cfg(feature = "testing")]
+
+mod common;
+
+use std::sync::Mutex;
+
+#[cfg(feature = "crypto")]
+use common::bytes_field;
+use tenderdash_abci::{
+    execution::{BlockExecutionTracker, BlockStage},
+    proto::abci::{
+        self, response, response_process_proposal::ProposalStatus,
+        response_verify_vote_extension::VerifyStatus,
+    },
+    testing::Simulator,
+    Application, RequestDispatcher,
+};
+#[cfg(feature = "crypto")]
+use tenderdash_abci::{
+    execution::{ExecutionCache, ExecutionResult, ProposalKey},
+    proto::abci::{tx_record::TxAction, TxRecord},
+};
+
+#[test]
+/// Feature: Block execution tracking
+///
+/// * Given nodes that track block execution
+/// * When blocks are committed in several rounds, and a new node catches up
+///   with handshake
+/// * Then no protocol violation is detected
+/// * And trackers of all nodes reach the last height
+fn test_tracker_follows_simulator() {
+    let mut simulator = Simulator::new(vec![
+        TrackedApp::default(),
+        TrackedApp::default(),
+        TrackedApp::default(),
+    ])
+    .with_rounds(3);
+    simulator.init_chain().expect("init chain");
+    simulator.run(4).expect("heights 1-4");
+
+    simulator.replace_node(1, TrackedApp::default());
+    simulator.handshake(1).expect("handshake");
+    simulator.run(1).expect("height 5");
+
+    for node in simulator.nodes() {
+        let tracker = node.tracker.lock().unwrap();
+        assert_eq!(tracker.stage(), BlockStage::Finalized);
+        assert_eq!(tracker.height(), Some(5));
+        assert_eq!(tracker.round(), Some(2));
+    }
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+/// Feature: Execution cache
+///
+/// * Given nodes that cache results of proposals they execute
+/// * When blocks are committed in several rounds, and a new node catches up
+///   with handshake
+/// * Then each finalized block is taken from the cache
+/// * And each proposal is executed only once by each node
+fn test_execution_cache_reused_in_finalize_block() {
+    let mut simulator = Simulator::new(vec![
+        CachedApp::default(),
+        CachedApp::default(),
+        CachedApp::default(),
+    ])
+    .with_rounds(2);
+    simulator.init_chain().expect("init chain");
+    for height in 0..4u8 {
+        simulator.inject_tx(vec![height]);
+        simulator.run(1).expect("run height");
+    }
+
+    simulator.replace_node(1, CachedApp::default());
+    simulator.handshake(1).expect("handshake");
+    simulator.inject_tx(b"last".to_vec());
+    simulator.run(1).expect("height 5");
+
+    for node in simulator.nodes() {
+        let counters = node.counters.lock().unwrap();
+        assert_eq!(counters.misses, 0);
+        assert!(counters.hits > 0);
+        assert!(node.cache.lock().unwrap().is_empty());
+    }
+    // 5 heights, 2 rounds each
+    let counters = simulator.nodes()[0].counters.lock().unwrap();
+    assert_eq!((counters.executions, counters.hits), (10, 5));
+    // 4 heights replayed in handshake, then 2 rounds of the last height
+    let counters = simulator.nodes()[1].counters.lock().unwrap();
+    assert_eq!((counters.executions, counters.hits), (6, 5));
+}
+
+/// Dispatcher that tracks block execution and turns protocol violations into
+/// exceptions.
+#[derive(Default)]
+struct TrackedApp {
+    tracker: Mutex<BlockExecutionTracker>,
+}
+
+impl RequestDispatcher for TrackedApp {
+    fn handle(&self, request: abci::Request) -> Option<abci::Response> {
+        if let Err(violation) = self.tracker.lock().unwrap().track(request.value.as_ref()?) {
+            return Some(abci::Response {
+                value: Some(response::Value::Exception(abci::ResponseException {
+                    error: violation.to_string(),
+                })),
+            });
+        }
+        #[cfg(feature = "crypto")]
+        let tracked = request.value.clone();
+        let response = EmptyApp {}.handle(request)?;
+        #[cfg(feature = "crypto")]
+        if let (Some(request), Some(value)) = (tracked, &response.value) {
+            self.tracker.lock().unwrap().track_response(&request, value);
+        }
+        Some(response)
+    }
+}
+
+/// Application that accepts all proposals and vote extensions.
+struct EmptyApp {}
+
+impl Application for EmptyApp {
+    fn process_proposal(
+        &self,
+        _request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        Ok(abci::ResponseProcessProposal {
+            status: ProposalStatus::Accept.into(),
+            ..Default::default()
+        })
+    }
+
+    fn verify_vote_extension(
+        &self,
+        _request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        Ok(abci::ResponseVerifyVoteExtension {
+            status: VerifyStatus::Accept.into(),
+        })
+    }
+}
+
+/// Counters of [CachedApp] executions.
+#[cfg(feature = "crypto")]
+#[derive(Default)]
+struct Counters {
+    /// Blocks executed in PrepareProposal and ProcessProposal.
+    executions: usize,
+    /// Finalized blocks taken from the cache.
+    hits: usize,
+    /// Finalized blocks that had to be executed again.
+    misses: usize,
+}
+
+/// Application counting transactions, that caches execution results of
+/// proposals and reuses them in FinalizeBlock.
+#[cfg(feature = "crypto")]
+#[derive(Default)]
+struct CachedApp {
+    /// Number of committed transactions.
+    committed: Mutex<u64>,
+    cache: Mutex<ExecutionCache<u64>>,
+    counters: Mutex<Counters>,
+}
+
+#[cfg(feature = "crypto")]
+impl CachedApp {
+    /// Execute block with `txs`; the state is the number of transactions.
+    fn execute<T>(&self, txs: &[T]) -> ExecutionResult<u64> {
+        self.counters.lock().unwrap().executions += 1;
+        let state = *self.committed.lock().unwrap() + txs.len() as u64;
+        ExecutionResult {
+            state,
+            tx_results: vec![Default::default(); txs.len()],
+            app_hash: [[0u8; 24].as_slice(), &state.to_be_bytes()].concat(),
+            validator_set_update: None,
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl Application for CachedApp {
+    fn prepare_proposal(
+        &self,
+        request: abci::RequestPrepareProposal,
+    ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+        let result = self.execute(&request.txs);
+        let response = abci::ResponsePrepareProposal {
+            tx_records: request
+                .txs
+                .iter()
+                .map(|tx| TxRecord {
+                    action: TxAction::Unmodified.into(),
+                    tx: tx.clone(),
+                })
+                .collect(),
+            app_hash: bytes_field(result.app_hash.clone()),
+            tx_results: result.tx_results.clone(),
+            ..Default::default()
+        };
+        let key = ProposalKey::prepared(&request, &response);
+        self.cache.lock().unwrap().insert(key, result);
+
+        Ok(response)
+    }
+
+    fn process_proposal(
+        &self,
+        request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        let result = self.execute(&request.txs);
+        let response = abci::ResponseProcessProposal {
+            status: ProposalStatus::Accept.into(),
+            app_hash: bytes_field(result.app_hash.clone()),
+            tx_results: result.tx_results.clone(),
+            ..Default::default()
+        };
+        let key = ProposalKey::processed(&request);
+        self.cache.lock().unwrap().insert(key, result);
+
+        Ok(response)
+    }
+
+    fn verify_vote_extension(
+        &self,
+        request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        EmptyApp {}.verify_vote_extension(request)
+    }
+
+    fn finalize_block(
+        &self,
+        request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        let cached = self.cache.lock().unwrap().take_finalized(&request);
+        let result = match cached {
+            Some(result) => {
+                self.counters.lock().unwrap().hits += 1;
+                result
+            },
+            None => {
+                self.counters.lock().unwrap().misses += 1;
+                let txs = request.block.and_then(|b| b.data).unwrap_or_default().txs;
+                self.execute(&txs)
+            },
+        };
+        *self.committed.lock().unwrap() = result.state;
+
+        Ok(Default::default())
+    }
+}
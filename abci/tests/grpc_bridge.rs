@@ -0,0 +1,210 @@
+//! Test serving [Application] over gRPC.
+#![cfg(all(feature = "server", feature = "grpc"))]
+
+mod common;
+
+use std::{thread, time::Duration};
+
+use common::bytes_field;
+use tenderdash_abci::{
+    proto::{
+        abci::{self, abci_application_client::AbciApplicationClient},
+        tonic::{self, transport::Channel, Code},
+    },
+    Application, CancellationToken, ConnectionClass, Error, GrpcBridge, RequestContext,
+    ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: gRPC address in ServerBuilder
+///
+/// * Given that the server is built with `grpc://` address
+/// * When a gRPC client sends requests
+/// * Then the application processes them
+/// * And exceptions are returned as gRPC status
+/// * And cancelled server reports drained shutdown
+async fn test_server_builder_grpc() {
+    const ADDRESS: &str = "127.0.0.1:26691";
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server = thread::spawn(move || {
+        let server = ServerBuilder::new(TestApp {}, &format!("grpc://{}", ADDRESS))
+            .with_cancel_token(server_cancel)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut client = connect(ADDRESS).await;
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "hello".to_string(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.into_inner().message, "hello");
+
+    let response = client
+        .info(abci::RequestInfo::default())
+        .await
+        .expect("info failed");
+    assert_eq!(response.into_inner().data, "grpc-test");
+
+    let status = client
+        .query(abci::RequestQuery::default())
+        .await
+        .expect_err("query must fail");
+    assert_eq!(status.code(), Code::Unknown);
+    assert_eq!(status.message(), "query not supported");
+
+    cancel.cancel();
+    drop(client);
+    let result = tokio::task::spawn_blocking(move || server.join().expect("server panicked"))
+        .await
+        .expect("join server thread");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Given application served by GrpcBridge in a tonic server, when a client
+/// sends a request, then the application responds.
+async fn test_grpc_bridge() {
+    const ADDRESS: &str = "127.0.0.1:26692";
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server = tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(GrpcBridge::new(TestApp {}).into_service())
+            .serve_with_shutdown(ADDRESS.parse().unwrap(), server_cancel.cancelled())
+            .await
+    });
+
+    let mut client = connect(ADDRESS).await;
+    let response = client
+        .info(abci::RequestInfo::default())
+        .await
+        .expect("info failed");
+    assert_eq!(response.into_inner().data, "grpc-test");
+
+    cancel.cancel();
+    drop(client);
+    server
+        .await
+        .expect("join server task")
+        .expect("server failed");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: gRPC connections and request limits
+///
+/// * Given `grpc://` server with request size limit of the mempool class, lower
+///   than the default limit
+/// * When two clients send requests
+/// * Then requests of each connection get the same, unique connection ID
+/// * And CheckTx over the class limit fails with `ResourceExhausted` status
+/// * And Echo of the same size succeeds
+async fn test_server_builder_grpc_connections() {
+    const ADDRESS: &str = "127.0.0.1:26693";
+    const LIMIT: usize = 100;
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server = thread::spawn(move || {
+        let server = ServerBuilder::new(TestApp {}, &format!("grpc://{}", ADDRESS))
+            .with_cancel_token(server_cancel)
+            .with_max_request_size(2 * LIMIT)
+            .with_max_request_size_for(ConnectionClass::Mempool, LIMIT)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let mut first = connect(ADDRESS).await;
+    let mut second = connect(ADDRESS).await;
+    let first_id = connection_id(&mut first).await;
+    assert_eq!(connection_id(&mut first).await, first_id);
+    assert_ne!(connection_id(&mut second).await, first_id);
+
+    let status = first
+        .check_tx(abci::RequestCheckTx {
+            tx: bytes_field(vec![0; LIMIT]),
+            ..Default::default()
+        })
+        .await
+        .expect_err("check_tx must fail");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    let message = "x".repeat(LIMIT);
+    let response = first
+        .echo(abci::RequestEcho {
+            message: message.clone(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.into_inner().message, message);
+
+    cancel.cancel();
+    drop((first, second));
+    let result = tokio::task::spawn_blocking(move || server.join().expect("server panicked"))
+        .await
+        .expect("join server thread");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+/// Connection ID of `client`, as seen by the application.
+async fn connection_id(client: &mut AbciApplicationClient<Channel>) -> String {
+    client
+        .query(abci::RequestQuery {
+            path: "connection_id".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("query failed")
+        .into_inner()
+        .info
+}
+
+/// Connect to the server, retrying until it is ready.
+async fn connect(address: &str) -> AbciApplicationClient<Channel> {
+    for _ in 0..100 {
+        if let Ok(client) = AbciApplicationClient::connect(format!("http://{}", address)).await {
+            return client;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("cannot connect to {}", address);
+}
+
+struct TestApp {}
+
+impl Application for TestApp {
+    fn info(
+        &self,
+        _request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        Ok(abci::ResponseInfo {
+            data: "grpc-test".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn query(
+        &self,
+        request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        if request.path == "connection_id" {
+            let context = RequestContext::current().expect("no request context");
+            return Ok(abci::ResponseQuery {
+                info: format!("{:?}", context.connection_id()),
+                ..Default::default()
+            });
+        }
+        Err(abci::ResponseException {
+            error: "query not supported".to_string(),
+        })
+    }
+}
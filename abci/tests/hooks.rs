@@ -0,0 +1,165 @@
+//! Test connection lifecycle hooks.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use common::connect;
+use tenderdash_abci::{
+    current_connection_id, proto::abci, Application, CancellationToken, ConnectionHooks,
+    DisconnectReason, Error, ServerBuilder,
+};
+use tokio::{io::AsyncWriteExt, net::UnixStream};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Connection lifecycle hooks
+///
+/// * Given that the server is built with connection hooks shared with the app
+/// * When a client connects, sends a request and disconnects
+/// * Then hooks are notified about the connection and the disconnect
+/// * And the app sees the connection reported to hooks
+/// * And invalid request is reported as decode error
+async fn test_connection_hooks() {
+    const SOCKET: &str = "/tmp/abci-hooks.sock";
+
+    let events = Arc::new(Events::default());
+    let app = TestApp {
+        events: Arc::clone(&events),
+    };
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server_events = Arc::clone(&events);
+    let server = thread::spawn(move || {
+        let server = ServerBuilder::new(app, &format!("unix://{}", SOCKET))
+            .with_connection_hooks(server_events)
+            .with_cancel_token(server_cancel)
+            .build()
+            .expect("server failed");
+
+        loop {
+            if let Err(Error::Cancelled()) = server.next_client() {
+                break;
+            }
+        }
+    });
+
+    let client = connect(SOCKET).await;
+    let response = client
+        .echo(abci::RequestEcho::default())
+        .await
+        .expect("echo failed");
+    let connection_id = match events.wait_for(1).await.as_slice() {
+        [Event::Connect(id)] => *id,
+        other => panic!("unexpected events {:?}", other),
+    };
+    assert_eq!(response.message, format!("connected {}", connection_id));
+
+    drop(client);
+    assert_eq!(
+        events.wait_for(2).await[1],
+        Event::Disconnect(connection_id, "Closed".to_string())
+    );
+
+    let mut stream = UnixStream::connect(SOCKET).await.expect("connect");
+    // frame of 3 bytes that is not a valid protobuf message
+    stream
+        .write_all(&[3, 0xff, 0xff, 0xff])
+        .await
+        .expect("write frame");
+    let events = events.wait_for(5).await;
+    let Event::Connect(invalid_id) = events[2] else {
+        panic!("unexpected events {:?}", events);
+    };
+    assert_ne!(invalid_id, connection_id);
+    assert_eq!(events[3], Event::DecodeError(invalid_id));
+    assert!(
+        matches!(&events[4], Event::Disconnect(id, reason) if *id == invalid_id && reason.starts_with("DecodeError")),
+        "{:?}",
+        events
+    );
+
+    cancel.cancel();
+    tokio::task::spawn_blocking(move || server.join().expect("server thread panicked"))
+        .await
+        .expect("join server thread");
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Event {
+    Connect(u64),
+    Disconnect(u64, String),
+    DecodeError(u64),
+}
+
+/// Connection events, shared between the app and the server.
+#[derive(Default)]
+struct Events(Mutex<Vec<Event>>);
+
+impl Events {
+    fn push(&self, event: Event) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn connected(&self, connection_id: u64) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .contains(&Event::Connect(connection_id))
+    }
+
+    /// Wait until at least `count` events are recorded.
+    async fn wait_for(&self, count: usize) -> Vec<Event> {
+        for _ in 0..100 {
+            let events = self.0.lock().unwrap().clone();
+            if events.len() >= count {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!(
+            "expected {} events, got {:?}",
+            count,
+            self.0.lock().unwrap()
+        );
+    }
+}
+
+impl ConnectionHooks for Events {
+    fn on_connect(&self, _peer_addr: &str, connection_id: u64) {
+        self.push(Event::Connect(connection_id));
+    }
+
+    fn on_disconnect(&self, connection_id: u64, reason: &DisconnectReason) {
+        self.push(Event::Disconnect(connection_id, format!("{:?}", reason)));
+    }
+
+    fn on_decode_error(&self, connection_id: u64, _error: &Error) {
+        self.push(Event::DecodeError(connection_id));
+    }
+}
+
+struct TestApp {
+    events: Arc<Events>,
+}
+
+impl Application for TestApp {
+    fn echo(
+        &self,
+        _request: abci::RequestEcho,
+    ) -> Result<abci::ResponseEcho, abci::ResponseException> {
+        let connection_id = current_connection_id().expect("connection id");
+        let state = if self.events.connected(connection_id) {
+            "connected"
+        } else {
+            "unknown"
+        };
+        Ok(abci::ResponseEcho {
+            message: format!("{} {}", state, connection_id),
+        })
+    }
+}
@@ -11,6 +11,7 @@ use blake2::{
     digest::{consts::U32, FixedOutput},
     Blake2b, Digest,
 };
+use common::bytes_field;
 use lazy_static::lazy_static;
 use proto::abci::{self, ResponseException};
 use tenderdash_abci::{check_version, proto, Application, CancellationToken};
@@ -27,8 +28,6 @@ lazy_static! {
 #[cfg(feature = "unix")]
 #[test]
 fn test_kvstore() {
-    use std::{fs, os::unix::prelude::PermissionsExt};
-
     use tenderdash_abci::ServerBuilder;
     tracing_subscriber::fmt()
         .with_max_level(LevelFilter::DEBUG)
@@ -56,12 +55,10 @@ fn test_kvstore() {
     let cancel = CANCEL_TOKEN.clone();
     let server = ServerBuilder::new(abci_app, &bind_address)
         .with_cancel_token(cancel)
+        .with_unix_socket_mode(0o777)
         .build()
         .expect("server failed");
 
-    let perms = fs::Permissions::from_mode(0o777);
-    fs::set_permissions(SOCKET, perms).expect("set perms");
-
     let socket_uri = bind_address.to_string();
     let _td = common::docker::TenderdashDocker::new("tenderdash", None, &socket_uri);
 
@@ -166,7 +163,7 @@ impl<'a> KVStoreABCI<'a> {
         KVStoreABCI { kvstore }
     }
 
-    fn lock_kvstore(&self) -> RwLockWriteGuard<KVStore> {
+    fn lock_kvstore(&self) -> RwLockWriteGuard<'_, KVStore> {
         self.kvstore.write().expect("kvstore lock is poisoned")
     }
 }
@@ -203,10 +200,11 @@ impl Application for KVStoreABCI<'_> {
     ) -> Result<abci::ResponseInitChain, abci::ResponseException> {
         // Do nothing special as we're working with a simple example
         Ok(abci::ResponseInitChain {
-            app_hash: self
-                .lock_kvstore()
-                .calculate_persisted_state_hash()
-                .to_vec(),
+            app_hash: bytes_field(
+                self.lock_kvstore()
+                    .calculate_persisted_state_hash()
+                    .to_vec(),
+            ),
             ..Default::default()
         })
     }
@@ -240,13 +238,13 @@ impl Application for KVStoreABCI<'_> {
             .iter()
             .map(|tx| {
                 Some(proto::abci::TxRecord {
-                    tx: bincode::encode_to_vec(tx, bincode::config::standard()).ok()?,
+                    tx: bytes_field(bincode::encode_to_vec(tx, bincode::config::standard()).ok()?),
                     action: proto::abci::tx_record::TxAction::Unmodified.into(),
                 })
             })
             .chain(node_proposed_transactions.map(|tx| {
                 Some(proto::abci::TxRecord {
-                    tx: bincode::encode_to_vec(tx, bincode::config::standard()).ok()?,
+                    tx: bytes_field(bincode::encode_to_vec(tx, bincode::config::standard()).ok()?),
                     action: proto::abci::tx_record::TxAction::Added.into(),
                 })
             }))
@@ -269,7 +267,7 @@ impl Application for KVStoreABCI<'_> {
         Ok(abci::ResponsePrepareProposal {
             tx_records,
             tx_results,
-            app_hash: kvstore_lock.calculate_uncommited_state_hash().to_vec(),
+            app_hash: bytes_field(kvstore_lock.calculate_uncommited_state_hash().to_vec()),
             app_version: 1,
             ..Default::default()
         })
@@ -300,7 +298,7 @@ impl Application for KVStoreABCI<'_> {
         // For simplicity just agree with proposed transactions:
         kvstore_lock.pending_operations = td_proposed_transactions;
 
-        let app_hash = kvstore_lock.calculate_uncommited_state_hash().to_vec();
+        let app_hash = bytes_field(kvstore_lock.calculate_uncommited_state_hash().to_vec());
 
         Ok(abci::ResponseProcessProposal {
             status: abci::response_process_proposal::ProposalStatus::Accept.into(),
@@ -0,0 +1,96 @@
+//! Test middleware layers added to the server.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::connect;
+use tenderdash_abci::{
+    layer::TraceLayer, proto::abci, AsyncApplication, AsyncRequestDispatcher, CancellationToken,
+    Error, Layer, ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Middleware layers
+///
+/// * Given that the server is built with a custom layer and built-in layers
+/// * When the client sends requests
+/// * Then each request passes through the custom layer
+/// * And responses of the application are returned unchanged
+async fn test_layers() {
+    const SOCKET: &str = "/tmp/abci-layers.sock";
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let cancel = CancellationToken::new();
+    let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", SOCKET))
+        .layer(CountLayer(requests.clone()))
+        .layer(TraceLayer)
+        .with_cancel_token(cancel.clone())
+        .build_async()
+        .expect("server failed");
+    let server = tokio::spawn(async move { server.next_client().await });
+
+    let client = connect(SOCKET).await;
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "layered".to_string(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.message, "layered");
+    assert!(matches!(
+        client.query(Default::default()).await,
+        Err(Error::Exception(_))
+    ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    cancel.cancel();
+    drop(client);
+    let result = server.await.expect("join server task");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+/// Layer that counts requests passed to the wrapped dispatcher.
+struct CountLayer(Arc<AtomicUsize>);
+
+impl<D> Layer<D> for CountLayer {
+    type Dispatcher = Count<D>;
+
+    fn layer(&self, inner: D) -> Self::Dispatcher {
+        Count {
+            inner,
+            requests: self.0.clone(),
+        }
+    }
+}
+
+struct Count<D> {
+    inner: D,
+    requests: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl<D: AsyncRequestDispatcher> AsyncRequestDispatcher for Count<D> {
+    async fn handle(&self, request: abci::Request) -> Option<abci::Response> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.inner.handle(request).await
+    }
+}
+
+struct TestApp {}
+
+#[async_trait::async_trait]
+impl AsyncApplication for TestApp {
+    async fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        Err(abci::ResponseException {
+            error: "query failed".to_string(),
+        })
+    }
+}
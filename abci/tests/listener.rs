@@ -0,0 +1,72 @@
+//! Test server using listener created outside of it.
+#![cfg(feature = "tcp")]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+};
+
+use tenderdash_abci::{
+    proto::{
+        abci,
+        prost::{bytes::BytesMut, Message},
+    },
+    Application, ServerBuilder,
+};
+
+#[test]
+/// Feature: Inherited listener
+///
+/// * Given that we have a TCP listener bound to a random port
+/// * When the server is built with this listener
+/// * Then clients connecting to the listener receive responses
+fn test_with_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind listener");
+    let address = listener.local_addr().expect("local address");
+
+    // bind address is ignored when listener is provided
+    let server = ServerBuilder::new(EchoApp {}, "unused")
+        .with_listener(listener)
+        .build()
+        .expect("server failed");
+
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).expect("connect");
+
+        let request = abci::Request {
+            value: Some(abci::request::Value::Echo(abci::RequestEcho {
+                message: "inherited".to_string(),
+            })),
+        };
+        stream
+            .write_all(&request.encode_length_delimited_to_vec())
+            .expect("write request");
+
+        let mut buf = BytesMut::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            if let Ok(response) = abci::Response::decode_length_delimited(buf.clone()) {
+                break response.value;
+            }
+            let n = stream.read(&mut chunk).expect("read response");
+            assert!(n > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    });
+
+    // Connection is closed when the client finishes
+    server.next_client().expect("connection failed");
+    let response = client.join().expect("client panicked");
+
+    assert_eq!(
+        response,
+        Some(abci::response::Value::Echo(abci::ResponseEcho {
+            message: "inherited".to_string(),
+        }))
+    );
+}
+
+struct EchoApp {}
+
+impl Application for EchoApp {}
@@ -0,0 +1,128 @@
+//! Test Prometheus metrics of the ABCI server.
+#![cfg(all(feature = "metrics", feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::thread;
+
+use common::connect;
+use tenderdash_abci::{proto::abci, Application, CancellationToken, Error, Metrics, ServerBuilder};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Prometheus metrics
+///
+/// * Given that the server is built with metrics and a metrics listener
+/// * When a client sends requests
+/// * Then requests, exceptions, frames and connections are counted
+/// * And metrics are served over HTTP
+async fn test_metrics() {
+    const SOCKET: &str = "/tmp/abci-metrics.sock";
+    const METRICS_ADDRESS: &str = "127.0.0.1:26721";
+
+    let metrics = Metrics::new();
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let server_metrics = metrics.clone();
+    // metrics listener runs on the test runtime, so it outlives the server
+    let runtime = tokio::runtime::Handle::current();
+    let server = thread::spawn(move || {
+        let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", SOCKET))
+            .with_runtime(runtime)
+            .with_metrics(server_metrics)
+            .with_metrics_listener(METRICS_ADDRESS)
+            .with_cancel_token(server_cancel)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let client = connect(SOCKET).await;
+    for _ in 0..3 {
+        client
+            .echo(abci::RequestEcho {
+                message: "hello".to_string(),
+            })
+            .await
+            .expect("echo failed");
+    }
+    assert!(matches!(
+        client.query(Default::default()).await,
+        Err(Error::Exception(_))
+    ));
+    drop(client);
+
+    let result = tokio::task::spawn_blocking(move || server.join().expect("server panicked"))
+        .await
+        .expect("join server thread");
+    assert!(result.is_ok(), "{:?}", result);
+
+    let rendered = metrics.render();
+    for line in [
+        "abci_requests_total{method=\"Echo\"} 3",
+        "abci_requests_total{method=\"Query\"} 1",
+        "abci_exceptions_total{method=\"Echo\"} 0",
+        "abci_exceptions_total{method=\"Query\"} 1",
+        "abci_request_duration_seconds_count{method=\"Echo\"} 3",
+        "abci_request_frame_bytes_count 4",
+        "abci_response_frame_bytes_count 4",
+        "abci_connections_accepted_total 1",
+        "abci_connections_closed_total 1",
+        "abci_queue_depth{queue=\"request\"} 0",
+    ] {
+        assert!(
+            rendered.lines().any(|l| l == line),
+            "missing {:?} in:\n{}",
+            line,
+            rendered
+        );
+    }
+
+    let response = http_get(METRICS_ADDRESS, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(response.contains("abci_requests_total{method=\"Echo\"} 3"));
+
+    let response = http_get(METRICS_ADDRESS, "/other").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{}",
+        response
+    );
+
+    cancel.cancel();
+}
+
+/// Send HTTP GET request for `path` and return the whole response.
+async fn http_get(address: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .expect("connect to metrics listener");
+    stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).as_bytes())
+        .await
+        .expect("send HTTP request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .expect("read HTTP response");
+    response
+}
+
+struct TestApp {}
+
+impl Application for TestApp {
+    fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        Err(abci::ResponseException {
+            error: "query not supported".to_string(),
+        })
+    }
+}
@@ -0,0 +1,95 @@
+//! Test isolation of panics raised by the application.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::thread::{self, JoinHandle};
+
+use common::connect;
+use tenderdash_abci::{
+    proto::abci, Application, CancellationToken, Error, PanicPolicy, ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Panic isolation
+///
+/// * Given that the server is built with PanicPolicy::Continue
+/// * When the application panics while processing a request
+/// * Then the client receives an exception with the method and panic message
+/// * And next requests are processed
+async fn test_panic_continue() {
+    const SOCKET: &str = "/tmp/abci-panic-continue.sock";
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, PanicPolicy::Continue, cancel.clone());
+    let client = connect(SOCKET).await;
+
+    let Err(Error::Exception(error)) = client.query(Default::default()).await else {
+        panic!("query must fail");
+    };
+    assert!(error.starts_with("Query request "), "{}", error);
+    assert!(error.ends_with(" panicked: query failed"), "{}", error);
+
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "alive".to_string(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.message, "alive");
+
+    cancel.cancel();
+    drop(client);
+    let result = tokio::task::spawn_blocking(move || server.join().expect("server panicked"))
+        .await
+        .expect("join server thread");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Given PanicPolicy::Cancel, when the application panics, then the client
+/// receives an exception and the server is cancelled.
+async fn test_panic_cancel() {
+    const SOCKET: &str = "/tmp/abci-panic-cancel.sock";
+    let cancel = CancellationToken::new();
+    let server = start_server(SOCKET, PanicPolicy::Cancel, cancel.clone());
+    let client = connect(SOCKET).await;
+
+    assert!(matches!(
+        client.query(Default::default()).await,
+        Err(Error::Exception(_))
+    ));
+
+    let result = tokio::task::spawn_blocking(move || server.join().expect("server panicked"))
+        .await
+        .expect("join server thread");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+    assert!(cancel.is_cancelled());
+}
+
+/// Start server on Unix socket in a separate thread.
+fn start_server(
+    socket: &'static str,
+    policy: PanicPolicy,
+    cancel: CancellationToken,
+) -> JoinHandle<Result<(), Error>> {
+    thread::spawn(move || {
+        let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", socket))
+            .with_panic_policy(policy)
+            .with_cancel_token(cancel)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    })
+}
+
+struct TestApp {}
+
+impl Application for TestApp {
+    fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        panic!("query failed");
+    }
+}
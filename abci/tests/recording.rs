@@ -0,0 +1,111 @@
+//! Test recording and replay of ABCI sessions.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    thread,
+};
+
+use common::connect_blocking;
+use tenderdash_abci::{
+    proto::abci, recording::Replayer, Application, CancellationToken, Recorder, ServerBuilder,
+};
+
+#[test]
+/// Feature: Record and replay
+///
+/// * Given that the application is wrapped in a recorder
+/// * When a client sends requests to the server
+/// * Then requests, responses and connection ids are recorded
+/// * And replay with the same application reports no divergence
+/// * And replay with a diverging application reports the differing field
+fn test_record_and_replay() {
+    const SOCKET: &str = "/tmp/abci-recording.sock";
+    let recording = std::env::temp_dir().join("abci-recording-test.rec");
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let app = Recorder::create(CounterApp::new(1), &recording).expect("create recording");
+    let server = thread::spawn(move || {
+        let server = ServerBuilder::new(app, &format!("unix://{}", SOCKET))
+            .with_cancel_token(server_cancel)
+            .build()
+            .expect("server failed");
+        server.next_client().ok();
+    });
+
+    let client = connect_blocking(SOCKET);
+    client
+        .echo(abci::RequestEcho {
+            message: "hello".to_string(),
+        })
+        .expect("echo failed");
+    for _ in 0..3 {
+        client
+            .finalize_block(Default::default())
+            .expect("finalize block failed");
+    }
+    let info = client.info(Default::default()).expect("info failed");
+    assert_eq!(info.last_block_height, 3);
+
+    cancel.cancel();
+    drop(client);
+    server.join().expect("server thread panicked");
+
+    let replayer = Replayer::open(&recording).expect("read recording");
+    let records = replayer.records();
+    assert_eq!(records.len(), 5);
+    assert!(records.iter().all(|record| record.connection_id > 0));
+    assert!(records.iter().all(|record| record.time.is_some()));
+
+    assert_eq!(replayer.replay(&CounterApp::new(1)), None);
+
+    let divergence = replayer
+        .replay(&CounterApp::new(2))
+        .expect("divergence not detected");
+    assert_eq!(divergence.index, 4);
+    assert_eq!(divergence.fields.len(), 1);
+    assert_eq!(divergence.fields[0].path, "value.Info.last_block_height");
+    assert_eq!(divergence.fields[0].expected.as_deref(), Some("3"));
+    assert_eq!(divergence.fields[0].actual.as_deref(), Some("6"));
+    assert!(divergence.to_string().contains("to Info"), "{}", divergence);
+
+    std::fs::remove_file(recording).ok();
+}
+
+/// Application that increases its height by `step` on each block.
+struct CounterApp {
+    step: i64,
+    height: AtomicI64,
+}
+
+impl CounterApp {
+    fn new(step: i64) -> Self {
+        Self {
+            step,
+            height: AtomicI64::new(0),
+        }
+    }
+}
+
+impl Application for CounterApp {
+    fn info(
+        &self,
+        _request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        Ok(abci::ResponseInfo {
+            last_block_height: self.height.load(Ordering::SeqCst),
+            ..Default::default()
+        })
+    }
+
+    fn finalize_block(
+        &self,
+        _request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        self.height.fetch_add(self.step, Ordering::SeqCst);
+        Ok(Default::default())
+    }
+}
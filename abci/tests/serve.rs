@@ -0,0 +1,77 @@
+//! Test run loop of the server.
+#![cfg(all(feature = "client", feature = "unix"))]
+
+mod common;
+
+use std::time::Duration;
+
+use common::connect;
+use tenderdash_abci::{
+    proto::abci, AsyncApplication, CancellationToken, Reconnect, ServePolicy, ServerBuilder,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: Server run loop
+///
+/// * Given that the async server is served with default policy
+/// * When clients connect one after another
+/// * Then each of them is served
+/// * And serve() returns Ok when the server is cancelled
+async fn test_serve_until_cancelled() {
+    const SOCKET: &str = "/tmp/abci-serve.sock";
+
+    let cancel = CancellationToken::new();
+    let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", SOCKET))
+        .with_cancel_token(cancel.clone())
+        .build_async()
+        .expect("server failed");
+    let serve = tokio::spawn(async move { server.serve(ServePolicy::new()).await });
+
+    for i in 0..3 {
+        let client = connect(SOCKET).await;
+        let message = format!("client {}", i);
+        let response = client
+            .echo(abci::RequestEcho {
+                message: message.clone(),
+            })
+            .await
+            .expect("echo failed");
+        assert_eq!(response.message, message);
+    }
+
+    cancel.cancel();
+    serve.await.expect("join serve task").expect("serve failed");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Given Reconnect::Never policy, when the client disconnects, then serve()
+/// returns Ok.
+async fn test_serve_once() {
+    const SOCKET: &str = "/tmp/abci-serve-once.sock";
+
+    let server = ServerBuilder::new(TestApp {}, &format!("unix://{}", SOCKET))
+        .build_async()
+        .expect("server failed");
+    let serve = tokio::spawn(async move {
+        server
+            .serve(ServePolicy::new().with_reconnect(Reconnect::Never))
+            .await
+    });
+
+    let client = connect(SOCKET).await;
+    client
+        .echo(abci::RequestEcho::default())
+        .await
+        .expect("echo failed");
+    drop(client);
+
+    tokio::time::timeout(Duration::from_secs(5), serve)
+        .await
+        .expect("serve did not return")
+        .expect("join serve task")
+        .expect("serve failed");
+}
+
+struct TestApp {}
+
+impl AsyncApplication for TestApp {}
@@ -0,0 +1,246 @@
+//! Test simulated Tenderdash network.
+#![cfg(feature = "testing")]
+
+mod common;
+
+use std::sync::Mutex;
+
+use blake2::{
+    digest::{consts::U32, generic_array::GenericArray},
+    Blake2b, Digest,
+};
+use common::bytes_field;
+use tenderdash_abci::{
+    proto::abci::{
+        self, response_process_proposal::ProposalStatus,
+        response_verify_vote_extension::VerifyStatus, tx_record::TxAction,
+    },
+    testing::{SimulationError, Simulator},
+    Application,
+};
+
+#[test]
+/// Feature: Simulated consensus
+///
+/// * Given a network of 3 nodes running the same application
+/// * When transactions are checked and blocks are committed in several rounds
+/// * Then all nodes agree on app hash
+/// * And transactions rejected by CheckTx or removed by the proposer are not
+///   committed
+fn test_simulator_consensus() {
+    let mut simulator = Simulator::new(vec![
+        ChainApp::default(),
+        ChainApp::default(),
+        ChainApp::default(),
+    ])
+    .with_rounds(2);
+
+    let genesis = simulator.init_chain().expect("init chain");
+    assert_eq!(genesis.app_hash, vec![0u8; 32]);
+
+    let response = simulator.check_tx(b"tx1".to_vec()).expect("check tx");
+    assert_eq!(response.code, 0);
+    let response = simulator.check_tx(b"invalid".to_vec()).expect("check tx");
+    assert_ne!(response.code, 0);
+    simulator.inject_tx(b"removed".to_vec());
+    simulator.inject_tx(b"tx2".to_vec());
+
+    let block = simulator.run_height().expect("height 1");
+    assert_eq!(block.height, 1);
+    assert_eq!(block.round, 1);
+    assert_eq!(block.txs, vec![b"tx1".to_vec(), b"tx2".to_vec()]);
+    assert_eq!(simulator.mempool(), &[b"removed".to_vec()]);
+
+    let blocks = simulator.run(3).expect("heights 2-4");
+    assert_eq!(blocks.last().expect("block").height, 4);
+    assert_eq!(simulator.height(), 4);
+    for node in simulator.nodes() {
+        let state = node.state.lock().unwrap();
+        assert_eq!(state.height, 4);
+        assert_eq!(state.app_hash.as_slice(), simulator.app_hash().unwrap());
+    }
+}
+
+#[test]
+/// Given a node with diverging state, when a block is processed, then the
+/// simulation fails with app hash mismatch.
+fn test_simulator_app_hash_mismatch() {
+    let mut simulator = Simulator::new(vec![
+        ChainApp::default(),
+        ChainApp {
+            salt: b"byzantine".to_vec(),
+            ..Default::default()
+        },
+    ]);
+    simulator.init_chain().expect("init chain");
+    simulator.inject_tx(b"tx".to_vec());
+
+    let result = simulator.run_height();
+    assert!(
+        matches!(&result, Err(SimulationError::Consensus { height: 1, reason }) if reason.contains("app hash")),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+/// Feature: Handshake
+///
+/// * Given a network that committed some blocks
+/// * When a node is replaced with a fresh one and handshake is performed
+/// * Then the node is initialized and missing blocks are replayed
+/// * And the network continues to commit blocks
+fn test_simulator_handshake() {
+    let mut simulator = Simulator::new(vec![ChainApp::default(), ChainApp::default()]);
+    simulator.init_chain().expect("init chain");
+    for i in 0..3 {
+        simulator.inject_tx(format!("tx{}", i).into_bytes());
+        simulator.run_height().expect("run height");
+    }
+
+    simulator.replace_node(1, ChainApp::default());
+    let info = simulator.handshake(1).expect("handshake");
+    assert_eq!(info.last_block_height, 0);
+    assert_eq!(simulator.nodes()[1].state.lock().unwrap().height, 3);
+
+    // Node is up to date, so nothing is replayed
+    let info = simulator.handshake(1).expect("second handshake");
+    assert_eq!(info.last_block_height, 3);
+
+    simulator.run_height().expect("run height after handshake");
+}
+
+/// State of [ChainApp].
+#[derive(Default)]
+struct State {
+    height: i64,
+    app_hash: Vec<u8>,
+}
+
+/// Application that hashes transactions of each block into its app hash.
+#[derive(Default)]
+struct ChainApp {
+    /// Mixed into app hash to simulate non-deterministic application.
+    salt: Vec<u8>,
+    state: Mutex<State>,
+}
+
+impl ChainApp {
+    /// Calculate app hash after executing `txs` on top of current state.
+    fn next_app_hash<T: AsRef<[u8]>>(&self, txs: &[T]) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut hasher: Blake2b<U32> = Digest::new();
+        hasher.update(&state.app_hash);
+        hasher.update(&self.salt);
+        for tx in txs {
+            hasher.update(tx.as_ref());
+        }
+        let hash: GenericArray<u8, U32> = hasher.finalize();
+        hash.to_vec()
+    }
+}
+
+impl Application for ChainApp {
+    fn info(
+        &self,
+        _request: abci::RequestInfo,
+    ) -> Result<abci::ResponseInfo, abci::ResponseException> {
+        let state = self.state.lock().unwrap();
+        Ok(abci::ResponseInfo {
+            last_block_height: state.height,
+            last_block_app_hash: state.app_hash.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn init_chain(
+        &self,
+        _request: abci::RequestInitChain,
+    ) -> Result<abci::ResponseInitChain, abci::ResponseException> {
+        let mut state = self.state.lock().unwrap();
+        state.app_hash = vec![0u8; 32];
+        Ok(abci::ResponseInitChain {
+            app_hash: bytes_field(state.app_hash.clone()),
+            ..Default::default()
+        })
+    }
+
+    fn check_tx(
+        &self,
+        request: abci::RequestCheckTx,
+    ) -> Result<abci::ResponseCheckTx, abci::ResponseException> {
+        Ok(abci::ResponseCheckTx {
+            code: u32::from(request.tx[..] == b"invalid"[..]),
+            ..Default::default()
+        })
+    }
+
+    fn prepare_proposal(
+        &self,
+        request: abci::RequestPrepareProposal,
+    ) -> Result<abci::ResponsePrepareProposal, abci::ResponseException> {
+        let tx_records = request
+            .txs
+            .into_iter()
+            .map(|tx| abci::TxRecord {
+                action: if tx[..] == b"removed"[..] {
+                    TxAction::Removed
+                } else {
+                    TxAction::Unmodified
+                }
+                .into(),
+                tx,
+            })
+            .collect::<Vec<_>>();
+        let txs = tx_records
+            .iter()
+            .filter(|record| record.action == TxAction::Unmodified as i32)
+            .map(|record| record.tx.clone())
+            .collect::<Vec<_>>();
+
+        Ok(abci::ResponsePrepareProposal {
+            tx_records,
+            app_hash: bytes_field(self.next_app_hash(&txs)),
+            ..Default::default()
+        })
+    }
+
+    fn process_proposal(
+        &self,
+        request: abci::RequestProcessProposal,
+    ) -> Result<abci::ResponseProcessProposal, abci::ResponseException> {
+        Ok(abci::ResponseProcessProposal {
+            status: ProposalStatus::Accept.into(),
+            app_hash: bytes_field(self.next_app_hash(&request.txs)),
+            ..Default::default()
+        })
+    }
+
+    fn verify_vote_extension(
+        &self,
+        _request: abci::RequestVerifyVoteExtension,
+    ) -> Result<abci::ResponseVerifyVoteExtension, abci::ResponseException> {
+        Ok(abci::ResponseVerifyVoteExtension {
+            status: VerifyStatus::Accept.into(),
+        })
+    }
+
+    fn finalize_block(
+        &self,
+        request: abci::RequestFinalizeBlock,
+    ) -> Result<abci::ResponseFinalizeBlock, abci::ResponseException> {
+        let block = request.block.unwrap_or_default();
+        let txs = block.data.unwrap_or_default().txs;
+        let app_hash = self.next_app_hash(&txs);
+        if app_hash != block.header.unwrap_or_default().app_hash {
+            return Err(abci::ResponseException {
+                error: "app hash mismatch".to_string(),
+            });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.height = request.height;
+        state.app_hash = app_hash;
+        Ok(Default::default())
+    }
+}
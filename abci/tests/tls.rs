@@ -0,0 +1,275 @@
+//! Test TLS-encrypted TCP transport.
+#![cfg(feature = "tls")]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use tenderdash_abci::{
+    proto::{
+        abci,
+        prost::{bytes::BytesMut, Message},
+    },
+    tls::rustls::{
+        self,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+        ClientConnection, RootCertStore, StreamOwned,
+    },
+    Application, CancellationToken, ServerBuilder, TlsConfig,
+};
+
+const ADDRESS: &str = "127.0.0.1:26711";
+
+#[test]
+/// Feature: TLS transport with client certificate authentication
+///
+/// * Given that we have a TLS server that requires client certificates
+/// * When a client without certificate connects
+/// * Then the connection is rejected
+/// * When a client with certificate signed by trusted CA connects
+/// * Then it receives responses to its requests
+fn test_tls_client_auth() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init()
+        .ok();
+
+    let ca = Issuer::new();
+    let (server_cert, server_key) = ca.issue("localhost");
+    let (client_cert, client_key) = ca.issue("client");
+
+    let tls = TlsConfig::from_pem(
+        server_cert.pem().as_bytes(),
+        server_key.serialize_pem().as_bytes(),
+        Some(ca.cert.pem().as_bytes()),
+    )
+    .expect("invalid TLS config");
+
+    let cancel = CancellationToken::new();
+    let bind_address = format!("tls://{}", ADDRESS);
+    let server_cancel = cancel.clone();
+    let server_thread = thread::spawn(move || {
+        let server = ServerBuilder::new(EchoApp {}, &bind_address)
+            .with_cancel_token(server_cancel)
+            .with_max_connections(2)
+            .with_tls(tls)
+            .build()
+            .expect("server failed");
+
+        server.next_client()
+    });
+
+    let roots = {
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert.der().clone()).expect("add CA");
+        Arc::new(roots)
+    };
+
+    let anonymous = client_config(&roots, None);
+    let mut client = connect(anonymous);
+    assert!(
+        try_echo(&mut client, "anonymous").is_err(),
+        "client without certificate must be rejected"
+    );
+
+    let authenticated = client_config(&roots, Some((client_cert, client_key)));
+    let mut client = connect(authenticated);
+    assert_eq!(
+        try_echo(&mut client, "hello").expect("echo failed"),
+        "hello"
+    );
+
+    cancel.cancel();
+    drop(client);
+    server_thread.join().expect("server thread panicked").ok();
+}
+
+#[test]
+/// Feature: TLS handshake timeout
+///
+/// * Given a TLS server serving one connection at a time
+/// * When a client opens TCP connection and never starts TLS handshake
+/// * Then the connection is closed after the handshake timeout
+/// * When a client stalls the handshake and the server is cancelled
+/// * Then the server stops without waiting for the handshake
+fn test_tls_handshake_timeout() {
+    const ADDRESS: &str = "127.0.0.1:26713";
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    let ca = Issuer::new();
+    let (cert, key) = ca.issue("localhost");
+    let tls = TlsConfig::from_pem(cert.pem().as_bytes(), key.serialize_pem().as_bytes(), None)
+        .expect("invalid TLS config")
+        .with_handshake_timeout(TIMEOUT);
+
+    let cancel = CancellationToken::new();
+    let server_cancel = cancel.clone();
+    let (results_tx, results) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let server = ServerBuilder::new(EchoApp {}, &format!("tls://{}", ADDRESS))
+            .with_cancel_token(server_cancel)
+            .with_tls(tls)
+            .build()
+            .expect("server failed");
+        for _ in 0..2 {
+            let started = Instant::now();
+            let result = server.next_client();
+            results_tx.send((started.elapsed(), result)).ok();
+        }
+    });
+
+    let mut idle = connect_tcp(ADDRESS);
+    let (elapsed, result) = results
+        .recv_timeout(Duration::from_secs(5))
+        .expect("handshake did not time out");
+    assert!(result.is_ok(), "{:?}", result);
+    assert!(elapsed >= TIMEOUT, "{:?}", elapsed);
+    let mut buf = [0u8; 1];
+    assert_eq!(idle.read(&mut buf).unwrap_or(0), 0, "connection not closed");
+
+    let _stalled = connect_tcp(ADDRESS);
+    thread::sleep(Duration::from_millis(50));
+    cancel.cancel();
+    let (elapsed, result) = results
+        .recv_timeout(TIMEOUT / 2)
+        .expect("server did not stop during handshake");
+    assert!(elapsed < TIMEOUT, "{:?}", elapsed);
+    assert!(
+        matches!(result, Err(tenderdash_abci::Error::Cancelled())),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+/// Given TLS configuration, when the server is bound to a `tcp://` address,
+/// then configuration error is returned.
+fn test_tls_requires_tls_scheme() {
+    let ca = Issuer::new();
+    let (cert, key) = ca.issue("localhost");
+    let tls = TlsConfig::from_pem(cert.pem().as_bytes(), key.serialize_pem().as_bytes(), None)
+        .expect("invalid TLS config");
+
+    let result = ServerBuilder::new(EchoApp {}, "tcp://127.0.0.1:26712")
+        .with_tls(tls)
+        .build();
+    assert!(matches!(
+        result,
+        Err(tenderdash_abci::Error::Configuration(_))
+    ));
+}
+
+/// Certificate authority used to issue test certificates.
+struct Issuer {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl Issuer {
+    fn new() -> Self {
+        let key = KeyPair::generate().expect("generate CA key");
+        let mut params = CertificateParams::new(Vec::new()).expect("CA params");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let cert = params.self_signed(&key).expect("self-sign CA");
+
+        Self { cert, key }
+    }
+
+    fn issue(&self, name: &str) -> (Certificate, KeyPair) {
+        let key = KeyPair::generate().expect("generate key");
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .expect("certificate params")
+            .signed_by(&key, &self.cert, &self.key)
+            .expect("sign certificate");
+
+        (cert, key)
+    }
+}
+
+fn client_config(
+    roots: &Arc<RootCertStore>,
+    identity: Option<(Certificate, KeyPair)>,
+) -> Arc<rustls::ClientConfig> {
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .expect("protocol versions")
+    .with_root_certificates(roots.clone());
+
+    let config = match identity {
+        Some((cert, key)) => {
+            let chain: Vec<CertificateDer<'static>> = vec![cert.der().clone()];
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+            builder
+                .with_client_auth_cert(chain, key)
+                .expect("client certificate")
+        },
+        None => builder.with_no_client_auth(),
+    };
+
+    Arc::new(config)
+}
+
+/// Open TCP connection to `address`, retrying until the socket is ready.
+fn connect_tcp(address: &str) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(address) {
+            return stream;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("cannot connect to {}", address);
+}
+
+/// Connect to the server, retrying until the socket is ready.
+fn connect(config: Arc<rustls::ClientConfig>) -> StreamOwned<ClientConnection, TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(ADDRESS) {
+            let server_name = ServerName::try_from("localhost").expect("server name");
+            let connection = ClientConnection::new(config, server_name).expect("TLS connection");
+            return StreamOwned::new(connection, stream);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("cannot connect to {}", ADDRESS);
+}
+
+/// Send echo request and return message from the response.
+fn try_echo(
+    stream: &mut StreamOwned<ClientConnection, TcpStream>,
+    message: &str,
+) -> std::io::Result<String> {
+    let request = abci::Request {
+        value: Some(abci::request::Value::Echo(abci::RequestEcho {
+            message: message.to_string(),
+        })),
+    };
+    stream.write_all(&request.encode_length_delimited_to_vec())?;
+    stream.flush()?;
+
+    let mut buf = BytesMut::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        if let Ok(response) = abci::Response::decode_length_delimited(buf.clone()) {
+            match response.value {
+                Some(abci::response::Value::Echo(echo)) => return Ok(echo.message),
+                value => panic!("unexpected response: {:?}", value),
+            }
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+struct EchoApp {}
+
+impl Application for EchoApp {}
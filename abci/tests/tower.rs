@@ -0,0 +1,70 @@
+//! Test serving tower services.
+#![cfg(all(feature = "client", feature = "unix", feature = "tower"))]
+
+mod common;
+
+use std::time::Duration;
+
+use common::connect;
+use tenderdash_abci::{
+    proto::abci, service::ApplicationService, AsyncApplication, CancellationToken, Error,
+    ServerBuilder,
+};
+use tower::ServiceBuilder;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+/// Feature: tower interoperability
+///
+/// * Given an application wrapped in tower timeout and concurrency limit
+///   middleware
+/// * When the server is built from the service
+/// * Then requests are processed by the application
+/// * And requests exceeding the timeout receive an exception
+async fn test_tower_middleware() {
+    const SOCKET: &str = "/tmp/abci-tower.sock";
+
+    let service = ServiceBuilder::new()
+        .buffer(16)
+        .concurrency_limit(4)
+        .timeout(Duration::from_millis(200))
+        .service(ApplicationService::new(TestApp {}));
+
+    let cancel = CancellationToken::new();
+    let server = ServerBuilder::from_service(service, &format!("unix://{}", SOCKET))
+        .with_cancel_token(cancel.clone())
+        .build_async()
+        .expect("server failed");
+    let server = tokio::spawn(async move { server.next_client().await });
+
+    let client = connect(SOCKET).await;
+    let response = client
+        .echo(abci::RequestEcho {
+            message: "tower".to_string(),
+        })
+        .await
+        .expect("echo failed");
+    assert_eq!(response.message, "tower");
+
+    let Err(Error::Exception(error)) = client.query(Default::default()).await else {
+        panic!("query must time out");
+    };
+    assert!(error.contains("timed out"), "{}", error);
+
+    cancel.cancel();
+    drop(client);
+    let result = server.await.expect("join server task");
+    assert!(matches!(result, Err(Error::Cancelled())), "{:?}", result);
+}
+
+struct TestApp {}
+
+#[async_trait::async_trait]
+impl AsyncApplication for TestApp {
+    async fn query(
+        &self,
+        _request: abci::RequestQuery,
+    ) -> Result<abci::ResponseQuery, abci::ResponseException> {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok(Default::default())
+    }
+}
@@ -2,7 +2,6 @@ use std::sync::Arc;
 
 use tenderdash_abci::RequestDispatcher;
 mod common;
-use std::{fs, os::unix::prelude::PermissionsExt};
 
 use tenderdash_abci::proto;
 use tracing_subscriber::filter::LevelFilter;
@@ -30,12 +29,10 @@ fn test_unix_socket_server() {
     let app = TestDispatcher {};
 
     let server = ServerBuilder::new(app, &bind_address)
+        .with_unix_socket_mode(0o777)
         .build()
         .expect("server failed");
 
-    let perms = fs::Permissions::from_mode(0o777);
-    fs::set_permissions(SOCKET, perms).expect("set perms");
-
     let td = Arc::new(common::docker::TenderdashDocker::new(
         "tenderdash_unix",
         None,
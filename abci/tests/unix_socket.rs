@@ -0,0 +1,76 @@
+//! Test Unix socket file handling.
+#![cfg(feature = "unix")]
+
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+};
+
+use tenderdash_abci::{Application, ServerBuilder};
+
+#[test]
+/// Feature: Unix socket file lifecycle
+///
+/// * Given that there is a stale socket file at the bind path
+/// * When the server is built with custom socket mode
+/// * Then the stale file is replaced with socket having requested permissions
+/// * And the socket file is removed when the server is dropped
+fn test_socket_file_lifecycle() {
+    const SOCKET: &str = "/tmp/abci-socket-lifecycle.sock";
+    let bind_address = format!("unix://{}", SOCKET);
+
+    // stale socket left by previous process; std listener does not remove it
+    fs::remove_file(SOCKET).ok();
+    drop(std::os::unix::net::UnixListener::bind(SOCKET).expect("bind stale socket"));
+    assert!(Path::new(SOCKET).exists());
+
+    let server = ServerBuilder::new(EchoApp {}, &bind_address)
+        .with_unix_socket_mode(0o660)
+        .with_unix_socket_group(current_gid())
+        .build()
+        .expect("server failed");
+
+    let metadata = fs::metadata(SOCKET).expect("socket metadata");
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+
+    drop(server);
+    assert!(!Path::new(SOCKET).exists(), "socket file not removed");
+}
+
+#[test]
+/// Given a regular file at the bind path, when the server is built, then error
+/// is returned and the file is left intact.
+fn test_refuse_to_replace_regular_file() {
+    const SOCKET: &str = "/tmp/abci-socket-regular-file.sock";
+    fs::write(SOCKET, "not a socket").expect("write file");
+
+    let result = ServerBuilder::new(EchoApp {}, &format!("unix://{}", SOCKET)).build();
+    assert!(matches!(
+        result,
+        Err(tenderdash_abci::Error::Configuration(_))
+    ));
+    assert_eq!(
+        fs::read_to_string(SOCKET).expect("read file"),
+        "not a socket"
+    );
+
+    fs::remove_file(SOCKET).ok();
+}
+
+/// Group ID of the current process, read from a file it owns.
+fn current_gid() -> u32 {
+    use std::os::unix::fs::MetadataExt;
+
+    const PROBE: &str = "/tmp/abci-socket-gid-probe";
+    fs::write(PROBE, "").expect("write probe file");
+    let gid = fs::metadata(PROBE).expect("probe metadata").gid();
+    fs::remove_file(PROBE).ok();
+
+    gid
+}
+
+struct EchoApp {}
+
+impl Application for EchoApp {}
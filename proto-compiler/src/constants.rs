@@ -85,6 +85,26 @@ const ALIAS_TIMESTAMP: &str = r#"#[cfg_attr(feature = "serde", serde(alias = "Ti
 const ALIAS_PARTS: &str = r#"#[cfg_attr(feature = "serde", serde(alias = "parts"))]"#;
 const DERIVE_FROM: &str = r#"#[derive(derive_more::From)]"#;
 const DERIVE_FROM_STR: &str = r#"#[derive(derive_more::FromStr)]"#;
+/// Fields generated as `bytes::Bytes` instead of `Vec<u8>` when `bytes` feature
+/// is enabled.
+///
+/// These fields can be large, so decoding them from `bytes::Bytes` buffer
+/// references the buffer instead of copying the data.
+/// The paths are defined as in the prost_build::Config::bytes here:
+/// <https://docs.rs/prost-build/0.13.3/prost_build/struct.Config.html#method.bytes>
+pub static BYTES_FIELDS: &[&str] = &[
+    ".tendermint.abci.RequestCheckTx.tx",
+    ".tendermint.abci.RequestPrepareProposal.txs",
+    ".tendermint.abci.RequestProcessProposal.txs",
+    ".tendermint.abci.TxRecord.tx",
+    ".tendermint.abci.RequestOfferSnapshot.app_hash",
+    ".tendermint.abci.RequestApplySnapshotChunk.chunk",
+    ".tendermint.abci.ResponseLoadSnapshotChunk.chunk",
+    ".tendermint.abci.ResponseInitChain.app_hash",
+    ".tendermint.abci.ResponsePrepareProposal.app_hash",
+    ".tendermint.abci.ResponseProcessProposal.app_hash",
+];
+
 /// Custom type attributes applied on top of protobuf structs
 /// The first item in the tuple defines the message where the annotation should
 /// apply and the second item is the string that should be added as annotation.
@@ -327,12 +327,28 @@ pub(crate) fn tenderdash_commitish() -> String {
     }
 }
 
-/// Save the commitish of last successful download to a file in a state file,
-/// located in the `dir` directory and named `download.state`.
-pub(crate) fn save_state(dir: &Path, commitish: &str) {
+/// Features of the compiler that change generated code.
+pub(crate) fn codegen_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "bytes") {
+        features.push("bytes");
+    }
+    features
+}
+
+/// State of code generation: Tenderdash commitish and `features` of the
+/// compiler, saved with [save_state()].
+pub(crate) fn generation_state(commitish: &str, features: &[&str]) -> String {
+    format!("{} features=[{}]", commitish, features.join(","))
+}
+
+/// Save the state of last successful code generation, as returned by
+/// [generation_state()], to a state file located in the `dir` directory and
+/// named `download.state`.
+pub(crate) fn save_state(dir: &Path, state: &str) {
     let state_file = PathBuf::from(&dir).join("download.state");
 
-    std::fs::write(&state_file, commitish)
+    std::fs::write(&state_file, state)
         .map_err(|e| {
             println!(
                 "[warn] => Failed to write download.state file {}: {}",
@@ -349,12 +365,10 @@ pub(crate) fn save_state(dir: &Path, commitish: &str) {
 pub(crate) fn check_state(dir: &Path, commitish: &str) -> bool {
     let state_file = PathBuf::from(&dir).join("download.state");
 
-    let expected = commitish.to_string();
-
     match read_to_string(state_file) {
         Ok(content) => {
-            println!("[info] => Detected Tenderdash version: {}.", content);
-            content == expected
+            println!("[info] => Detected generation state: {}.", content);
+            content.split_whitespace().next() == Some(commitish)
         },
         Err(_) => false,
     }
@@ -4,13 +4,13 @@ use tempfile::tempdir;
 
 mod functions;
 use functions::{
-    abci_version, copy_files, fetch_commitish, find_proto_files, generate_tenderdash_lib,
-    tenderdash_commitish, tenderdash_version,
+    abci_version, codegen_features, copy_files, fetch_commitish, find_proto_files,
+    generate_tenderdash_lib, generation_state, tenderdash_commitish, tenderdash_version,
 };
 
 mod constants;
 pub use constants::GenerationMode;
-use constants::{CUSTOM_FIELD_ATTRIBUTES, CUSTOM_TYPE_ATTRIBUTES, TENDERDASH_REPO};
+use constants::{BYTES_FIELDS, CUSTOM_FIELD_ATTRIBUTES, CUSTOM_TYPE_ATTRIBUTES, TENDERDASH_REPO};
 
 use crate::functions::{check_deps, check_state, save_state};
 
@@ -26,11 +26,15 @@ use crate::functions::{check_deps, check_state, save_state};
 pub fn proto_compile(mode: GenerationMode) {
     let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
 
-    let prost_out_dir = root
-        .join("..")
-        .join("proto")
-        .join("src")
-        .join(mode.module_name());
+    // Sources generated with different features are written to separate
+    // directories, so that switching features never leaves stale sources.
+    let features = codegen_features();
+    let module_dir = if features.is_empty() {
+        mode.module_name()
+    } else {
+        format!("{}_{}", mode.module_name(), features.join("_"))
+    };
+    let prost_out_dir = root.join("..").join("proto").join("src").join(module_dir);
     let tenderdash_lib_target = prost_out_dir.join("mod.rs");
 
     let out_dir = var("OUT_DIR")
@@ -53,6 +57,7 @@ pub fn proto_compile(mode: GenerationMode) {
     let thirdparty_dir = root.join("third_party");
 
     let commitish = tenderdash_commitish();
+    let state = generation_state(&commitish, &features);
 
     // ensure dependencies are up to date
     if let Err(e) = check_deps() {
@@ -105,6 +110,13 @@ pub fn proto_compile(mode: GenerationMode) {
         println!("[info] => Adding field attribute: {:?}", field_attribute);
         pb.field_attribute(field_attribute.0, field_attribute.1);
     }
+    if cfg!(feature = "bytes") {
+        println!(
+            "[info] => Using bytes::Bytes for fields: {:?}",
+            BYTES_FIELDS
+        );
+        pb.bytes(BYTES_FIELDS);
+    }
     // The below in-place path redirection replaces references to the Duration
     // and Timestamp WKTs with our own versions that have valid doctest comments.
     // See also https://github.com/danburkert/prost/issues/374 .
@@ -149,6 +161,6 @@ pub fn proto_compile(mode: GenerationMode) {
         &mode,
     );
 
-    save_state(&prost_out_dir, &commitish);
+    save_state(&prost_out_dir, &state);
     println!("[info] => Done!");
 }
@@ -1,5 +1,10 @@
 //! tenderdash-proto library gives the developer access to the Tenderdash
 //! proto-defined structs.
+//!
+//! With `zero-copy` feature, large fields, like transactions, are generated as
+//! `bytes::Bytes` instead of `Vec<u8>`. This feature is not additive: it
+//! changes types of public fields for all crates in the dependency graph, so
+//! it should only be enabled by the final application.
 
 #![cfg_attr(not(feature = "grpc"), no_std)]
 #![deny(warnings, trivial_casts, trivial_numeric_casts, unused_import_braces)]
@@ -33,7 +38,9 @@ use bytes::{Buf, BufMut};
 pub use error::Error;
 pub use prost;
 use prost::{encoding::encoded_len_varint, Message};
+// Sources generated with `zero-copy` feature are kept in separate directories.
 #[rustfmt::skip]
+#[cfg_attr(feature = "zero-copy", path = "tenderdash_nostd_bytes/mod.rs")]
 pub mod tenderdash_nostd;
 #[cfg(not(feature = "grpc"))]
 // Re-export the nostd module only if the std one is not available
@@ -41,6 +48,7 @@ pub use tenderdash_nostd::*;
 
 #[cfg(feature = "grpc")]
 #[rustfmt::skip]
+#[cfg_attr(feature = "zero-copy", path = "tenderdash_grpc_bytes/mod.rs")]
 pub mod tenderdash_grpc;
 #[cfg(feature = "grpc")]
 pub use tenderdash_grpc::*;
@@ -179,3 +179,32 @@ pub fn test_consensus_params_serde() {
     let new_params: ConsensusParams = serde_json::from_str(json).unwrap();
     assert_eq!(new_params.version.unwrap().consensus_version, 1)
 }
+
+#[test]
+#[cfg(feature = "zero-copy")]
+/// Given `zero-copy` feature, when a request is decoded from `Bytes`, then
+/// transactions reference the source buffer instead of copying it.
+pub fn test_zero_copy_txs() {
+    use bytes::Bytes;
+    use tenderdash_proto::{abci::RequestPrepareProposal, prost::Message};
+
+    let request = RequestPrepareProposal {
+        txs: vec![
+            Bytes::from_static(&[1u8; 1024]),
+            Bytes::from(vec![2u8; 1024]),
+        ],
+        ..Default::default()
+    };
+    let buf = Bytes::from(request.encode_to_vec());
+
+    let decoded = RequestPrepareProposal::decode(buf.clone()).unwrap();
+    assert_eq!(decoded, request);
+
+    let buf_range = buf.as_ptr_range();
+    for tx in decoded.txs {
+        assert!(
+            buf_range.contains(&tx.as_ptr()),
+            "tx was copied out of the receive buffer"
+        );
+    }
+}